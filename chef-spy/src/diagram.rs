@@ -0,0 +1,245 @@
+use std::{collections::HashMap, time::Duration};
+
+use common::{msgs::StepPhase, state::State, units::format_duration};
+
+use crate::sample::{Record, Sample};
+
+/// Participants of sequence diagrams, as their diagram id, displayed name and
+/// the kind of service they publish heartbeats as
+const PARTICIPANTS: [(&str, &str, &str); 5] = [
+    ("H", "Head Chef", "head-chef"),
+    ("P", "Prep Chef", "prep-chef"),
+    ("C", "Cook Chef", "cook-chef"),
+    ("I", "Pantry", "pantry"),
+    ("E", "Expediter", "expediter"),
+];
+
+/// Diagram id of the participant publishing heartbeats as the given service
+fn participant(service: &str) -> Option<&'static str> {
+    PARTICIPANTS
+        .iter()
+        .find(|(_, _, kind)| service.starts_with(kind))
+        .map(|(id, _, _)| *id)
+}
+
+/// Renders recorded samples as a Mermaid sequence diagram of the messages
+/// passed between the head chef, the worker chefs, the pantry and the
+/// expediter
+///
+/// Commands, acks, done messages, stock requests and replies, plate orders and
+/// plates become arrows labelled with the time into the recording they were
+/// seen at. Changes in a chef's state, as heard in its heartbeats, changes in
+/// the phase of a command, control requests and changes in how full an
+/// appliance is become notes.
+pub fn sequence_diagram(records: &[Record]) -> String {
+    let mut diagram = "sequenceDiagram\n".to_string();
+    for (id, name, _) in PARTICIPANTS {
+        diagram += &format!("    participant {} as {}\n", id, name);
+    }
+
+    let start = records
+        .first()
+        .map(Record::get_timestamp)
+        .unwrap_or_default();
+    // last state heard from each service and phase reported for each command
+    let mut states: HashMap<String, State> = HashMap::new();
+    let mut phases: HashMap<(u32, u32), StepPhase> = HashMap::new();
+    // worker each command was sent to, to place its phase notes
+    let mut workers: HashMap<(u32, u32), &str> = HashMap::new();
+    // items on and queued for each appliance
+    let mut loads: HashMap<(String, String), (u32, u32)> = HashMap::new();
+    for record in records {
+        let at = format_offset(record.get_timestamp().saturating_sub(start));
+        let line = match record.get_sample() {
+            Sample::PrepareCommand(msg) => {
+                workers.insert((msg.get_run_id(), msg.get_id()), "P");
+                format!(
+                    "H->>P: {} prepare {} (command {})",
+                    at,
+                    msg.get_item(),
+                    msg.get_id()
+                )
+            }
+            Sample::CookCommand(msg) => {
+                workers.insert((msg.get_run_id(), msg.get_id()), "C");
+                format!(
+                    "H->>C: {} cook {} for {} (command {})",
+                    at,
+                    msg.get_item(),
+                    format_duration(msg.get_duration()),
+                    msg.get_id()
+                )
+            }
+            Sample::PrepareCommandAck(msg) => {
+                format!("P-->>H: {} ack {}", at, msg.get_command_id())
+            }
+            Sample::CookCommandAck(msg) => format!("C-->>H: {} ack {}", at, msg.get_command_id()),
+            Sample::PrepareCommandDone(msg) => {
+                format!("P-->>H: {} done {}", at, msg.get_command_id())
+            }
+            Sample::CookCommandDone(msg) => {
+                format!("C-->>H: {} done {}", at, msg.get_command_id())
+            }
+            Sample::StepProgress(msg) => {
+                let command = (msg.get_run_id(), msg.get_command_id());
+                if phases.insert(command, msg.get_phase()) == Some(msg.get_phase()) {
+                    continue;
+                }
+                let worker = workers.get(&command).copied().unwrap_or("H");
+                format!(
+                    "Note over {}: {} {} command {}",
+                    worker,
+                    at,
+                    msg.get_phase(),
+                    msg.get_command_id()
+                )
+            }
+            Sample::Control(msg) => format!("Note over H: {} {} requested", at, msg.get_action()),
+            Sample::StockRequest(msg) => format!("H->>I: {} {} ingredients", at, msg.get_action()),
+            Sample::StockReply(msg) if msg.get_shortfalls().is_empty() => {
+                format!("I-->>H: {} {} ok", at, msg.get_action())
+            }
+            Sample::StockReply(msg) => {
+                let missing: Vec<String> = msg
+                    .get_shortfalls()
+                    .iter()
+                    .map(|shortfall| shortfall.get_item().to_string())
+                    .collect();
+                format!(
+                    "I-->>H: {} {} short of {}",
+                    at,
+                    msg.get_action(),
+                    missing.join(", ")
+                )
+            }
+            Sample::PlateOrder(msg) => format!("H->>E: {} order {}", at, msg.get_recipe()),
+            Sample::Plate(msg) => {
+                let cold = msg
+                    .get_components()
+                    .iter()
+                    .filter(|component| component.is_cold())
+                    .count();
+                format!("E-->>H: {} plate ({} cold)", at, cold)
+            }
+            Sample::ApplianceStatus(msg) => {
+                let load = (msg.get_occupied(), msg.get_queued());
+                let appliance = (msg.get_service().to_string(), msg.get_name().to_string());
+                if loads.insert(appliance, load) == Some(load) {
+                    continue;
+                }
+                format!(
+                    "Note over C: {} {} {}/{} in use, {} queued",
+                    at,
+                    msg.get_name(),
+                    msg.get_occupied(),
+                    msg.get_capacity(),
+                    msg.get_queued()
+                )
+            }
+            Sample::Heartbeat(msg) => {
+                let state = msg.get_state();
+                let Some(id) = participant(msg.get_service()) else {
+                    continue;
+                };
+                if states
+                    .insert(msg.get_service().to_string(), state.clone())
+                    .as_ref()
+                    == Some(state)
+                {
+                    continue;
+                }
+                format!(
+                    "Note over {}: {} {} {}",
+                    id,
+                    at,
+                    msg.get_service(),
+                    state.name()
+                )
+            }
+        };
+        diagram += &format!("    {}\n", line);
+    }
+    diagram
+}
+
+/// Formats a time into the recording, e.g. `+12.5s`
+fn format_offset(offset: Duration) -> String {
+    format!("+{:.1}s", offset.as_secs_f64())
+}
+
+/// Test module for run diagrams
+#[cfg(test)]
+mod diagram_tests {
+    use std::time::Duration;
+
+    use common::{
+        msgs::{
+            CommandDone, Heartbeat, PrepareCommand, PrepareCommandAck, StepPhase, StepProgress,
+        },
+        state::State,
+        steps::FoodItem,
+        trace::TraceSpan,
+    };
+
+    use crate::{
+        diagram::sequence_diagram,
+        sample::{Record, Sample},
+    };
+
+    /// Checks messages become arrows and only changes of state and phase
+    /// become notes
+    #[test]
+    fn run_sequence() {
+        let trace = || TraceSpan::root("prepare").context();
+        let progress = |percent| {
+            Sample::StepProgress(StepProgress::new(
+                7,
+                0,
+                percent,
+                Duration::ZERO,
+                Duration::ZERO,
+                StepPhase::GATHERING,
+            ))
+        };
+        let heartbeat = || {
+            Sample::Heartbeat(Heartbeat::new(
+                "prep-chef-12".to_string(),
+                State::EXECUTING,
+                Vec::new(),
+            ))
+        };
+        let samples = [
+            Sample::PrepareCommand(PrepareCommand::new(
+                7,
+                "4f2a".to_string(),
+                0,
+                FoodItem::CHICKEN,
+                trace(),
+            )),
+            Sample::PrepareCommandAck(PrepareCommandAck::new(7, 0, trace())),
+            heartbeat(),
+            progress(10),
+            heartbeat(),
+            progress(20),
+            Sample::PrepareCommandDone(CommandDone::new(7, 0, trace())),
+        ];
+        let records: Vec<Record> = samples
+            .into_iter()
+            .enumerate()
+            .map(|(i, sample)| Record::new(Duration::from_millis(1000 + 500 * i as u64), sample))
+            .collect();
+
+        let diagram = sequence_diagram(&records);
+        let lines: Vec<&str> = diagram.lines().skip(6).map(str::trim).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "H->>P: +0.0s prepare Chicken (command 0)",
+                "P-->>H: +0.5s ack 0",
+                "Note over P: +1.0s prep-chef-12 EXECUTING",
+                "Note over P: +1.5s gathering command 0",
+                "P-->>H: +3.0s done 0",
+            ]
+        );
+    }
+}
@@ -0,0 +1,193 @@
+mod diagram;
+mod replay;
+mod sample;
+mod spy;
+
+use std::{
+    env,
+    fs::File,
+    io::Write,
+    thread::sleep,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use common::topics::TopicName;
+
+use crate::{
+    diagram::sequence_diagram,
+    replay::Replayer,
+    sample::{Record, Sample},
+    spy::Spy,
+};
+
+const USAGE: &str = "Usage: chef-spy [-t <topic>]... [-r <run-id>] [-o <record-file>]
+       chef-spy --replay <record-file>
+       chef-spy [-t <topic>]... [-r <run-id>] --diagram <record-file>";
+
+/// Delay between polls of the kitchen topics
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Options parsed from the command line
+#[derive(Default)]
+struct SpyArgs {
+    /// Only show samples from these topics, or every topic if empty
+    topics: Vec<TopicName>,
+    /// Only show samples belonging to this run
+    run_id: Option<u32>,
+    /// File to record shown samples to
+    record_file: Option<String>,
+    /// File of recorded samples to publish instead of listening
+    replay_file: Option<String>,
+    /// File of recorded samples to draw a sequence diagram of instead of
+    /// listening
+    diagram_file: Option<String>,
+}
+
+impl SpyArgs {
+    /// Whether a sample passes the topic and run filters
+    fn matches(&self, sample: &Sample) -> bool {
+        let topic_match = self.topics.is_empty() || self.topics.contains(&sample.topic());
+        let run_match = self.run_id.is_none() || sample.run_id() == self.run_id;
+        topic_match && run_match
+    }
+}
+
+/// Helper which parses the spy's options from provided command line args
+fn retrieve_args() -> Result<SpyArgs, String> {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let mut spy_args = SpyArgs::default();
+    let mut args = args.iter();
+    while let Some(a) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("Missing value for {}", a))?;
+        match a.as_str() {
+            "-t" => spy_args.topics.push(value.parse()?),
+            "-r" => {
+                spy_args.run_id = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("Invalid run id: {}", value))?,
+                )
+            }
+            "-o" => spy_args.record_file = Some(value.clone()),
+            "--replay" => spy_args.replay_file = Some(value.clone()),
+            "--diagram" => spy_args.diagram_file = Some(value.clone()),
+            _ => return Err(format!("Unknown argument: {}", a)),
+        }
+    }
+    Ok(spy_args)
+}
+
+/// Time since the UNIX epoch
+fn now() -> Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+/// Formats a time since the UNIX epoch as a UTC time of day
+fn format_timestamp(timestamp: Duration) -> String {
+    let secs = timestamp.as_secs();
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        secs / 3600 % 24,
+        secs / 60 % 60,
+        secs % 60,
+        timestamp.subsec_millis()
+    )
+}
+
+/// Publishes every sample in the given recording, preserving the original
+/// spacing between them
+fn replay(file_name: &str) -> Result<(), String> {
+    let contents = std::fs::read_to_string(file_name).map_err(|e| e.to_string())?;
+    let records = Record::from_recording(&contents)?;
+    let replayer = Replayer::new();
+
+    // give subscribers a chance to discover the replayer
+    sleep(Duration::from_secs(1));
+
+    let mut previous = None;
+    for record in records {
+        if let Some(previous) = previous {
+            sleep(record.get_timestamp().saturating_sub(previous));
+        }
+        previous = Some(record.get_timestamp());
+        println!(
+            "{} replaying {} {:?}",
+            format_timestamp(now()),
+            record.get_sample().topic(),
+            record.get_sample()
+        );
+        replayer.send(record.into_sample())?;
+    }
+    Ok(())
+}
+
+/// Prints a Mermaid sequence diagram of the samples in the given recording
+/// which pass the filters
+fn diagram(file_name: &str, spy_args: &SpyArgs) -> Result<(), String> {
+    let contents = std::fs::read_to_string(file_name).map_err(|e| e.to_string())?;
+    let records: Vec<Record> = Record::from_recording(&contents)?
+        .into_iter()
+        .filter(|record| spy_args.matches(record.get_sample()))
+        .collect();
+    print!("{}", sequence_diagram(&records));
+    Ok(())
+}
+
+/// Prints every sample passing the filters, recording them if requested
+fn listen(spy_args: &SpyArgs) -> Result<(), String> {
+    let mut record_file = match &spy_args.record_file {
+        Some(file_name) => {
+            // avoid clobbering an earlier recording
+            if std::path::Path::new(file_name).exists() {
+                return Err("Another file already exists at: ".to_string() + file_name);
+            }
+            Some(File::create(file_name).map_err(|e| e.to_string())?)
+        }
+        None => None,
+    };
+
+    let mut spy = Spy::new();
+    loop {
+        for sample in spy.poll() {
+            if !spy_args.matches(&sample) {
+                continue;
+            }
+            let record = Record::new(now(), sample);
+            println!(
+                "{} {} {:?}",
+                format_timestamp(record.get_timestamp()),
+                record.get_sample().topic(),
+                record.get_sample()
+            );
+            if let Some(file) = &mut record_file {
+                file.write_all(record.to_document()?.as_bytes())
+                    .map_err(|e| e.to_string())?;
+            }
+        }
+        sleep(POLL_INTERVAL);
+    }
+}
+
+fn main() {
+    let spy_args = match retrieve_args() {
+        Ok(spy_args) => spy_args,
+        Err(e) => {
+            println!("{}\n{}", e, USAGE);
+            return;
+        }
+    };
+
+    let result = match (&spy_args.replay_file, &spy_args.diagram_file) {
+        (Some(file_name), _) => replay(file_name),
+        (None, Some(file_name)) => diagram(file_name, &spy_args),
+        (None, None) => listen(&spy_args),
+    };
+    if let Err(e) = result {
+        println!("Spy has failed: {}", e);
+    }
+}
@@ -0,0 +1,94 @@
+use common::{
+    io::Sender,
+    msgs::{
+        ApplianceStatus, CommandDone, Control, CookCommand, CookCommandAck, Heartbeat, Plate,
+        PlateOrder, PrepareCommand, PrepareCommandAck, StepProgress, StockReply, StockRequest,
+    },
+    topics::TopicName,
+};
+
+use crate::sample::Sample;
+
+/// Publishes recorded samples back onto their original topics
+pub struct Replayer {
+    prep_command_sender: Sender<PrepareCommand>,
+    prep_command_ack_sender: Sender<PrepareCommandAck>,
+    prep_command_done_sender: Sender<CommandDone>,
+    cook_command_sender: Sender<CookCommand>,
+    cook_command_ack_sender: Sender<CookCommandAck>,
+    cook_command_done_sender: Sender<CommandDone>,
+    progress_sender: Sender<StepProgress>,
+    heartbeat_sender: Sender<Heartbeat>,
+    control_sender: Sender<Control>,
+    appliance_status_sender: Sender<ApplianceStatus>,
+    stock_request_sender: Sender<StockRequest>,
+    stock_reply_sender: Sender<StockReply>,
+    plate_order_sender: Sender<PlateOrder>,
+    plate_sender: Sender<Plate>,
+}
+
+impl Replayer {
+    /// Creates a new replayer able to publish on all kitchen topics
+    pub fn new() -> Self {
+        Self {
+            prep_command_sender: Sender::new(TopicName::PrepareCommand.to_string(), None),
+            prep_command_ack_sender: Sender::new(TopicName::PrepareCommandAck.to_string(), None),
+            prep_command_done_sender: Sender::new(TopicName::PrepareCommandDone.to_string(), None),
+            cook_command_sender: Sender::new(TopicName::CookCommand.to_string(), None),
+            cook_command_ack_sender: Sender::new(TopicName::CookCommandAck.to_string(), None),
+            cook_command_done_sender: Sender::new(TopicName::CookCommandDone.to_string(), None),
+            progress_sender: Sender::new(TopicName::StepProgress.to_string(), None),
+            heartbeat_sender: Sender::new(TopicName::Heartbeat.to_string(), None),
+            control_sender: Sender::new(TopicName::Control.to_string(), None),
+            appliance_status_sender: Sender::new(TopicName::ApplianceStatus.to_string(), None),
+            stock_request_sender: Sender::new(TopicName::StockRequest.to_string(), None),
+            stock_reply_sender: Sender::new(TopicName::StockReply.to_string(), None),
+            plate_order_sender: Sender::new(TopicName::PlateOrder.to_string(), None),
+            plate_sender: Sender::new(TopicName::Plate.to_string(), None),
+        }
+    }
+
+    /// Publishes the given sample on the topic it was recorded from
+    pub fn send(&self, sample: Sample) -> Result<(), String> {
+        match sample {
+            Sample::PrepareCommand(msg) => self
+                .prep_command_sender
+                .send(msg)
+                .map_err(|e| e.to_string()),
+            Sample::PrepareCommandAck(msg) => self
+                .prep_command_ack_sender
+                .send(msg)
+                .map_err(|e| e.to_string()),
+            Sample::PrepareCommandDone(msg) => self
+                .prep_command_done_sender
+                .send(msg)
+                .map_err(|e| e.to_string()),
+            Sample::CookCommand(msg) => self
+                .cook_command_sender
+                .send(msg)
+                .map_err(|e| e.to_string()),
+            Sample::CookCommandAck(msg) => self
+                .cook_command_ack_sender
+                .send(msg)
+                .map_err(|e| e.to_string()),
+            Sample::CookCommandDone(msg) => self
+                .cook_command_done_sender
+                .send(msg)
+                .map_err(|e| e.to_string()),
+            Sample::StepProgress(msg) => self.progress_sender.send(msg).map_err(|e| e.to_string()),
+            Sample::Heartbeat(msg) => self.heartbeat_sender.send(msg).map_err(|e| e.to_string()),
+            Sample::Control(msg) => self.control_sender.send(msg).map_err(|e| e.to_string()),
+            Sample::ApplianceStatus(msg) => self
+                .appliance_status_sender
+                .send(msg)
+                .map_err(|e| e.to_string()),
+            Sample::StockRequest(msg) => self
+                .stock_request_sender
+                .send(msg)
+                .map_err(|e| e.to_string()),
+            Sample::StockReply(msg) => self.stock_reply_sender.send(msg).map_err(|e| e.to_string()),
+            Sample::PlateOrder(msg) => self.plate_order_sender.send(msg).map_err(|e| e.to_string()),
+            Sample::Plate(msg) => self.plate_sender.send(msg).map_err(|e| e.to_string()),
+        }
+    }
+}
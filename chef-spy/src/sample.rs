@@ -0,0 +1,177 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use common::{
+    msgs::{
+        ApplianceStatus, CommandDone, Control, CookCommand, CookCommandAck, Heartbeat, Plate,
+        PlateOrder, PrepareCommand, PrepareCommandAck, StepProgress, StockReply, StockRequest,
+    },
+    topics::TopicName,
+};
+
+/// A single message observed on one of the kitchen topics
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Sample {
+    PrepareCommand(PrepareCommand),
+    PrepareCommandAck(PrepareCommandAck),
+    PrepareCommandDone(CommandDone),
+    CookCommand(CookCommand),
+    CookCommandAck(CookCommandAck),
+    CookCommandDone(CommandDone),
+    StepProgress(StepProgress),
+    Heartbeat(Heartbeat),
+    Control(Control),
+    ApplianceStatus(ApplianceStatus),
+    StockRequest(StockRequest),
+    StockReply(StockReply),
+    PlateOrder(PlateOrder),
+    Plate(Plate),
+}
+
+impl Sample {
+    /// Topic the sample was published on
+    pub fn topic(&self) -> TopicName {
+        match self {
+            Sample::PrepareCommand(_) => TopicName::PrepareCommand,
+            Sample::PrepareCommandAck(_) => TopicName::PrepareCommandAck,
+            Sample::PrepareCommandDone(_) => TopicName::PrepareCommandDone,
+            Sample::CookCommand(_) => TopicName::CookCommand,
+            Sample::CookCommandAck(_) => TopicName::CookCommandAck,
+            Sample::CookCommandDone(_) => TopicName::CookCommandDone,
+            Sample::StepProgress(_) => TopicName::StepProgress,
+            Sample::Heartbeat(_) => TopicName::Heartbeat,
+            Sample::Control(_) => TopicName::Control,
+            Sample::ApplianceStatus(_) => TopicName::ApplianceStatus,
+            Sample::StockRequest(_) => TopicName::StockRequest,
+            Sample::StockReply(_) => TopicName::StockReply,
+            Sample::PlateOrder(_) => TopicName::PlateOrder,
+            Sample::Plate(_) => TopicName::Plate,
+        }
+    }
+
+    /// Run the sample belongs to, if its message carries one
+    pub fn run_id(&self) -> Option<u32> {
+        match self {
+            Sample::PrepareCommand(msg) => Some(msg.get_run_id()),
+            Sample::PrepareCommandAck(msg) => Some(msg.get_run_id()),
+            Sample::PrepareCommandDone(msg) | Sample::CookCommandDone(msg) => {
+                Some(msg.get_run_id())
+            }
+            Sample::CookCommand(msg) => Some(msg.get_run_id()),
+            Sample::CookCommandAck(msg) => Some(msg.get_run_id()),
+            Sample::StepProgress(msg) => Some(msg.get_run_id()),
+            Sample::Heartbeat(_) | Sample::ApplianceStatus(_) => None,
+            Sample::Control(msg) => msg.get_run_id(),
+            Sample::StockRequest(msg) => Some(msg.get_run_id()),
+            Sample::StockReply(msg) => Some(msg.get_run_id()),
+            Sample::PlateOrder(msg) => Some(msg.get_run_id()),
+            Sample::Plate(msg) => Some(msg.get_run_id()),
+        }
+    }
+}
+
+/// A sample along with the time it was observed, as stored in recordings
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Record {
+    /// Time since the UNIX epoch at which the sample was received
+    timestamp: Duration,
+    sample: Sample,
+}
+
+impl Record {
+    pub fn new(timestamp: Duration, sample: Sample) -> Self {
+        Self { timestamp, sample }
+    }
+    pub fn get_timestamp(&self) -> Duration {
+        self.timestamp
+    }
+    pub fn get_sample(&self) -> &Sample {
+        &self.sample
+    }
+    pub fn into_sample(self) -> Sample {
+        self.sample
+    }
+
+    /// Serializes the record as a single YAML document, to be appended to a
+    /// recording
+    pub fn to_document(&self) -> Result<String, String> {
+        serde_yaml::to_string(self)
+            .map(|yaml| format!("---\n{}", yaml))
+            .map_err(|e| e.to_string())
+    }
+
+    /// Parses every record out of the contents of a recording
+    pub fn from_recording(contents: &str) -> Result<Vec<Self>, String> {
+        serde_yaml::Deserializer::from_str(contents)
+            .map(|document| Self::deserialize(document).map_err(|e| e.to_string()))
+            .collect()
+    }
+}
+
+/// Test module for spy samples and recordings
+#[cfg(test)]
+mod sample_tests {
+    use std::time::Duration;
+
+    use common::{
+        msgs::{CommandDone, CookCommand, Heartbeat},
+        state::{State, StateMachine},
+        steps::FoodItem,
+        topics::TopicName,
+        trace::TraceSpan,
+    };
+
+    use crate::sample::{Record, Sample};
+
+    /// Checks records survive being written to and read from a recording
+    #[test]
+    fn recording_round_trip() {
+        let mut machine = StateMachine::new();
+        machine.fail("burnt".to_string());
+        let records = [
+            Record::new(
+                Duration::from_millis(1500),
+                Sample::CookCommand(CookCommand::new(
+                    42,
+                    "4f2a".to_string(),
+                    1,
+                    FoodItem::SALMON,
+                    Duration::from_secs(10),
+                    None,
+                    TraceSpan::root("cook").context(),
+                )),
+            ),
+            Record::new(
+                Duration::from_millis(2500),
+                Sample::CookCommandDone(CommandDone::new(42, 1, TraceSpan::root("cook").context())),
+            ),
+            Record::new(
+                Duration::from_millis(3000),
+                Sample::Heartbeat(Heartbeat::new(
+                    "cook-chef-1".to_string(),
+                    State::FAILED("burnt".to_string()),
+                    machine.get_history().to_vec(),
+                )),
+            ),
+        ];
+        let recording: String = records
+            .iter()
+            .map(|record| record.to_document().unwrap())
+            .collect();
+
+        let parsed = Record::from_recording(&recording).unwrap();
+        assert_eq!(parsed.len(), 3);
+        assert_eq!(parsed[0].get_timestamp(), Duration::from_millis(1500));
+        assert_eq!(parsed[0].get_sample().topic(), TopicName::CookCommand);
+        assert_eq!(parsed[1].get_sample().topic(), TopicName::CookCommandDone);
+        assert_eq!(parsed[1].get_sample().run_id(), Some(42));
+        assert_eq!(parsed[2].get_sample().run_id(), None);
+        match parsed[2].get_sample() {
+            Sample::Heartbeat(heartbeat) => {
+                assert_eq!(heartbeat.get_history(), machine.get_history())
+            }
+            sample => panic!("Expected a heartbeat, got {:?}", sample.topic()),
+        }
+    }
+}
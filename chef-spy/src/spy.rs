@@ -0,0 +1,104 @@
+use common::{
+    io::Receiver,
+    msgs::{
+        ApplianceStatus, CommandDone, Control, CookCommand, CookCommandAck, Heartbeat, Plate,
+        PlateOrder, PrepareCommand, PrepareCommandAck, StepProgress, StockReply, StockRequest,
+    },
+    topics::TopicName,
+};
+
+use crate::sample::Sample;
+
+/// Passive DDS participant subscribed to every kitchen topic
+pub struct Spy {
+    prep_command_receiver: Receiver<PrepareCommand>,
+    prep_command_ack_receiver: Receiver<PrepareCommandAck>,
+    prep_command_done_receiver: Receiver<CommandDone>,
+    cook_command_receiver: Receiver<CookCommand>,
+    cook_command_ack_receiver: Receiver<CookCommandAck>,
+    cook_command_done_receiver: Receiver<CommandDone>,
+    progress_receiver: Receiver<StepProgress>,
+    heartbeat_receiver: Receiver<Heartbeat>,
+    control_receiver: Receiver<Control>,
+    appliance_status_receiver: Receiver<ApplianceStatus>,
+    stock_request_receiver: Receiver<StockRequest>,
+    stock_reply_receiver: Receiver<StockReply>,
+    plate_order_receiver: Receiver<PlateOrder>,
+    plate_receiver: Receiver<Plate>,
+}
+
+impl Spy {
+    /// Creates a new spy subscribed to all kitchen topics
+    pub fn new() -> Self {
+        Self {
+            prep_command_receiver: Receiver::new(TopicName::PrepareCommand.to_string(), None),
+            prep_command_ack_receiver: Receiver::new(
+                TopicName::PrepareCommandAck.to_string(),
+                None,
+            ),
+            prep_command_done_receiver: Receiver::new(
+                TopicName::PrepareCommandDone.to_string(),
+                None,
+            ),
+            cook_command_receiver: Receiver::new(TopicName::CookCommand.to_string(), None),
+            cook_command_ack_receiver: Receiver::new(TopicName::CookCommandAck.to_string(), None),
+            cook_command_done_receiver: Receiver::new(TopicName::CookCommandDone.to_string(), None),
+            progress_receiver: Receiver::new(TopicName::StepProgress.to_string(), None),
+            heartbeat_receiver: Receiver::new(TopicName::Heartbeat.to_string(), None),
+            control_receiver: Receiver::new(TopicName::Control.to_string(), None),
+            appliance_status_receiver: Receiver::new(TopicName::ApplianceStatus.to_string(), None),
+            stock_request_receiver: Receiver::new(TopicName::StockRequest.to_string(), None),
+            stock_reply_receiver: Receiver::new(TopicName::StockReply.to_string(), None),
+            plate_order_receiver: Receiver::new(TopicName::PlateOrder.to_string(), None),
+            plate_receiver: Receiver::new(TopicName::Plate.to_string(), None),
+        }
+    }
+
+    /// Drains every topic, returning all samples received since the last poll
+    pub fn poll(&mut self) -> Vec<Sample> {
+        let mut samples = Vec::new();
+        while let Some(msg) = self.prep_command_receiver.receive() {
+            samples.push(Sample::PrepareCommand(msg));
+        }
+        while let Some(msg) = self.prep_command_ack_receiver.receive() {
+            samples.push(Sample::PrepareCommandAck(msg));
+        }
+        while let Some(msg) = self.prep_command_done_receiver.receive() {
+            samples.push(Sample::PrepareCommandDone(msg));
+        }
+        while let Some(msg) = self.cook_command_receiver.receive() {
+            samples.push(Sample::CookCommand(msg));
+        }
+        while let Some(msg) = self.cook_command_ack_receiver.receive() {
+            samples.push(Sample::CookCommandAck(msg));
+        }
+        while let Some(msg) = self.cook_command_done_receiver.receive() {
+            samples.push(Sample::CookCommandDone(msg));
+        }
+        while let Some(msg) = self.progress_receiver.receive() {
+            samples.push(Sample::StepProgress(msg));
+        }
+        while let Some(msg) = self.heartbeat_receiver.receive() {
+            samples.push(Sample::Heartbeat(msg));
+        }
+        while let Some(msg) = self.control_receiver.receive() {
+            samples.push(Sample::Control(msg));
+        }
+        while let Some(msg) = self.appliance_status_receiver.receive() {
+            samples.push(Sample::ApplianceStatus(msg));
+        }
+        while let Some(msg) = self.stock_request_receiver.receive() {
+            samples.push(Sample::StockRequest(msg));
+        }
+        while let Some(msg) = self.stock_reply_receiver.receive() {
+            samples.push(Sample::StockReply(msg));
+        }
+        while let Some(msg) = self.plate_order_receiver.receive() {
+            samples.push(Sample::PlateOrder(msg));
+        }
+        while let Some(msg) = self.plate_receiver.receive() {
+            samples.push(Sample::Plate(msg));
+        }
+        samples
+    }
+}
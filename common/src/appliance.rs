@@ -0,0 +1,295 @@
+use std::{fmt::Display, path::Path, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{steps::FoodItem, units::Temperature};
+
+/// Temperature in degrees Celsius appliances start at, and cool back down to
+pub const AMBIENT_CELSIUS: f64 = 20.0;
+
+/// Temperature in degrees Celsius appliances are heated to for cook steps
+/// which give none
+pub const DEFAULT_CELSIUS: f64 = 200.0;
+
+/// Degrees an appliance may be below its target while still counting as hot
+const HOT_TOLERANCE: f64 = 1.0;
+
+/// Kinds of appliance food is cooked on
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ApplianceKind {
+    OVEN,
+    PAN,
+}
+
+impl ApplianceKind {
+    /// Lowercase name of the kind of appliance
+    pub fn name(&self) -> &'static str {
+        match self {
+            ApplianceKind::OVEN => "oven",
+            ApplianceKind::PAN => "pan",
+        }
+    }
+
+    /// Kind of appliance the given item is cooked on
+    pub fn for_item(item: FoodItem) -> Self {
+        match item {
+            FoodItem::CHICKEN => ApplianceKind::PAN,
+            FoodItem::SALMON => ApplianceKind::OVEN,
+        }
+    }
+}
+
+impl Display for ApplianceKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Degrees Celsius a cook step should be carried out at
+pub fn target_celsius(temperature: Option<Temperature>) -> f64 {
+    temperature.map_or(DEFAULT_CELSIUS, |temperature| temperature.celsius())
+}
+
+/// Returns the ambient temperature, as appliances start out
+fn ambient() -> f64 {
+    AMBIENT_CELSIUS
+}
+
+/// An oven, pan or similar which items are cooked on
+///
+/// Appliances hold up to `capacity` items at once, all cooked at the same
+/// temperature. They heat towards that temperature while in use and slowly
+/// cool back down once empty, so a following cook at the same temperature need
+/// not preheat again.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Appliance {
+    name: String,
+    kind: ApplianceKind,
+    /// Degrees Celsius gained per second while heating
+    heat_rate: f64,
+    /// Degrees Celsius lost per second while empty
+    cool_rate: f64,
+    capacity: u32,
+    #[serde(skip, default = "ambient")]
+    current: f64,
+    #[serde(skip)]
+    target: Option<f64>,
+    #[serde(skip)]
+    occupied: u32,
+}
+
+impl Appliance {
+    /// Creates a new appliance, starting out cold and empty
+    pub fn new(
+        name: String,
+        kind: ApplianceKind,
+        heat_rate: f64,
+        cool_rate: f64,
+        capacity: u32,
+    ) -> Self {
+        Self {
+            name,
+            kind,
+            heat_rate,
+            cool_rate,
+            capacity,
+            current: AMBIENT_CELSIUS,
+            target: None,
+            occupied: 0,
+        }
+    }
+
+    /// Creates a typical appliance of the given kind: a pan for one item which
+    /// heats up in seconds, or an oven for two which heats up more slowly
+    pub fn default_for(kind: ApplianceKind) -> Self {
+        match kind {
+            ApplianceKind::PAN => Self::new(kind.name().to_string(), kind, 36.0, 2.0, 1),
+            ApplianceKind::OVEN => Self::new(kind.name().to_string(), kind, 12.0, 0.5, 2),
+        }
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+    pub fn get_kind(&self) -> ApplianceKind {
+        self.kind
+    }
+    pub fn get_capacity(&self) -> u32 {
+        self.capacity
+    }
+    pub fn get_occupied(&self) -> u32 {
+        self.occupied
+    }
+    /// Current temperature in degrees Celsius
+    pub fn get_current(&self) -> f64 {
+        self.current
+    }
+    /// Temperature in degrees Celsius being heated to, if in use
+    pub fn get_target(&self) -> Option<f64> {
+        self.target
+    }
+
+    /// Whether the appliance is at least as hot as the given temperature
+    pub fn is_hot(&self, celsius: f64) -> bool {
+        self.current >= celsius - HOT_TOLERANCE
+    }
+
+    /// Time the appliance would take to heat from its current temperature to
+    /// the given one, failing if it can't get there
+    pub fn heating_time(&self, celsius: f64) -> Result<Duration, String> {
+        if self.is_hot(celsius) {
+            return Ok(Duration::ZERO);
+        }
+        Duration::try_from_secs_f64((celsius - self.current) / self.heat_rate)
+            .map_err(|_| format!("The {} can't be heated to {}°C", self.name, celsius))
+    }
+
+    /// Checks the appliance holds at least one item, and heats and cools at
+    /// finite, positive rates
+    pub fn check(&self) -> Result<(), String> {
+        if self.capacity == 0 {
+            return Err(format!(
+                "The {} needs room for at least one item",
+                self.name
+            ));
+        }
+        for (name, rate) in [("heat_rate", self.heat_rate), ("cool_rate", self.cool_rate)] {
+            if !rate.is_finite() || rate <= 0.0 {
+                return Err(format!(
+                    "The {} needs a positive {}, not {}",
+                    self.name, name, rate
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether an item could be put on the appliance to cook at the given
+    /// temperature, needing a free slot and any items already on it to cook
+    /// at the same temperature
+    pub fn can_take(&self, celsius: f64) -> bool {
+        self.occupied < self.capacity && self.target.is_none_or(|target| target == celsius)
+    }
+
+    /// Puts an item on the appliance, heating it to the given temperature
+    pub fn occupy(&mut self, celsius: f64) {
+        self.occupied += 1;
+        self.target = Some(celsius);
+    }
+
+    /// Takes an item off the appliance, letting it cool once empty
+    pub fn release(&mut self) {
+        self.occupied = self.occupied.saturating_sub(1);
+        if self.occupied == 0 {
+            self.target = None;
+        }
+    }
+
+    /// Heats the appliance towards its target, or cools it towards the
+    /// ambient temperature if empty, over the given time
+    pub fn update(&mut self, elapsed: Duration) {
+        let secs = elapsed.as_secs_f64();
+        match self.target {
+            Some(target) if self.current < target => {
+                self.current = (self.current + self.heat_rate * secs).min(target);
+            }
+            Some(_) => (),
+            None => self.current = (self.current - self.cool_rate * secs).max(AMBIENT_CELSIUS),
+        }
+    }
+}
+
+/// Appliances a cook chef cooks on unless given others, starting out cold
+pub fn default_appliances() -> Vec<Appliance> {
+    vec![
+        Appliance::default_for(ApplianceKind::OVEN),
+        Appliance::default_for(ApplianceKind::PAN),
+    ]
+}
+
+/// Reads the list of appliances to cook on from the YAML file at the given path
+pub fn load_appliances(path: &Path) -> Result<Vec<Appliance>, String> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+    let appliances: Vec<Appliance> =
+        serde_yaml::from_str(&contents).map_err(|e| format!("{}: {}", path.display(), e))?;
+    for appliance in &appliances {
+        appliance
+            .check()
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+    Ok(appliances)
+}
+
+/// Test module for appliances
+#[cfg(test)]
+mod appliance_tests {
+    use std::time::Duration;
+
+    use crate::appliance::{Appliance, ApplianceKind, AMBIENT_CELSIUS};
+
+    /// Checks a hot appliance needs no preheating, and cools once empty
+    #[test]
+    fn preheat_once() {
+        let mut oven = Appliance::default_for(ApplianceKind::OVEN);
+        assert_eq!(oven.heating_time(200.0), Ok(Duration::from_secs(15)));
+
+        oven.occupy(200.0);
+        oven.update(Duration::from_secs(20));
+        assert_eq!(oven.get_current(), 200.0);
+        assert_eq!(oven.heating_time(200.0), Ok(Duration::ZERO));
+        oven.release();
+
+        // a short while later the oven is still hot enough
+        oven.update(Duration::from_secs(1));
+        assert!(oven.can_take(180.0));
+        assert_eq!(oven.heating_time(180.0), Ok(Duration::ZERO));
+        oven.update(Duration::from_secs(3600));
+        assert_eq!(oven.get_current(), AMBIENT_CELSIUS);
+    }
+
+    /// Checks items only share an appliance with free slots at the same
+    /// temperature
+    #[test]
+    fn capacity() {
+        let mut oven = Appliance::default_for(ApplianceKind::OVEN);
+        oven.occupy(200.0);
+        assert!(!oven.can_take(220.0));
+        assert!(oven.can_take(200.0));
+        oven.occupy(200.0);
+        assert!(!oven.can_take(200.0));
+        oven.release();
+        assert!(oven.can_take(200.0));
+    }
+
+    /// Checks appliances listed in YAML start out cold and empty
+    #[test]
+    fn from_yaml() {
+        let appliances: Vec<Appliance> = serde_yaml::from_str(
+            "- name: big oven\n  kind: oven\n  heat_rate: 15.0\n  cool_rate: 0.5\n  capacity: 4\n",
+        )
+        .unwrap();
+        assert_eq!(appliances[0].get_kind(), ApplianceKind::OVEN);
+        assert_eq!(appliances[0].get_capacity(), 4);
+        assert_eq!(appliances[0].get_current(), AMBIENT_CELSIUS);
+        assert_eq!(appliances[0].get_occupied(), 0);
+    }
+
+    /// Checks appliances which can't heat or hold anything, or can't be heated
+    /// to a temperature, give errors rather than panicking
+    #[test]
+    fn unreachable_temperatures() {
+        let oven = Appliance::default_for(ApplianceKind::OVEN);
+        assert!(oven.heating_time(f64::NAN).is_err());
+        assert!(oven.heating_time(f64::INFINITY).is_err());
+        assert!(oven.heating_time(1e300).is_err());
+
+        let cold = Appliance::new("cold".to_string(), ApplianceKind::OVEN, 0.0, 0.5, 1);
+        assert!(cold.check().is_err());
+        assert!(cold.heating_time(200.0).is_err());
+        let full = Appliance::new("full".to_string(), ApplianceKind::PAN, 36.0, 2.0, 0);
+        assert!(full.check().is_err());
+        assert!(Appliance::default_for(ApplianceKind::PAN).check().is_ok());
+    }
+}
@@ -0,0 +1,56 @@
+use std::{
+    cell::Cell,
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+thread_local! {
+    /// When the virtual clock on this thread started, and the time on it
+    /// since, while a `Simulation` runs services on it
+    static VIRTUAL: Cell<Option<(Instant, Duration)>> = const { Cell::new(None) };
+}
+
+/// Current time, as read by services so a `Simulation` can stand in a
+/// virtual clock for the real one
+pub fn now() -> Instant {
+    match VIRTUAL.get() {
+        Some((start, elapsed)) => start + elapsed,
+        None => Instant::now(),
+    }
+}
+
+/// Time passed since the given instant
+pub fn since(earlier: Instant) -> Duration {
+    now().saturating_duration_since(earlier)
+}
+
+/// Current wall clock time, counted from the Unix epoch on a virtual clock
+pub fn system_now() -> SystemTime {
+    match VIRTUAL.get() {
+        Some((_, elapsed)) => UNIX_EPOCH + elapsed,
+        None => SystemTime::now(),
+    }
+}
+
+/// Blocks for the given duration, or moves a virtual clock on by it
+pub fn sleep(duration: Duration) {
+    match VIRTUAL.get() {
+        Some((start, elapsed)) => VIRTUAL.set(Some((start, elapsed + duration))),
+        None => thread::sleep(duration),
+    }
+}
+
+/// Sets this thread's virtual clock, started at `start`, to the given time
+pub(crate) fn set_virtual(start: Instant, elapsed: Duration) {
+    VIRTUAL.set(Some((start, elapsed)));
+}
+
+/// Time on this thread's virtual clock, if one is set
+pub(crate) fn virtual_elapsed() -> Option<Duration> {
+    VIRTUAL.get().map(|(_, elapsed)| elapsed)
+}
+
+/// Returns this thread to the real clock
+pub(crate) fn clear_virtual() {
+    VIRTUAL.set(None);
+}
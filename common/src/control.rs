@@ -0,0 +1,40 @@
+use crate::{
+    io::Receiver,
+    msgs::{Control, ControlAction},
+    topics::TopicName,
+};
+
+/// Listens for pause, resume and cancel requests on the control topic
+pub struct ControlListener {
+    receiver: Receiver<Control>,
+}
+
+impl Default for ControlListener {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ControlListener {
+    pub fn new() -> Self {
+        Self {
+            receiver: Receiver::new(TopicName::Control.to_string(), None),
+        }
+    }
+
+    /// Drains the control topic, returning the latest action requested for
+    /// the given run, if any
+    pub fn latest(&mut self, run_id: u32) -> Option<ControlAction> {
+        self.receive_all()
+            .iter()
+            .rev()
+            .find(|control| control.applies_to(run_id))
+            .map(Control::get_action)
+    }
+
+    /// Drains the control topic, returning every request for any run, oldest
+    /// first
+    pub fn receive_all(&mut self) -> Vec<Control> {
+        std::iter::from_fn(|| self.receiver.receive()).collect()
+    }
+}
@@ -0,0 +1,204 @@
+use std::time::Duration;
+
+use crate::{appliance::default_appliances, steps::Step, timings::step_time};
+
+/// Indices of the earlier steps each step depends on
+///
+/// A step depends on the last earlier step acting on the same item, as an item
+/// must be prepared before it is cooked and its batches are cooked in order.
+/// Steps acting on different items are independent.
+pub fn dependencies(steps: &[Step]) -> Vec<Vec<usize>> {
+    steps
+        .iter()
+        .enumerate()
+        .map(|(i, step)| {
+            steps[..i]
+                .iter()
+                .rposition(|earlier| earlier.item() == step.item())
+                .into_iter()
+                .collect()
+        })
+        .collect()
+}
+
+/// Estimated timing of a single step of a recipe
+///
+/// #### Fields
+/// - `duration` a worker chef takes to carry out the step, including the
+///   chef's own gathering, preparing or preheating time. Preheating assumes
+///   a single cook chef carrying out the steps in order, so an appliance is
+///   still hot from the cook before, less what it has cooled since.
+/// - `earliest_start` the step can begin at, relative to the start of the
+///   recipe, once the steps it depends on are done
+/// - `dependencies` indices of the steps which must be done first
+#[derive(Debug, Clone, PartialEq)]
+pub struct StepEstimate {
+    duration: Duration,
+    earliest_start: Duration,
+    dependencies: Vec<usize>,
+}
+
+impl StepEstimate {
+    pub fn get_duration(&self) -> Duration {
+        self.duration
+    }
+    pub fn get_earliest_start(&self) -> Duration {
+        self.earliest_start
+    }
+    pub fn get_dependencies(&self) -> &Vec<usize> {
+        &self.dependencies
+    }
+
+    /// Earliest time the step can be done by
+    pub fn finish(&self) -> Duration {
+        self.earliest_start + self.duration
+    }
+}
+
+/// Estimated timing of a recipe
+///
+/// #### Fields
+/// - `steps` the earliest start schedule, one estimate per recipe step
+/// - `total` time the recipe takes when independent steps run at once
+/// - `sequential` time the recipe takes when steps run one after another, as
+///   the driver dispatches them
+/// - `critical_path` indices of the chain of dependent steps which sets the
+///   total time, in order
+#[derive(Debug, Clone, PartialEq)]
+pub struct Estimate {
+    steps: Vec<StepEstimate>,
+    total: Duration,
+    sequential: Duration,
+    critical_path: Vec<usize>,
+}
+
+impl Estimate {
+    /// Schedules the given steps as early as their dependencies allow,
+    /// failing if any can't be carried out or the recipe would take longer
+    /// than can be counted
+    pub fn new(steps: &[Step]) -> Result<Self, String> {
+        let too_long = || "The recipe would take too long to estimate".to_string();
+        let mut schedule: Vec<StepEstimate> = Vec::new();
+        let mut appliances = default_appliances();
+        for (step, dependencies) in steps.iter().zip(dependencies(steps)) {
+            let earliest_start = dependencies
+                .iter()
+                .map(|&i| schedule[i].finish())
+                .max()
+                .unwrap_or_default();
+            let duration = step_time(step, &mut appliances)?;
+            earliest_start.checked_add(duration).ok_or_else(too_long)?;
+            schedule.push(StepEstimate {
+                duration,
+                earliest_start,
+                dependencies,
+            });
+        }
+        let sequential = schedule
+            .iter()
+            .try_fold(Duration::ZERO, |total, step| {
+                total.checked_add(step.duration)
+            })
+            .ok_or_else(too_long)?;
+
+        // walk back from the last step to finish through whichever
+        // dependency held up each step
+        let mut critical_path = Vec::new();
+        let mut current = (0..schedule.len()).max_by_key(|&i| schedule[i].finish());
+        while let Some(i) = current {
+            critical_path.push(i);
+            current = schedule[i]
+                .dependencies
+                .iter()
+                .copied()
+                .max_by_key(|&dependency| schedule[dependency].finish());
+        }
+        critical_path.reverse();
+
+        Ok(Self {
+            total: schedule
+                .iter()
+                .map(StepEstimate::finish)
+                .max()
+                .unwrap_or_default(),
+            sequential,
+            steps: schedule,
+            critical_path,
+        })
+    }
+
+    pub fn get_steps(&self) -> &Vec<StepEstimate> {
+        &self.steps
+    }
+    pub fn get_total(&self) -> Duration {
+        self.total
+    }
+    pub fn get_sequential(&self) -> Duration {
+        self.sequential
+    }
+    pub fn get_critical_path(&self) -> &Vec<usize> {
+        &self.critical_path
+    }
+}
+
+/// Test module for recipe time estimates
+#[cfg(test)]
+mod estimate_tests {
+    use std::time::Duration;
+
+    use crate::{
+        estimate::Estimate,
+        steps::{FoodItem, Step},
+    };
+
+    /// Checks independent items are scheduled side by side and the slower
+    /// item's steps form the critical path
+    #[test]
+    fn schedule_two_items() {
+        let estimate = Estimate::new(&[
+            // 2s gathering + 5s preparing, then 5s preheating + 10s cooking
+            Step::Prepare(FoodItem::CHICKEN),
+            // 3s gathering + 3s preparing, then 15s preheating + 10s cooking
+            Step::Prepare(FoodItem::SALMON),
+            Step::Cook(FoodItem::CHICKEN, Duration::from_secs(10), None),
+            Step::Cook(FoodItem::SALMON, Duration::from_secs(10), None),
+        ])
+        .unwrap();
+        let starts: Vec<u64> = estimate
+            .get_steps()
+            .iter()
+            .map(|step| step.get_earliest_start().as_secs())
+            .collect();
+        assert_eq!(starts, vec![0, 0, 7, 6]);
+        assert_eq!(estimate.get_steps()[3].get_dependencies(), &vec![1]);
+        assert_eq!(estimate.get_total(), Duration::from_secs(31));
+        assert_eq!(estimate.get_sequential(), Duration::from_secs(53));
+        assert_eq!(estimate.get_critical_path(), &vec![1, 3]);
+
+        let empty = Estimate::new(&[]).unwrap();
+        assert_eq!(empty.get_total(), Duration::ZERO);
+        assert!(empty.get_critical_path().is_empty());
+    }
+
+    /// Checks a cook straight after another on the same appliance is not
+    /// charged for preheating again, while one after a gap is charged for
+    /// the heat lost meanwhile
+    #[test]
+    fn appliance_stays_hot() {
+        let estimate = Estimate::new(&[
+            // 15s preheating the oven, then cooking
+            Step::Cook(FoodItem::SALMON, Duration::from_secs(10), None),
+            Step::Cook(FoodItem::SALMON, Duration::from_secs(10), None),
+            // 5s preheating the pan then cooking, while the oven cools by 30°C
+            Step::Cook(FoodItem::CHICKEN, Duration::from_secs(55), None),
+            Step::Cook(FoodItem::SALMON, Duration::from_secs(10), None),
+        ])
+        .unwrap();
+        let durations: Vec<u128> = estimate
+            .get_steps()
+            .iter()
+            .map(|step| step.get_duration().as_millis())
+            .collect();
+        assert_eq!(durations, vec![25_000, 10_000, 60_000, 12_500]);
+    }
+}
@@ -0,0 +1,451 @@
+use std::{
+    env,
+    path::Path,
+    str::FromStr,
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Deserializer};
+use tracing::warn;
+
+use crate::{
+    state::State,
+    topics::TopicName,
+    units::{format_duration, parse_duration},
+};
+
+/// Environment variable naming a scenario file of faults to inject into a
+/// service, for testing how failures are handled
+pub const FAULTS_VAR: &str = "CHEF_FAULTS";
+
+/// Exit code of a service crashed by an injected fault
+pub const CRASH_EXIT_CODE: i32 = 86;
+
+/// Extra time a reordered message is held back for, letting the messages
+/// after it overtake it
+const REORDER_HOLD: Duration = Duration::from_secs(1);
+
+/// Faults injected into this process, configured once by `init`
+static ACTIVE: OnceLock<(&'static str, Scenario)> = OnceLock::new();
+
+/// Reads a duration written as e.g. `2s` or `500ms`
+fn friendly_duration<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    parse_duration(&text).map_err(serde::de::Error::custom)
+}
+
+fn one() -> f64 {
+    1.0
+}
+
+fn first() -> usize {
+    1
+}
+
+/// Faults injected into the messages received on a topic
+///
+/// Each message is dropped, held back behind later messages or duplicated
+/// with the given probabilities, after first being delayed by `delay`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TopicFaults {
+    topic: String,
+    /// Kind of service receiving the messages to fault, or any if unset
+    #[serde(default)]
+    service: Option<String>,
+    #[serde(default)]
+    drop: f64,
+    #[serde(default, deserialize_with = "friendly_duration")]
+    delay: Duration,
+    #[serde(default)]
+    duplicate: f64,
+    #[serde(default)]
+    reorder: f64,
+    /// Most messages to drop, reorder or duplicate, or unlimited if unset
+    #[serde(default)]
+    limit: Option<u32>,
+}
+
+/// Faults injected into a kind of service, e.g. `cook-chef`
+///
+/// The service crashes or fails on entering `crash_at` or `fail_at` for the
+/// `entry`th time, and takes `slow_steps` times as long over each step.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServiceFaults {
+    service: String,
+    #[serde(default)]
+    crash_at: Option<State>,
+    #[serde(default)]
+    fail_at: Option<State>,
+    #[serde(default = "first")]
+    entry: usize,
+    #[serde(default = "one")]
+    slow_steps: f64,
+}
+
+/// How an injected fault stops a service
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StateFault {
+    /// Exits the process at once, without winding down or reporting failure
+    CRASH,
+    /// Fails the service, as if its hook returned an error
+    FAIL,
+}
+
+/// A set of faults to inject into the chef services, read from a scenario
+/// file so the same failure can be reproduced from run to run
+///
+/// Faults are drawn from a random sequence per topic starting from `seed`, so
+/// the same messages are faulted each time a scenario is run.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    #[serde(default)]
+    seed: u64,
+    #[serde(default)]
+    topics: Vec<TopicFaults>,
+    #[serde(default)]
+    services: Vec<ServiceFaults>,
+}
+
+impl Scenario {
+    /// Reads and checks the YAML scenario at the given path
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+        let scenario: Self =
+            serde_yaml::from_str(&contents).map_err(|e| format!("{}: {}", path.display(), e))?;
+        scenario
+            .check()
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(scenario)
+    }
+
+    /// Checks topics exist, probabilities lie between 0 and 1, and faults are
+    /// only injected on entering states a service can leave
+    fn check(&self) -> Result<(), String> {
+        for faults in &self.topics {
+            TopicName::from_str(&faults.topic)?;
+            for (name, probability) in [
+                ("drop", faults.drop),
+                ("duplicate", faults.duplicate),
+                ("reorder", faults.reorder),
+            ] {
+                if !(0.0..=1.0).contains(&probability) {
+                    return Err(format!(
+                        "{} probability on {} must be between 0 and 1",
+                        name, faults.topic
+                    ));
+                }
+            }
+        }
+        for faults in &self.services {
+            for state in [&faults.crash_at, &faults.fail_at].into_iter().flatten() {
+                if state.is_terminal() {
+                    return Err(format!(
+                        "Faults cannot be injected on entering {}",
+                        state.name()
+                    ));
+                }
+            }
+            if faults.entry == 0 {
+                return Err(format!("entry for {} counts from 1", faults.service));
+            }
+            if !(faults.slow_steps.is_finite() && faults.slow_steps > 0.0) {
+                return Err(format!(
+                    "slow_steps for {} must be a positive number",
+                    faults.service
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Injector for the faults on the given topic received by the given kind
+    /// of service, if there are any
+    pub fn injector<T: Clone>(&self, service: &str, topic: &str) -> Option<TopicInjector<T>> {
+        let faults = self.topics.iter().find(|faults| {
+            faults.topic == topic && faults.service.as_deref().is_none_or(|s| s == service)
+        })?;
+        Some(TopicInjector::new(faults.clone(), self.seed))
+    }
+
+    /// Fault to inject into the given kind of service as it enters the given
+    /// state for the `entry`th time, if any
+    pub fn state_fault(&self, service: &str, state: &State, entry: usize) -> Option<StateFault> {
+        self.services
+            .iter()
+            .filter(|faults| faults.service == service && faults.entry == entry)
+            .find_map(|faults| {
+                if faults.crash_at.as_ref() == Some(state) {
+                    Some(StateFault::CRASH)
+                } else if faults.fail_at.as_ref() == Some(state) {
+                    Some(StateFault::FAIL)
+                } else {
+                    None
+                }
+            })
+    }
+
+    /// Factor the given kind of service's step times are stretched by
+    pub fn step_factor(&self, service: &str) -> f64 {
+        self.services
+            .iter()
+            .filter(|faults| faults.service == service)
+            .map(|faults| faults.slow_steps)
+            .product()
+    }
+
+    /// Stretches a step time by the factor the given kind of service's steps
+    /// run long by, failing if the stretched time is too long to represent
+    pub fn stretch(&self, service: &str, duration: Duration) -> Result<Duration, String> {
+        let factor = self.step_factor(service);
+        Duration::try_from_secs_f64(duration.as_secs_f64() * factor).map_err(|e| {
+            format!(
+                "Unable to stretch {} by {}: {}",
+                format_duration(duration),
+                factor,
+                e
+            )
+        })
+    }
+}
+
+/// Small seeded random number generator (SplitMix64), giving the same
+/// sequence for the same seed on every platform
+#[derive(Debug)]
+pub(crate) struct Rng(pub(crate) u64);
+
+impl Rng {
+    /// Next number in the sequence, between 0 and 1
+    pub(crate) fn next_f64(&mut self) -> f64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        (z >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Applies a topic's faults to the messages taken from a receiver, holding
+/// back those delayed or reordered until they are due
+#[derive(Debug)]
+pub struct TopicInjector<T> {
+    faults: TopicFaults,
+    rng: Rng,
+    faulted: u32,
+    pending: Vec<(Instant, T)>,
+}
+
+impl<T: Clone> TopicInjector<T> {
+    pub fn new(faults: TopicFaults, seed: u64) -> Self {
+        // give each topic its own sequence, so faults on one topic do not
+        // depend on how many messages arrive on another
+        let topic_hash = faults
+            .topic
+            .bytes()
+            .fold(0xcbf2_9ce4_8422_2325u64, |hash, b| {
+                (hash ^ b as u64).wrapping_mul(0x100_0000_01b3)
+            });
+        Self {
+            faults,
+            rng: Rng(seed ^ topic_hash),
+            faulted: 0,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Passes every message `take` yields through the faults, then returns
+    /// the earliest message due by `now`, if any
+    pub fn receive(&mut self, now: Instant, mut take: impl FnMut() -> Option<T>) -> Option<T> {
+        while let Some(msg) = take() {
+            self.inject(now, msg);
+        }
+        let index = self
+            .pending
+            .iter()
+            .enumerate()
+            .filter(|(_, (due, _))| *due <= now)
+            .min_by_key(|(_, (due, _))| *due)
+            .map(|(index, _)| index)?;
+        Some(self.pending.remove(index).1)
+    }
+
+    /// Drops, holds back or duplicates a message received at `now`
+    fn inject(&mut self, now: Instant, msg: T) {
+        let topic = self.faults.topic.clone();
+        if self.roll(self.faults.drop) {
+            warn!(topic, "Injected fault, dropping message");
+            return;
+        }
+        let mut due = now + self.faults.delay;
+        if self.roll(self.faults.reorder) {
+            warn!(topic, "Injected fault, holding message back");
+            due += REORDER_HOLD;
+        }
+        if self.roll(self.faults.duplicate) {
+            warn!(topic, "Injected fault, duplicating message");
+            self.pending.push((due, msg.clone()));
+        }
+        self.pending.push((due, msg));
+    }
+
+    /// Whether to inject a fault of the given probability, until the topic's
+    /// limit of faulted messages is reached
+    fn roll(&mut self, probability: f64) -> bool {
+        let hit = self.rng.next_f64() < probability
+            && self.faults.limit.is_none_or(|limit| self.faulted < limit);
+        if hit {
+            self.faulted += 1;
+        }
+        hit
+    }
+}
+
+/// Loads the scenario named by `CHEF_FAULTS`, if set, to inject its faults
+/// into the given kind of service for the rest of the process
+pub fn init(service: &'static str) -> Result<(), String> {
+    let Ok(path) = env::var(FAULTS_VAR) else {
+        return Ok(());
+    };
+    let scenario = Scenario::load(Path::new(&path))?;
+    warn!(scenario = path, "Injecting faults");
+    let _ = ACTIVE.set((service, scenario));
+    Ok(())
+}
+
+/// Injector for the faults on the given topic in this process, if any
+pub fn injector<T: Clone>(topic: &str) -> Option<TopicInjector<T>> {
+    let (service, scenario) = ACTIVE.get()?;
+    scenario.injector(service, topic)
+}
+
+/// Fault to inject into the given kind of service as it enters the given
+/// state for the `entry`th time, if any
+pub fn state_fault(service: &str, state: &State, entry: usize) -> Option<StateFault> {
+    let (_, scenario) = ACTIVE.get()?;
+    scenario.state_fault(service, state, entry)
+}
+
+/// Stretches a step time by the factor this process's steps run long by,
+/// failing if the stretched time is too long to represent
+pub fn stretch(duration: Duration) -> Result<Duration, String> {
+    match ACTIVE.get() {
+        Some((service, scenario)) => scenario.stretch(service, duration),
+        None => Ok(duration),
+    }
+}
+
+/// Test module for fault injection
+#[cfg(test)]
+mod faults_tests {
+    use std::{
+        path::Path,
+        time::{Duration, Instant},
+    };
+
+    use crate::{
+        faults::{Scenario, StateFault},
+        state::State,
+    };
+
+    fn scenario(yaml: &str) -> Scenario {
+        let scenario: Scenario = serde_yaml::from_str(yaml).unwrap();
+        scenario.check().unwrap();
+        scenario
+    }
+
+    /// Checks messages are dropped, delayed, duplicated and reordered, and the
+    /// same ones are faulted for the same seed
+    #[test]
+    fn topic_faults() {
+        let faulted = |yaml: &str| {
+            let mut injector = scenario(yaml)
+                .injector::<u32>("prep-chef", "prepare_command")
+                .unwrap();
+            let start = Instant::now();
+            let mut sent = 0..20;
+            let mut received = vec![injector.receive(start, || sent.next())];
+            for secs in 1..4 {
+                received.push(injector.receive(start + Duration::from_secs(secs), || None));
+            }
+            while let Some(msg) = injector.receive(start + Duration::from_secs(4), || None) {
+                received.push(Some(msg));
+            }
+            received
+        };
+
+        let dropped = faulted("topics: [{topic: prepare_command, drop: 1.0, limit: 3}]");
+        assert_eq!(dropped[0], Some(3));
+        assert_eq!(dropped.iter().flatten().count(), 17);
+
+        let delayed = faulted("topics: [{topic: prepare_command, delay: 2s}]");
+        assert_eq!(delayed[..3], [None, None, Some(0)]);
+
+        let duplicated = faulted("topics: [{topic: prepare_command, duplicate: 1.0}]");
+        assert_eq!(duplicated[..2], [Some(0), Some(0)]);
+
+        let reordered = "{seed: 7, topics: [{topic: prepare_command, reorder: 0.5}]}";
+        let received = faulted(reordered);
+        assert_eq!(received.iter().flatten().count(), 20);
+        assert!(!received.iter().flatten().is_sorted());
+        assert_eq!(received, faulted(reordered));
+
+        let other_service =
+            scenario("topics: [{topic: prepare_command, service: head-chef, drop: 1.0}]")
+                .injector::<u32>("prep-chef", "prepare_command");
+        assert!(other_service.is_none());
+    }
+
+    /// Checks services are crashed or failed on the chosen entry to a state,
+    /// and invalid scenarios are refused
+    #[test]
+    fn service_faults() {
+        let faults = scenario(
+            "services:
+              - {service: cook-chef, fail_at: EXECUTING, entry: 2, slow_steps: 3}
+              - {service: prep-chef, crash_at: ISSUED}",
+        );
+        assert_eq!(faults.state_fault("cook-chef", &State::EXECUTING, 1), None);
+        assert_eq!(
+            faults.state_fault("cook-chef", &State::EXECUTING, 2),
+            Some(StateFault::FAIL)
+        );
+        assert_eq!(
+            faults.state_fault("prep-chef", &State::ISSUED, 1),
+            Some(StateFault::CRASH)
+        );
+        assert_eq!(faults.step_factor("cook-chef"), 3.0);
+        assert_eq!(faults.step_factor("prep-chef"), 1.0);
+        assert_eq!(
+            faults.stretch("cook-chef", Duration::from_secs(5)),
+            Ok(Duration::from_secs(15))
+        );
+        assert!(faults.stretch("cook-chef", Duration::MAX).is_err());
+
+        for invalid in [
+            "topics: [{topic: prepare_commands}]",
+            "topics: [{topic: control, drop: 2}]",
+            "services: [{service: cook-chef, fail_at: COMPLETED}]",
+            "services: [{service: cook-chef, slow_steps: 0}]",
+            "services: [{service: cook-chef, slow_steps: .nan}]",
+            "services: [{service: cook-chef, slow_steps: .inf}]",
+        ] {
+            let scenario: Scenario = serde_yaml::from_str(invalid).unwrap();
+            assert!(scenario.check().is_err(), "{}", invalid);
+        }
+    }
+
+    /// Checks the example scenarios all load
+    #[test]
+    fn example_scenarios() {
+        for entry in std::fs::read_dir(Path::new("../faults")).unwrap() {
+            let path = entry.unwrap().path();
+            assert!(Scenario::load(&path).is_ok(), "{}", path.display());
+        }
+    }
+}
@@ -0,0 +1,241 @@
+use std::{fmt::Display, path::Path, str::FromStr};
+
+use serde_yaml::Value;
+
+use crate::{migrations, recipe::Recipe};
+
+/// File formats recipes can be read from and written to
+///
+/// YAML is always available, as it is also the document model recipes are
+/// expanded in; the others are enabled by the cargo feature of the same name
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    YAML,
+    #[cfg(feature = "json")]
+    JSON,
+    #[cfg(feature = "toml")]
+    TOML,
+    #[cfg(feature = "ron")]
+    RON,
+}
+
+impl Format {
+    /// Every format enabled in this build
+    pub const ALL: &'static [Format] = &[
+        Format::YAML,
+        #[cfg(feature = "json")]
+        Format::JSON,
+        #[cfg(feature = "toml")]
+        Format::TOML,
+        #[cfg(feature = "ron")]
+        Format::RON,
+    ];
+
+    /// Short name used to select the format explicitly
+    pub fn name(&self) -> &'static str {
+        match self {
+            Format::YAML => "yaml",
+            #[cfg(feature = "json")]
+            Format::JSON => "json",
+            #[cfg(feature = "toml")]
+            Format::TOML => "toml",
+            #[cfg(feature = "ron")]
+            Format::RON => "ron",
+        }
+    }
+
+    /// File extensions identifying the format
+    pub fn extensions(&self) -> &'static [&'static str] {
+        match self {
+            Format::YAML => &["yaml", "yml"],
+            #[cfg(feature = "json")]
+            Format::JSON => &["json"],
+            #[cfg(feature = "toml")]
+            Format::TOML => &["toml"],
+            #[cfg(feature = "ron")]
+            Format::RON => &["ron"],
+        }
+    }
+
+    /// Looks up the format of a file from its extension
+    pub fn from_path(path: &Path) -> Result<Self, String> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default();
+        Self::ALL
+            .iter()
+            .find(|format| format.extensions().contains(&extension))
+            .copied()
+            .ok_or_else(|| {
+                let supported: Vec<&str> = Self::ALL
+                    .iter()
+                    .flat_map(|format| format.extensions())
+                    .copied()
+                    .collect();
+                format!(
+                    "Provided recipe file must be one of: {} ({})",
+                    supported.join(", "),
+                    path.display()
+                )
+            })
+    }
+
+    /// Parses a recipe document written in this format
+    ///
+    /// RON documents are read as a complete recipe, so they cannot include
+    /// other recipes.
+    pub(crate) fn parse(&self, contents: &str) -> Result<Value, String> {
+        match self {
+            Format::YAML => serde_yaml::from_str(contents).map_err(|e| e.to_string()),
+            #[cfg(feature = "json")]
+            Format::JSON => serde_json::from_str(contents).map_err(|e| e.to_string()),
+            #[cfg(feature = "toml")]
+            Format::TOML => toml::from_str(contents).map_err(|e| e.to_string()),
+            // RON's self-describing form drops enum variant names
+            #[cfg(feature = "ron")]
+            Format::RON => {
+                let recipe: Recipe = ron::from_str(contents).map_err(|e| e.to_string())?;
+                serde_yaml::to_value(recipe).map_err(|e| e.to_string())
+            }
+        }
+    }
+
+    /// Writes a recipe in this format
+    pub(crate) fn write(&self, recipe: &Recipe) -> Result<String, String> {
+        match self {
+            Format::YAML => serde_yaml::to_string(recipe).map_err(|e| e.to_string()),
+            #[cfg(feature = "json")]
+            Format::JSON => serde_json::to_string_pretty(recipe).map_err(|e| e.to_string()),
+            #[cfg(feature = "toml")]
+            Format::TOML => toml::to_string_pretty(recipe).map_err(|e| e.to_string()),
+            #[cfg(feature = "ron")]
+            Format::RON => ron::ser::to_string_pretty(recipe, ron::ser::PrettyConfig::default())
+                .map_err(|e| e.to_string()),
+        }
+    }
+}
+
+impl Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    /// Parses a format from its name or one of its extensions
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .find(|format| format.name() == s || format.extensions().contains(&s))
+            .copied()
+            .ok_or_else(|| format!("Unknown recipe format: {}", s))
+    }
+}
+
+/// Reads and parses the recipe file at the given path in the given format,
+/// migrating it to the current format version
+pub(crate) fn load(path: &Path, format: Format) -> Result<Value, String> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+    format
+        .parse(&contents)
+        .and_then(migrations::migrate)
+        .map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Test module for recipe file formats
+#[cfg(test)]
+mod formats_tests {
+    use std::{path::Path, time::Duration};
+
+    use proptest::prelude::*;
+
+    use crate::{
+        formats::Format,
+        ingredients::{Ingredient, Quantity, Unit},
+        recipe::Recipe,
+        steps::{FoodItem, Step},
+        strategies,
+    };
+
+    /// Checks a recipe survives being written and read back in every format
+    #[test]
+    fn round_trip() {
+        let dir = std::env::temp_dir().join(format!("formats-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let recipe = Recipe::new(
+            "salmon".to_string(),
+            "pan fried salmon".to_string(),
+            2,
+            vec![
+                Ingredient::new(FoodItem::SALMON, Quantity::new(300.0, Unit::GRAMS))
+                    .with_batch_capacity(500.0),
+            ],
+            vec![
+                Step::Prepare(FoodItem::SALMON),
+                Step::Cook(FoodItem::SALMON, Duration::from_millis(90500), None),
+            ],
+        );
+        for format in Format::ALL {
+            let path = dir.join(format!("recipe.{}", format.extensions()[0]));
+            recipe.to_file(path.to_str().unwrap()).unwrap();
+            let read = Recipe::from_file(path.to_str().unwrap())
+                .unwrap_or_else(|e| panic!("{}: {}", format, e));
+            assert_eq!(read.get_title(), recipe.get_title(), "{}", format);
+            assert_eq!(read.get_servings(), 2, "{}", format);
+            assert_eq!(
+                read.get_ingredients(),
+                recipe.get_ingredients(),
+                "{}",
+                format
+            );
+            assert_eq!(read.get_steps(), recipe.get_steps(), "{}", format);
+            assert_eq!(read.fingerprint(), recipe.fingerprint(), "{}", format);
+        }
+    }
+
+    /// Checks formats are found by extension and by explicit name
+    #[test]
+    fn format_lookup() {
+        assert_eq!(Format::from_path(Path::new("a/b.yml")), Ok(Format::YAML));
+        assert!(Format::from_path(Path::new("recipe.txt")).is_err());
+        assert!(Format::from_path(Path::new("recipe")).is_err());
+        for format in Format::ALL {
+            assert_eq!(format.name().parse(), Ok(*format));
+            assert_eq!(
+                Format::from_path(Path::new(&format!("r.{}", format.extensions()[0]))),
+                Ok(*format)
+            );
+        }
+        assert!("xml".parse::<Format>().is_err());
+    }
+
+    proptest! {
+        /// Checks any recipe reads back exactly as written, in every format
+        #[test]
+        fn any_round_trip(recipe in strategies::recipe(0..12, Duration::from_secs(3600)), case: u64) {
+            let dir = std::env::temp_dir()
+                .join(format!("formats-any-{}-{}", std::process::id(), case));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            for format in Format::ALL {
+                let path = dir.join(format!("recipe.{}", format.extensions()[0]));
+                recipe.to_file(path.to_str().unwrap()).unwrap();
+                let read = Recipe::from_file(path.to_str().unwrap())
+                    .map_err(|e| TestCaseError::fail(format!("{}: {}", format, e)))?;
+                prop_assert_eq!(
+                    serde_json::to_value(&read).unwrap(),
+                    serde_json::to_value(&recipe).unwrap(),
+                    "{}",
+                    format
+                );
+            }
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+}
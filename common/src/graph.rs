@@ -0,0 +1,147 @@
+use crate::{estimate::Estimate, recipe::Recipe, steps::FoodItem, units::format_duration};
+
+/// Identifier of the node drawn for the step at the given index
+fn node_id(index: usize) -> String {
+    format!("s{}", index + 1)
+}
+
+/// Label of the node drawn for the step at the given index, with the lines
+/// joined by the given line break
+fn node_label(recipe: &Recipe, estimate: &Estimate, index: usize, line_break: &str) -> String {
+    format!(
+        "{}. {}{}~{}",
+        index + 1,
+        recipe.get_steps()[index],
+        line_break,
+        format_duration(estimate.get_steps()[index].get_duration())
+    )
+}
+
+/// Indices of the recipe's steps acting on each food item it uses
+fn steps_by_item(recipe: &Recipe) -> Vec<(FoodItem, Vec<usize>)> {
+    let mut groups: Vec<(FoodItem, Vec<usize>)> = Vec::new();
+    for (i, step) in recipe.get_steps().iter().enumerate() {
+        match groups.iter_mut().find(|(item, _)| *item == step.item()) {
+            Some((_, steps)) => steps.push(i),
+            None => groups.push((step.item(), vec![i])),
+        }
+    }
+    groups
+}
+
+/// Pairs of step indices where the second depends on the first
+fn edges(estimate: &Estimate) -> Vec<(usize, usize)> {
+    estimate
+        .get_steps()
+        .iter()
+        .enumerate()
+        .flat_map(|(i, step)| {
+            step.get_dependencies()
+                .iter()
+                .map(move |&dependency| (dependency, i))
+        })
+        .collect()
+}
+
+/// Renders the recipe's steps as a Graphviz DOT flowchart
+///
+/// Steps are grouped by the item they act on and labelled with their
+/// estimated duration, with edges from each step to those depending on it.
+/// Steps on the critical path are drawn bold. Fails if the recipe can't be
+/// estimated.
+pub fn to_dot(recipe: &Recipe) -> Result<String, String> {
+    let estimate = recipe.estimate()?;
+    let critical_path = estimate.get_critical_path();
+    let mut dot = format!("digraph {:?} {{\n", recipe.get_title());
+    dot += "    rankdir=LR;\n    node [shape=box];\n";
+    for (item, steps) in steps_by_item(recipe) {
+        dot += &format!("    subgraph cluster_{} {{\n", item.name());
+        dot += &format!("        label={:?};\n", item.to_string());
+        for i in steps {
+            let style = if critical_path.contains(&i) {
+                ", style=bold"
+            } else {
+                ""
+            };
+            dot += &format!(
+                "        {} [label={:?}{}];\n",
+                node_id(i),
+                node_label(recipe, &estimate, i, "\n"),
+                style
+            );
+        }
+        dot += "    }\n";
+    }
+    for (from, to) in edges(&estimate) {
+        dot += &format!("    {} -> {};\n", node_id(from), node_id(to));
+    }
+    Ok(dot + "}\n")
+}
+
+/// Renders the recipe's steps as a Mermaid flowchart, laid out like `to_dot`
+pub fn to_mermaid(recipe: &Recipe) -> Result<String, String> {
+    let estimate = recipe.estimate()?;
+    let critical_path = estimate.get_critical_path();
+    let mut mermaid = format!("---\ntitle: {}\n---\nflowchart LR\n", recipe.get_title());
+    for (item, steps) in steps_by_item(recipe) {
+        mermaid += &format!("    subgraph {}[{}]\n", item.name(), item);
+        for i in steps {
+            mermaid += &format!(
+                "        {}[\"{}\"]\n",
+                node_id(i),
+                node_label(recipe, &estimate, i, "<br/>")
+            );
+        }
+        mermaid += "    end\n";
+    }
+    for (from, to) in edges(&estimate) {
+        mermaid += &format!("    {} --> {}\n", node_id(from), node_id(to));
+    }
+    if !critical_path.is_empty() {
+        let critical: Vec<String> = critical_path.iter().map(|&i| node_id(i)).collect();
+        mermaid += "    classDef critical stroke-width:3px\n";
+        mermaid += &format!("    class {} critical\n", critical.join(","));
+    }
+    Ok(mermaid)
+}
+
+/// Test module for recipe diagrams
+#[cfg(test)]
+mod graph_tests {
+    use std::time::Duration;
+
+    use crate::{
+        graph::{to_dot, to_mermaid},
+        recipe::Recipe,
+        steps::{FoodItem, Step},
+    };
+
+    /// Checks both diagrams group steps by item and link dependent steps
+    #[test]
+    fn recipe_diagrams() {
+        let recipe = Recipe::new(
+            "surf".to_string(),
+            "surf and turf".to_string(),
+            1,
+            Vec::new(),
+            vec![
+                Step::Prepare(FoodItem::CHICKEN),
+                Step::Prepare(FoodItem::SALMON),
+                Step::Cook(FoodItem::SALMON, Duration::from_secs(10), None),
+            ],
+        );
+
+        let dot = to_dot(&recipe).unwrap();
+        assert!(dot.starts_with("digraph \"surf\" {"), "{}", dot);
+        assert!(dot.contains("subgraph cluster_salmon {"), "{}", dot);
+        assert!(dot.contains("s3 [label=\"3. Cook Salmon for 10s\\n~25s\", style=bold];"));
+        assert!(dot.contains("s2 -> s3;"), "{}", dot);
+        assert!(!dot.contains("s1 ->"), "{}", dot);
+
+        let mermaid = to_mermaid(&recipe).unwrap();
+        assert!(mermaid.contains("subgraph chicken[Chicken]"), "{}", mermaid);
+        assert!(mermaid.contains("s1[\"1. Prepare Chicken<br/>~7s\"]"));
+        assert!(mermaid.contains("s2 --> s3"), "{}", mermaid);
+        assert!(mermaid.contains("class s2,s3 critical"), "{}", mermaid);
+    }
+}
@@ -0,0 +1,64 @@
+use std::time::{Duration, Instant};
+
+use crate::{
+    clock,
+    io::Sender,
+    msgs::Heartbeat,
+    state::{State, StateMachine},
+    topics::TopicName,
+};
+
+/// Default interval between heartbeats published by a chef service
+pub const DEFAULT_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Publishes heartbeats on behalf of a service
+///
+/// A heartbeat is sent whenever the service's state changes, and otherwise
+/// at most once every `interval`
+pub struct HeartbeatPublisher {
+    service: String,
+    interval: Duration,
+    last_sent: Option<(Instant, State)>,
+    sender: Sender<Heartbeat>,
+}
+
+impl HeartbeatPublisher {
+    /// Creates a publisher for a service of the given kind, e.g. `prep-chef`.
+    /// The process id is appended so multiple instances can be told apart.
+    pub fn new(kind: &str, interval: Duration) -> Self {
+        Self {
+            service: format!("{}-{}", kind, std::process::id()),
+            interval,
+            last_sent: None,
+            sender: Sender::new(TopicName::Heartbeat.to_string(), None),
+        }
+    }
+
+    /// Returns the unique name heartbeats are published under
+    pub fn get_service(&self) -> &str {
+        &self.service
+    }
+
+    /// Sends a heartbeat for the machine's state, along with the transitions
+    /// which led to it, if one is due
+    pub fn beat(&mut self, machine: &StateMachine) -> Result<(), String> {
+        let state = machine.get_state();
+        let due = match &self.last_sent {
+            None => true,
+            Some((sent_at, sent_state)) => {
+                sent_state != state || clock::since(*sent_at) >= self.interval
+            }
+        };
+        if due {
+            self.sender
+                .send(Heartbeat::new(
+                    self.service.clone(),
+                    state.clone(),
+                    machine.get_history().to_vec(),
+                ))
+                .map_err(|e| format!("Failed to send heartbeat: {}", e))?;
+            self.last_sent = Some((clock::now(), state.clone()));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,378 @@
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use schemars::{json_schema, JsonSchema, Schema, SchemaGenerator};
+use serde::Deserialize;
+use serde_yaml::{Mapping, Value};
+
+use crate::{
+    formats::{self, Format},
+    steps::Step,
+};
+
+/// YAML tag marking a step which includes the steps of another recipe
+const INCLUDE_TAG: &str = "Include";
+
+/// Prefix marking a string in a recipe's steps as a reference to a parameter
+const PARAM_PREFIX: char = '$';
+
+/// Where a step of an expanded recipe was written
+///
+/// #### Fields
+/// - `file` the step was read from
+/// - `index` of the step within that file's steps
+/// - `included_from` the include step which pulled in the file, if any
+#[derive(Debug, Clone, PartialEq)]
+pub struct StepOrigin {
+    file: String,
+    index: usize,
+    included_from: Option<Box<StepOrigin>>,
+}
+
+impl StepOrigin {
+    pub fn get_file(&self) -> &String {
+        &self.file
+    }
+    pub fn get_index(&self) -> usize {
+        self.index
+    }
+    pub fn get_included_from(&self) -> Option<&StepOrigin> {
+        self.included_from.as_deref()
+    }
+}
+
+impl Display for StepOrigin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} step {}", self.file, self.index + 1)?;
+        if let Some(parent) = &self.included_from {
+            write!(f, " (included from {})", parent)?;
+        }
+        Ok(())
+    }
+}
+
+/// An include step, naming the recipe whose steps are included and the
+/// parameters they are included with
+#[derive(Deserialize, JsonSchema)]
+struct Include {
+    /// Path of the included recipe, relative to the including file
+    recipe: String,
+    /// Values for the included recipe's parameters
+    #[serde(default)]
+    #[schemars(with = "BTreeMap<String, serde_json::Value>")]
+    params: Mapping,
+}
+
+/// Schema of an entry in a recipe file's steps, which is either a step or an
+/// include of another recipe's steps
+pub(crate) struct StepEntry;
+
+impl JsonSchema for StepEntry {
+    fn schema_name() -> Cow<'static, str> {
+        "StepEntry".into()
+    }
+
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "anyOf": [
+                generator.subschema_for::<Step>(),
+                {
+                    "type": "object",
+                    "properties": { "include": generator.subschema_for::<Include>() },
+                    "required": ["include"],
+                    "additionalProperties": false
+                }
+            ]
+        })
+    }
+}
+
+/// The parts of a recipe file needed to expand its steps
+#[derive(Deserialize)]
+struct RecipeSteps {
+    /// Parameters the recipe accepts, with their default values. Parameters
+    /// without a default must be given by whoever includes the recipe.
+    #[serde(default)]
+    params: Mapping,
+    steps: Vec<Value>,
+}
+
+/// Resolves the value of every parameter a recipe declares, preferring the
+/// given values over the declared defaults
+fn bind_params(declared: &Mapping, given: &Mapping, file: &Path) -> Result<Mapping, String> {
+    if let Some((name, _)) = given.iter().find(|(name, _)| !declared.contains_key(*name)) {
+        return Err(format!(
+            "{} has no parameter named {}",
+            file.display(),
+            serde_yaml::to_string(name).unwrap_or_default().trim_end()
+        ));
+    }
+    let mut params = declared.clone();
+    for (name, value) in given {
+        params.insert(name.clone(), value.clone());
+    }
+    if let Some((name, _)) = params.iter().find(|(_, value)| value.is_null()) {
+        return Err(format!(
+            "No value given for parameter {} of {}",
+            serde_yaml::to_string(name).unwrap_or_default().trim_end(),
+            file.display()
+        ));
+    }
+    Ok(params)
+}
+
+/// Looks up the value of the named parameter
+fn param<'a>(params: &'a Mapping, name: &str) -> Result<&'a Value, String> {
+    params
+        .get(name)
+        .ok_or_else(|| format!("Unknown parameter: {}", name))
+}
+
+/// Replaces every `${name}` within a longer string with the text of the named
+/// parameter, e.g. `${minutes}m` becomes `12m`
+fn interpolate(s: &str, params: &Mapping) -> Result<String, String> {
+    let mut interpolated = String::new();
+    let mut rest = s;
+    while let Some(start) = rest.find("${") {
+        let end = rest[start..]
+            .find('}')
+            .map(|end| start + end)
+            .ok_or_else(|| format!("Unterminated parameter in: {}", s))?;
+        let name = &rest[start + 2..end];
+        interpolated += &rest[..start];
+        interpolated += &match param(params, name)? {
+            Value::String(text) => text.clone(),
+            Value::Number(number) => number.to_string(),
+            Value::Bool(flag) => flag.to_string(),
+            _ => return Err(format!("Parameter {} cannot be used within text", name)),
+        };
+        rest = &rest[end + 1..];
+    }
+    Ok(interpolated + rest)
+}
+
+/// Replaces every `$name` or `${name}` string within the value with the named
+/// parameter, and interpolates `${name}` references within longer strings
+fn substitute(value: Value, params: &Mapping) -> Result<Value, String> {
+    Ok(match value {
+        Value::String(s) => {
+            let whole = s
+                .strip_prefix("${")
+                .and_then(|name| name.strip_suffix('}'))
+                .filter(|name| !name.contains(['$', '{', '}']))
+                .or_else(|| {
+                    s.strip_prefix(PARAM_PREFIX)
+                        .filter(|name| !name.contains('{'))
+                });
+            match whole {
+                Some(name) => param(params, name)?.clone(),
+                None if s.contains("${") => Value::String(interpolate(&s, params)?),
+                None => Value::String(s),
+            }
+        }
+        Value::Sequence(values) => Value::Sequence(
+            values
+                .into_iter()
+                .map(|value| substitute(value, params))
+                .collect::<Result<_, _>>()?,
+        ),
+        Value::Mapping(mapping) => Value::Mapping(
+            mapping
+                .into_iter()
+                .map(|(key, value)| Ok((key, substitute(value, params)?)))
+                .collect::<Result<_, String>>()?,
+        ),
+        Value::Tagged(mut tagged) => {
+            tagged.value = substitute(tagged.value, params)?;
+            Value::Tagged(tagged)
+        }
+        other => other,
+    })
+}
+
+/// Returns the body of an include step, written either tagged as
+/// `!Include {...}` or as a map such as `include: {...}`
+fn include_body(step: &Value) -> Option<&Value> {
+    match step {
+        Value::Tagged(tagged) if tagged.tag == INCLUDE_TAG => Some(&tagged.value),
+        Value::Mapping(mapping) if mapping.len() == 1 => mapping
+            .iter()
+            .find(|(kind, _)| kind.as_str().map(str::to_lowercase).as_deref() == Some("include"))
+            .map(|(_, body)| body),
+        _ => None,
+    }
+}
+
+/// Expands the steps of a parsed recipe file, recursively replacing include
+/// steps with the steps of the recipes they name
+///
+/// `stack` holds the canonical paths of the files currently being expanded,
+/// starting with the top level recipe, and is used to detect include cycles
+pub(crate) fn expand(
+    document: Value,
+    file: &Path,
+    params: &Mapping,
+    included_from: Option<&StepOrigin>,
+    stack: &mut Vec<PathBuf>,
+) -> Result<Vec<(Step, StepOrigin)>, String> {
+    let recipe: RecipeSteps =
+        serde_yaml::from_value(document).map_err(|e| format!("{}: {}", file.display(), e))?;
+    let params = bind_params(&recipe.params, params, file)?;
+
+    let mut steps = Vec::new();
+    for (index, step) in recipe.steps.into_iter().enumerate() {
+        let origin = StepOrigin {
+            file: file.display().to_string(),
+            index,
+            included_from: included_from.map(|origin| Box::new(origin.clone())),
+        };
+        let step = substitute(step, &params).map_err(|e| format!("{}: {}", origin, e))?;
+        match include_body(&step) {
+            Some(body) => {
+                let include: Include = serde_yaml::from_value(body.clone())
+                    .map_err(|e| format!("{}: {}", origin, e))?;
+                // includes are relative to the file including them
+                let path = file
+                    .parent()
+                    .unwrap_or_else(|| Path::new(""))
+                    .join(&include.recipe);
+                let canonical = path.canonicalize().map_err(|e| {
+                    format!("{}: unable to include {}: {}", origin, path.display(), e)
+                })?;
+                if stack.contains(&canonical) {
+                    let cycle: Vec<String> = stack
+                        .iter()
+                        .chain([&canonical])
+                        .map(|path| path.display().to_string())
+                        .collect();
+                    return Err(format!("Include cycle: {}", cycle.join(" -> ")));
+                }
+                stack.push(canonical);
+                let document = formats::load(&path, Format::from_path(&path)?)?;
+                let included = expand(document, &path, &include.params, Some(&origin), stack)?;
+                stack.pop();
+                steps.extend(included);
+            }
+            None => {
+                let step: Step =
+                    serde_yaml::from_value(step).map_err(|e| format!("{}: {}", origin, e))?;
+                steps.push((step, origin));
+            }
+        }
+    }
+    Ok(steps)
+}
+
+/// Test module for recipe includes
+#[cfg(test)]
+mod includes_tests {
+    use std::{collections::BTreeMap, path::PathBuf, time::Duration};
+
+    use crate::{
+        recipe::Recipe,
+        steps::{FoodItem, Step},
+    };
+
+    /// Creates an empty directory for the named test's recipe files
+    fn recipe_dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("includes-{}-{}", test, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("parts")).unwrap();
+        dir
+    }
+
+    /// Checks included steps are expanded in place with their parameters and
+    /// remember where they came from
+    #[test]
+    fn include_with_params() {
+        let dir = recipe_dir("params");
+        std::fs::write(
+            dir.join("parts/sear.yaml"),
+            "params:\n  item: null\n  time: {secs: 60, nanos: 0}\nsteps:\n- !Prepare $item\n- !Cook [$item, $time]\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("main.yaml"),
+            "title: main\ndescription: two seared items\nsteps:\n- !Include {recipe: parts/sear.yaml, params: {item: CHICKEN}}\n- !Include\n  recipe: parts/sear.yaml\n  params: {item: SALMON, time: {secs: 30, nanos: 0}}\n",
+        )
+        .unwrap();
+
+        let recipe = Recipe::from_file(dir.join("main.yaml").to_str().unwrap()).unwrap();
+        assert_eq!(
+            recipe.get_steps(),
+            &vec![
+                Step::Prepare(FoodItem::CHICKEN),
+                Step::Cook(FoodItem::CHICKEN, Duration::from_secs(60), None),
+                Step::Prepare(FoodItem::SALMON),
+                Step::Cook(FoodItem::SALMON, Duration::from_secs(30), None),
+            ]
+        );
+        let origin = &recipe.get_origins()[3];
+        assert!(origin.get_file().ends_with("sear.yaml"));
+        assert_eq!(origin.get_index(), 1);
+        assert_eq!(origin.get_included_from().unwrap().get_index(), 1);
+        assert!(origin.to_string().contains("(included from"));
+    }
+
+    /// Checks recipes including themselves, directly or not, are rejected
+    #[test]
+    fn include_cycle() {
+        let dir = recipe_dir("cycle");
+        std::fs::write(
+            dir.join("a.yaml"),
+            "title: a\ndescription: a\nsteps:\n- !Include {recipe: parts/b.yaml}\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("parts/b.yaml"),
+            "steps:\n- !Prepare SALMON\n- !Include {recipe: ../a.yaml}\n",
+        )
+        .unwrap();
+
+        let error = Recipe::from_file(dir.join("a.yaml").to_str().unwrap()).unwrap_err();
+        assert!(error.starts_with("Include cycle"), "{}", error);
+    }
+
+    /// Checks recipe parameters are interpolated into steps, overridden per
+    /// run, and the substituted steps still checked
+    #[test]
+    fn template_overrides() {
+        let dir = recipe_dir("template");
+        let file = dir.join("fry.yaml");
+        std::fs::write(
+            &file,
+            "title: fry\ndescription: fry\nparams:\n  protein: chicken\n  minutes: 2\nsteps:\n- prepare: ${protein}\n- cook: {item: $protein, for: '${minutes}m30s'}\n",
+        )
+        .unwrap();
+        let file = file.to_str().unwrap();
+
+        let recipe = Recipe::from_file(file).unwrap();
+        assert_eq!(
+            recipe.get_steps()[1],
+            Step::Cook(FoodItem::CHICKEN, Duration::from_secs(150), None)
+        );
+
+        let overrides = BTreeMap::from([
+            ("protein".to_string(), "salmon".to_string()),
+            ("minutes".to_string(), "1".to_string()),
+        ]);
+        let recipe = Recipe::from_file_with(file, &overrides).unwrap();
+        assert_eq!(
+            recipe.get_steps(),
+            &vec![
+                Step::Prepare(FoodItem::SALMON),
+                Step::Cook(FoodItem::SALMON, Duration::from_secs(90), None),
+            ]
+        );
+
+        let bad_item = BTreeMap::from([("protein".to_string(), "tofu".to_string())]);
+        let error = Recipe::from_file_with(file, &bad_item).unwrap_err();
+        assert!(error.contains("Unknown food item: tofu"), "{}", error);
+        let unknown = BTreeMap::from([("sauce".to_string(), "soy".to_string())]);
+        assert!(Recipe::from_file_with(file, &unknown).is_err());
+    }
+}
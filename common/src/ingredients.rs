@@ -0,0 +1,176 @@
+use std::fmt::Display;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::steps::FoodItem;
+
+/// Most batches an ingredient may be cooked in, so a tiny batch capacity or a
+/// large number of servings can't flood the kitchen with cook steps
+pub const MAX_BATCHES: u32 = 100;
+
+/// Units an ingredient quantity may be measured in
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub enum Unit {
+    GRAMS,
+    MILLILITRES,
+    PIECES,
+}
+
+impl Display for Unit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Unit::GRAMS => write!(f, "g"),
+            Unit::MILLILITRES => write!(f, "ml"),
+            Unit::PIECES => write!(f, "pcs"),
+        }
+    }
+}
+
+/// An amount of an ingredient
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Quantity {
+    amount: f64,
+    unit: Unit,
+}
+
+impl Quantity {
+    pub fn new(amount: f64, unit: Unit) -> Self {
+        Self { amount, unit }
+    }
+    pub fn get_amount(&self) -> f64 {
+        self.amount
+    }
+    pub fn get_unit(&self) -> Unit {
+        self.unit
+    }
+
+    /// Multiplies the amount by the given factor, keeping the unit
+    pub fn scaled(&self, factor: f64) -> Self {
+        Self::new(self.amount * factor, self.unit)
+    }
+}
+
+impl Display for Quantity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.amount, self.unit)
+    }
+}
+
+/// A food item used by a recipe, with how much of it the recipe needs and the
+/// rules followed when the recipe is scaled
+///
+/// #### Fields
+/// - `item` the ingredient is made of
+/// - `quantity` used by the recipe at its stated servings
+/// - `batch_capacity` most of the item, in the quantity's unit, which can be
+///   cooked at once, e.g. what fits in a pan. Larger quantities are cooked in
+///   several batches.
+/// - `cook_time_exponent` how cook time grows with the quantity in a batch,
+///   `time * ratio^exponent`. 0 keeps cook times fixed, 1 scales them linearly.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Ingredient {
+    #[serde(with = "crate::steps::item_name")]
+    #[schemars(with = "FoodItem")]
+    item: FoodItem,
+    quantity: Quantity,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    batch_capacity: Option<f64>,
+    #[serde(default)]
+    cook_time_exponent: f64,
+}
+
+impl Ingredient {
+    pub fn new(item: FoodItem, quantity: Quantity) -> Self {
+        Self {
+            item,
+            quantity,
+            batch_capacity: None,
+            cook_time_exponent: 0.0,
+        }
+    }
+
+    /// Sets the most of the item which can be cooked in one batch
+    pub fn with_batch_capacity(mut self, capacity: f64) -> Self {
+        self.batch_capacity = Some(capacity);
+        self
+    }
+
+    /// Sets how cook time grows with the quantity in a batch
+    pub fn with_cook_time_exponent(mut self, exponent: f64) -> Self {
+        self.cook_time_exponent = exponent;
+        self
+    }
+
+    pub fn get_item(&self) -> FoodItem {
+        self.item
+    }
+    pub fn get_quantity(&self) -> Quantity {
+        self.quantity
+    }
+    pub fn get_batch_capacity(&self) -> Option<f64> {
+        self.batch_capacity
+    }
+    pub fn get_cook_time_exponent(&self) -> f64 {
+        self.cook_time_exponent
+    }
+
+    /// Checks the ingredient's quantity and batch capacity are finite and
+    /// positive, with no more than `MAX_BATCHES` batches needed, and its cook
+    /// time exponent finite and not negative
+    pub fn check(&self) -> Result<(), String> {
+        let amount = self.quantity.amount;
+        if !amount.is_finite() || amount <= 0.0 {
+            return Err(format!(
+                "The quantity of {} must be positive, not {}",
+                self.item, amount
+            ));
+        }
+        if let Some(capacity) = self.batch_capacity {
+            if !capacity.is_finite() || capacity <= 0.0 {
+                return Err(format!(
+                    "The batch capacity of {} must be positive, not {}",
+                    self.item, capacity
+                ));
+            }
+            if amount / capacity > MAX_BATCHES as f64 {
+                return Err(format!(
+                    "{} of {} can't be cooked in {} batches of up to {} {}",
+                    self.quantity, self.item, MAX_BATCHES, capacity, self.quantity.unit
+                ));
+            }
+        }
+        if !self.cook_time_exponent.is_finite() || self.cook_time_exponent < 0.0 {
+            return Err(format!(
+                "The cook time exponent of {} can't be negative, not {}",
+                self.item, self.cook_time_exponent
+            ));
+        }
+        Ok(())
+    }
+
+    /// Number of batches needed to cook the ingredient's quantity, which is
+    /// at most `MAX_BATCHES` once the ingredient has been checked
+    pub fn batches(&self) -> u32 {
+        match self.batch_capacity {
+            Some(capacity) if capacity > 0.0 => {
+                ((self.quantity.amount / capacity).ceil() as u32).max(1)
+            }
+            _ => 1,
+        }
+    }
+
+    /// Quantity cooked in each batch
+    pub fn batch_amount(&self) -> f64 {
+        self.quantity.amount / self.batches() as f64
+    }
+
+    /// Returns a copy of the ingredient with its quantity multiplied by the
+    /// given factor
+    pub fn scaled(&self, factor: f64) -> Self {
+        Self {
+            quantity: self.quantity.scaled(factor),
+            ..self.clone()
+        }
+    }
+}
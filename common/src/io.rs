@@ -5,21 +5,52 @@ use rustdds::{
     QosPolicyBuilder, Subscriber, Topic, TopicKind,
 };
 use serde::{Deserialize, Serialize};
-use std::fmt::Debug;
+use std::{
+    fmt::Debug,
+    sync::{Arc, Mutex},
+};
+use tracing::error;
+
+use crate::{
+    clock,
+    faults::{self, TopicInjector},
+    metrics,
+    protocol::{self, Envelope, PROTOCOL_VERSION},
+    sim::{self, Bus},
+};
 
 /// Constant id representing the DDS domain
 const DOMAIN_ID: u16 = 0;
 
-/// A sender instance for sending messages of type T
-pub struct Sender<T>
+/// DDS components kept alive to write to a topic
+struct DdsOutlet<T>
 where
-    T: Debug + Serialize,
+    T: Serialize,
 {
     _participant: DomainParticipant,
     _qos: QosPolicies,
     _topic: Topic,
     _publisher: Publisher,
-    writer: DataWriter<T>,
+    writer: DataWriter<Envelope<T>>,
+}
+
+/// Where a Sender's messages are published
+enum Outlet<T>
+where
+    T: Serialize,
+{
+    Dds(Box<DdsOutlet<T>>),
+    /// The bus of the simulation running on this thread
+    Simulated(Arc<Mutex<Bus>>),
+}
+
+/// A sender instance for sending messages of type T
+pub struct Sender<T>
+where
+    T: Debug + Serialize,
+{
+    topic_name: String,
+    outlet: Outlet<T>,
 }
 
 impl<T> Sender<T>
@@ -33,7 +64,16 @@ where
     /// - Topic
     /// - Publisher
     /// - DataWriter
+    ///
+    /// Within a `Simulation` messages are published on its bus instead.
     pub fn new(topic_name: String, topic_desc: Option<String>) -> Self {
+        if let Some(bus) = sim::bus() {
+            return Self {
+                topic_name,
+                outlet: Outlet::Simulated(bus),
+            };
+        }
+
         // create domain participant and qos policies
         let participant = DomainParticipant::new(DOMAIN_ID).unwrap();
         let qos = QosPolicyBuilder::new().build();
@@ -42,7 +82,7 @@ where
         let topic = participant
             .create_topic(
                 topic_name.clone(),
-                topic_desc.unwrap_or(topic_name),
+                topic_desc.unwrap_or(topic_name.clone()),
                 &qos,
                 TopicKind::NoKey,
             )
@@ -52,39 +92,115 @@ where
         let publisher = participant.create_publisher(&qos).unwrap();
 
         let writer = publisher
-            .create_datawriter_no_key::<T, CDRSerializerAdapter<_>>(&topic, None)
+            .create_datawriter_no_key::<Envelope<T>, CDRSerializerAdapter<_>>(&topic, None)
             .unwrap();
 
         Self {
-            _participant: participant,
-            _qos: qos,
-            _topic: topic,
-            _publisher: publisher,
-            writer,
+            topic_name,
+            outlet: Outlet::Dds(Box::new(DdsOutlet {
+                _participant: participant,
+                _qos: qos,
+                _topic: topic,
+                _publisher: publisher,
+                writer,
+            })),
         }
     }
 
-    /// Sends the given message via DDS to this Sender's topic.
+    /// Sends the given message via DDS to this Sender's topic, in the current
+    /// protocol version.
+    ///
+    /// Failed writes are counted in the `chef_dds_write_errors_total` metric.
     pub fn send(&self, msg: T) -> Result<(), WriteError<T>> {
-        self.writer.write(msg, None)
+        let result = match &self.outlet {
+            Outlet::Dds(dds) => dds
+                .writer
+                .write(Envelope::new(msg), None)
+                .map_err(unwrap_write_error),
+            Outlet::Simulated(bus) => match serde_json::to_value(&msg) {
+                Ok(body) => {
+                    let at = clock::virtual_elapsed().unwrap_or_default();
+                    bus.lock()
+                        .unwrap()
+                        .publish(&self.topic_name, at, PROTOCOL_VERSION, body);
+                    Ok(())
+                }
+                Err(e) => Err(WriteError::Serialization {
+                    reason: e.to_string(),
+                    data: msg,
+                }),
+            },
+        };
+        result.inspect_err(|_| {
+            metrics::inc(
+                "chef_dds_write_errors_total",
+                "DDS writes which failed, per topic",
+                &[("topic", &self.topic_name)],
+            )
+        })
     }
 }
 
-/// A receiver instance for receiving messages of type T
-pub struct Receiver<T>
+/// Gives back the message sent in the envelope which failed to be written
+fn unwrap_write_error<T>(error: WriteError<Envelope<T>>) -> WriteError<T> {
+    // envelopes being sent always hold their message
+    let unwrap = |envelope: Envelope<T>| envelope.into_message().unwrap();
+    match error {
+        WriteError::Serialization { reason, data } => WriteError::Serialization {
+            reason,
+            data: unwrap(data),
+        },
+        WriteError::Poisoned { reason, data } => WriteError::Poisoned {
+            reason,
+            data: unwrap(data),
+        },
+        WriteError::Io(e) => WriteError::Io(e),
+        WriteError::WouldBlock { data } => WriteError::WouldBlock { data: unwrap(data) },
+        WriteError::Internal { reason } => WriteError::Internal { reason },
+    }
+}
+
+/// DDS components kept alive to read from a topic
+struct DdsInlet<T>
 where
-    T: 'static + Debug + for<'de> Deserialize<'de>,
+    T: 'static + for<'de> Deserialize<'de>,
 {
     _participant: DomainParticipant,
     _qos: QosPolicies,
     _topic: Topic,
     _subscriber: Subscriber,
-    reader: DataReader<T>,
+    reader: DataReader<Envelope<T>>,
+}
+
+/// Where a Receiver's messages are taken from
+enum Inlet<T>
+where
+    T: 'static + for<'de> Deserialize<'de>,
+{
+    Dds(Box<DdsInlet<T>>),
+    /// A subscriber on the bus of the simulation running on this thread
+    Simulated(Arc<Mutex<Bus>>, usize),
+}
+
+/// A receiver instance for receiving messages of type T
+///
+/// Messages are passed through any faults injected on the topic by the
+/// scenario loaded with `faults::init`.
+pub struct Receiver<T>
+where
+    T: 'static + Debug + Clone + for<'de> Deserialize<'de>,
+{
+    topic_name: String,
+    inlet: Inlet<T>,
+    injector: Option<TopicInjector<T>>,
+    /// Last error logged by `receive`, so an unreadable message retried each
+    /// cycle is only reported once
+    last_error: Option<String>,
 }
 
 impl<T> Receiver<T>
 where
-    T: 'static + Debug + for<'de> Deserialize<'de>,
+    T: 'static + Debug + Clone + for<'de> Deserialize<'de>,
 {
     /// Creates new Receiver within given domain for a given topic
     ///
@@ -93,7 +209,20 @@ where
     /// - Topic
     /// - Subscriber
     /// - DataReader
+    ///
+    /// Within a `Simulation` messages are taken from its bus instead.
     pub fn new(topic_name: String, topic_desc: Option<String>) -> Self {
+        let injector = faults::injector(&topic_name);
+        if let Some(bus) = sim::bus() {
+            let subscriber = bus.lock().unwrap().subscribe(&topic_name);
+            return Self {
+                topic_name,
+                inlet: Inlet::Simulated(bus, subscriber),
+                injector,
+                last_error: None,
+            };
+        }
+
         // create domain participant and qos policies
         let participant = DomainParticipant::new(DOMAIN_ID).unwrap();
         let qos = QosPolicyBuilder::new().build();
@@ -102,7 +231,7 @@ where
         let topic = participant
             .create_topic(
                 topic_name.clone(),
-                topic_desc.unwrap_or(topic_name),
+                topic_desc.unwrap_or(topic_name.clone()),
                 &qos,
                 TopicKind::NoKey,
             )
@@ -112,23 +241,88 @@ where
         let subscriber = participant.create_subscriber(&qos).unwrap();
 
         let reader = subscriber
-            .create_datareader_no_key::<T, CDRDeserializerAdapter<_>>(&topic, None)
+            .create_datareader_no_key::<Envelope<T>, CDRDeserializerAdapter<_>>(&topic, None)
             .unwrap();
 
         Self {
-            _participant: participant,
-            _qos: qos,
-            _topic: topic,
-            _subscriber: subscriber,
-            reader,
+            topic_name,
+            inlet: Inlet::Dds(Box::new(DdsInlet {
+                _participant: participant,
+                _qos: qos,
+                _topic: topic,
+                _subscriber: subscriber,
+                reader,
+            })),
+            injector,
+            last_error: None,
         }
     }
 
     /// Attempts to receive a given message via DDS from this Receiver's topic.
+    ///
+    /// Messages which can't be read, such as those from services speaking an
+    /// incompatible protocol version, are logged and skipped.
     pub fn receive(&mut self) -> Option<T> {
-        match self.reader.take_next_sample() {
-            Ok(Some(msg)) => Some(msg.into_value()),
-            _ => None,
+        match self.try_receive() {
+            Ok(msg) => msg,
+            Err(e) => {
+                if self.last_error.as_ref() != Some(&e) {
+                    error!("{}", e);
+                    self.last_error = Some(e);
+                }
+                None
+            }
+        }
+    }
+
+    /// Attempts to receive a given message via DDS from this Receiver's topic,
+    /// failing if one can't be read, such as one from a service speaking an
+    /// incompatible protocol version.
+    ///
+    /// Failures are counted in the `chef_protocol_errors_total` metric.
+    pub fn try_receive(&mut self) -> Result<Option<T>, String> {
+        let topic = &self.topic_name;
+        let inlet = &mut self.inlet;
+        let mut failure = None;
+        let mut take = || {
+            let read = match inlet {
+                Inlet::Dds(dds) => match dds.reader.take_next_sample() {
+                    Ok(Some(sample)) => sample.into_value().open(topic).map(Some),
+                    Ok(None) => Ok(None),
+                    Err(e) => Err(format!("Unable to read a {} message: {}", topic, e)),
+                },
+                Inlet::Simulated(bus, subscriber) => {
+                    let now = clock::virtual_elapsed().unwrap_or_default();
+                    match bus.lock().unwrap().take(*subscriber, now) {
+                        Some((version, _)) if !protocol::is_supported(version) => {
+                            Err(protocol::unsupported(topic, version))
+                        }
+                        Some((_, body)) => serde_json::from_value(body)
+                            .map(Some)
+                            .map_err(|e| format!("Unable to read a {} message: {}", topic, e)),
+                        None => Ok(None),
+                    }
+                }
+            };
+            read.unwrap_or_else(|e| {
+                failure = Some(e);
+                None
+            })
+        };
+        let msg = match &mut self.injector {
+            Some(injector) => injector.receive(clock::now(), &mut take),
+            None => take(),
+        };
+        match failure {
+            Some(e) => {
+                metrics::inc(
+                    "chef_protocol_errors_total",
+                    "Messages which could not be read, per topic",
+                    &[("topic", &self.topic_name)],
+                );
+                Err(e)
+            }
+            None => Ok(msg),
         }
     }
 }
@@ -1,6 +1,32 @@
+pub mod appliance;
+pub mod clock;
+pub mod control;
+pub mod estimate;
+pub mod faults;
+pub mod formats;
+pub mod graph;
+pub mod heartbeat;
+pub mod includes;
+pub mod ingredients;
 pub mod io;
+pub mod logging;
+pub mod metrics;
+pub mod migrations;
 pub mod msgs;
+pub mod progress;
+pub mod protocol;
 pub mod recipe;
+pub mod schema;
+pub mod service;
+pub mod sim;
 pub mod state;
 pub mod steps;
+pub mod stock;
+/// Proptest strategies generating arbitrary recipes, for property tests here
+/// and in the services
+#[cfg(any(test, feature = "proptest"))]
+pub mod strategies;
+pub mod timings;
 pub mod topics;
+pub mod trace;
+pub mod units;
@@ -0,0 +1,32 @@
+use std::env;
+
+use tracing::{info_span, span::EnteredSpan};
+use tracing_subscriber::EnvFilter;
+
+/// Environment variable selecting the log output format
+///
+/// - `json` emits one JSON object per line, for log pipelines
+/// - `pretty` emits multi-line, human friendly records
+/// - anything else, or unset, emits one human readable line per record
+pub const LOG_FORMAT_VAR: &str = "CHEF_LOG_FORMAT";
+
+/// Filter used when `RUST_LOG` is unset. The DDS layer is silenced as it logs
+/// its discovery chatter at info level and above.
+const DEFAULT_FILTER: &str = "info,rustdds=off";
+
+/// Installs the global log subscriber for a service
+///
+/// Levels are filtered using the standard `RUST_LOG` directives, defaulting to
+/// `DEFAULT_FILTER`. The returned span names the service on every record
+/// logged while it is held, so it should be kept alive for the whole of `main`.
+pub fn init(service: &'static str) -> EnteredSpan {
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_FILTER));
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+    match env::var(LOG_FORMAT_VAR).as_deref() {
+        Ok("json") => builder.json().init(),
+        Ok("pretty") => builder.pretty().init(),
+        _ => builder.init(),
+    }
+    info_span!("chef", service).entered()
+}
@@ -0,0 +1,348 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    io::{Read, Write},
+    net::TcpListener,
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{clock, state::State, steps::FoodItem};
+
+/// Upper bounds, in seconds, of the buckets used by every histogram
+pub const DEFAULT_BUCKETS: [f64; 14] = [
+    0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0, 1200.0,
+];
+
+/// Process wide registry which every service records its metrics into
+static REGISTRY: Mutex<Registry> = Mutex::new(Registry::new());
+
+/// Label names and values identifying one series within a metric
+type Labels = Vec<(String, String)>;
+
+/// Observations recorded into a single histogram series
+#[derive(Default)]
+struct HistogramSeries {
+    /// Non-cumulative count of observations falling in each bucket
+    buckets: [u64; DEFAULT_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+/// All series sharing a metric name
+enum Family {
+    Counter {
+        help: String,
+        series: BTreeMap<Labels, f64>,
+    },
+    Histogram {
+        help: String,
+        series: BTreeMap<Labels, HistogramSeries>,
+    },
+}
+
+/// Collection of metrics which can be rendered in the Prometheus text
+/// exposition format
+pub struct Registry {
+    families: BTreeMap<String, Family>,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Registry {
+    /// Creates an empty registry
+    pub const fn new() -> Self {
+        Self {
+            families: BTreeMap::new(),
+        }
+    }
+
+    /// Adds the given value to a counter, creating it if needed
+    pub fn add(&mut self, name: &str, help: &str, labels: &[(&str, &str)], value: f64) {
+        let family = self
+            .families
+            .entry(name.to_string())
+            .or_insert_with(|| Family::Counter {
+                help: help.to_string(),
+                series: BTreeMap::new(),
+            });
+        if let Family::Counter { series, .. } = family {
+            *series.entry(Self::labels(labels)).or_default() += value;
+        }
+    }
+
+    /// Records an observation into a histogram, creating it if needed
+    pub fn observe(&mut self, name: &str, help: &str, labels: &[(&str, &str)], value: f64) {
+        let family = self
+            .families
+            .entry(name.to_string())
+            .or_insert_with(|| Family::Histogram {
+                help: help.to_string(),
+                series: BTreeMap::new(),
+            });
+        if let Family::Histogram { series, .. } = family {
+            let histogram = series.entry(Self::labels(labels)).or_default();
+            if let Some(bucket) = DEFAULT_BUCKETS.iter().position(|bound| value <= *bound) {
+                histogram.buckets[bucket] += 1;
+            }
+            histogram.sum += value;
+            histogram.count += 1;
+        }
+    }
+
+    /// Renders every metric in the text exposition format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        for (name, family) in &self.families {
+            match family {
+                Family::Counter { help, series } => {
+                    let _ = writeln!(out, "# HELP {} {}", name, help);
+                    let _ = writeln!(out, "# TYPE {} counter", name);
+                    for (labels, value) in series {
+                        let _ = writeln!(out, "{}{} {}", name, Self::format(labels, None), value);
+                    }
+                }
+                Family::Histogram { help, series } => {
+                    let _ = writeln!(out, "# HELP {} {}", name, help);
+                    let _ = writeln!(out, "# TYPE {} histogram", name);
+                    for (labels, histogram) in series {
+                        let mut cumulative = 0;
+                        for (bound, count) in DEFAULT_BUCKETS.iter().zip(histogram.buckets) {
+                            cumulative += count;
+                            let le = bound.to_string();
+                            let _ = writeln!(
+                                out,
+                                "{}_bucket{} {}",
+                                name,
+                                Self::format(labels, Some(&le)),
+                                cumulative
+                            );
+                        }
+                        let _ = writeln!(
+                            out,
+                            "{}_bucket{} {}",
+                            name,
+                            Self::format(labels, Some("+Inf")),
+                            histogram.count
+                        );
+                        let labels = Self::format(labels, None);
+                        let _ = writeln!(out, "{}_sum{} {}", name, labels, histogram.sum);
+                        let _ = writeln!(out, "{}_count{} {}", name, labels, histogram.count);
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    /// Converts borrowed label pairs into an owned, ordered series key
+    fn labels(labels: &[(&str, &str)]) -> Labels {
+        let mut labels: Labels = labels
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        labels.sort();
+        labels
+    }
+
+    /// Formats labels as `{name="value",...}`, with an optional bucket bound
+    fn format(labels: &Labels, le: Option<&str>) -> String {
+        let mut pairs: Vec<String> = labels
+            .iter()
+            .map(|(name, value)| {
+                let value = value
+                    .replace('\\', "\\\\")
+                    .replace('"', "\\\"")
+                    .replace('\n', "\\n");
+                format!("{}=\"{}\"", name, value)
+            })
+            .collect();
+        if let Some(le) = le {
+            pairs.push(format!("le=\"{}\"", le));
+        }
+        if pairs.is_empty() {
+            String::new()
+        } else {
+            format!("{{{}}}", pairs.join(","))
+        }
+    }
+}
+
+/// Increments a counter in the process wide registry
+pub fn inc(name: &str, help: &str, labels: &[(&str, &str)]) {
+    add(name, help, labels, 1.0);
+}
+
+/// Adds the given value to a counter in the process wide registry
+pub fn add(name: &str, help: &str, labels: &[(&str, &str)], value: f64) {
+    if let Ok(mut registry) = REGISTRY.lock() {
+        registry.add(name, help, labels, value);
+    }
+}
+
+/// Records an observation into a histogram in the process wide registry
+pub fn observe(name: &str, help: &str, labels: &[(&str, &str)], value: f64) {
+    if let Ok(mut registry) = REGISTRY.lock() {
+        registry.observe(name, help, labels, value);
+    }
+}
+
+/// Renders the process wide registry in the text exposition format
+pub fn render() -> String {
+    REGISTRY
+        .lock()
+        .map(|registry| registry.render())
+        .unwrap_or_default()
+}
+
+/// Serves the process wide registry over HTTP on the given local port
+///
+/// Requests are handled on a background thread; every request, regardless of
+/// its path, receives the current metrics
+pub fn serve(port: u16) -> std::io::Result<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            // the request itself is irrelevant, read and discard its head
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request);
+            let body = render();
+            let _ = write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+        }
+    });
+    Ok(())
+}
+
+/// Milestones in the handling of a command, counted per item and step kind
+#[derive(Debug, Clone, Copy)]
+pub enum CommandEvent {
+    Sent,
+    Received,
+    Refused,
+    Acked,
+    Completed,
+    Failed,
+}
+
+/// Counts a command reaching the given milestone
+pub fn record_command(event: CommandEvent, item: FoodItem, step: &str) {
+    let (name, help) = match event {
+        CommandEvent::Sent => ("chef_commands_sent_total", "Commands sent"),
+        CommandEvent::Received => ("chef_commands_received_total", "Commands received"),
+        CommandEvent::Refused => (
+            "chef_commands_refused_total",
+            "Commands refused for belonging to an unexpected recipe",
+        ),
+        CommandEvent::Acked => ("chef_commands_acked_total", "Commands acknowledged"),
+        CommandEvent::Completed => ("chef_commands_completed_total", "Commands completed"),
+        CommandEvent::Failed => ("chef_commands_failed_total", "Commands failed"),
+    };
+    let item = item.to_string();
+    inc(name, help, &[("item", &item), ("step", step)]);
+}
+
+/// Records the time between a command being sent and acknowledged
+pub fn observe_ack_latency(item: FoodItem, step: &str, latency: Duration) {
+    let item = item.to_string();
+    observe(
+        "chef_ack_latency_seconds",
+        "Time between a command being sent and acknowledged",
+        &[("item", &item), ("step", step)],
+        latency.as_secs_f64(),
+    );
+}
+
+/// Records the time taken to carry out a command
+pub fn observe_step_duration(item: FoodItem, step: &str, duration: Duration) {
+    let item = item.to_string();
+    observe(
+        "chef_step_duration_seconds",
+        "Time taken to carry out a command",
+        &[("item", &item), ("step", step)],
+        duration.as_secs_f64(),
+    );
+}
+
+/// Accumulates the time a service spends in each `State`
+#[derive(Default)]
+pub struct StateTimer {
+    current: Option<(&'static str, Instant)>,
+}
+
+impl StateTimer {
+    /// Attributes the time since the last update to the state the service was
+    /// in, then starts timing the given state
+    pub fn update(&mut self, state: &State) {
+        let now = clock::now();
+        if let Some((name, since)) = self.current {
+            add(
+                "chef_state_seconds_total",
+                "Time spent by the service in each state",
+                &[("state", name)],
+                now.duration_since(since).as_secs_f64(),
+            );
+        }
+        self.current = Some((state.name(), now));
+    }
+}
+
+/// Test module for metrics collection and rendering
+#[cfg(test)]
+mod metrics_tests {
+    use crate::metrics::Registry;
+
+    /// Checks counters accumulate separately per label set
+    #[test]
+    fn counter_render() {
+        let mut registry = Registry::new();
+        registry.add("commands_total", "Commands", &[("item", "Salmon")], 1.0);
+        registry.add("commands_total", "Commands", &[("item", "Salmon")], 2.0);
+        registry.add("commands_total", "Commands", &[("item", "Chicken")], 1.0);
+        let rendered = registry.render();
+        assert!(rendered.contains("# TYPE commands_total counter\n"));
+        assert!(rendered.contains("commands_total{item=\"Salmon\"} 3\n"));
+        assert!(rendered.contains("commands_total{item=\"Chicken\"} 1\n"));
+    }
+
+    /// Checks histogram buckets are rendered cumulatively
+    #[test]
+    fn histogram_render() {
+        let mut registry = Registry::new();
+        registry.observe("latency_seconds", "Latency", &[], 0.2);
+        registry.observe("latency_seconds", "Latency", &[], 3.0);
+        registry.observe("latency_seconds", "Latency", &[], 5000.0);
+        let rendered = registry.render();
+        assert!(rendered.contains("latency_seconds_bucket{le=\"0.1\"} 0\n"));
+        assert!(rendered.contains("latency_seconds_bucket{le=\"0.5\"} 1\n"));
+        assert!(rendered.contains("latency_seconds_bucket{le=\"5\"} 2\n"));
+        assert!(rendered.contains("latency_seconds_bucket{le=\"1200\"} 2\n"));
+        assert!(rendered.contains("latency_seconds_bucket{le=\"+Inf\"} 3\n"));
+        assert!(rendered.contains("latency_seconds_sum 5003.2\n"));
+        assert!(rendered.contains("latency_seconds_count 3\n"));
+    }
+
+    /// Checks special characters in label values are escaped
+    #[test]
+    fn label_escaping() {
+        let mut registry = Registry::new();
+        registry.add(
+            "runs_total",
+            "Runs",
+            &[("recipe", "the \"best\" bowl")],
+            1.0,
+        );
+        assert!(registry
+            .render()
+            .contains("runs_total{recipe=\"the \\\"best\\\" bowl\"} 1\n"));
+    }
+}
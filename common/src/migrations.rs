@@ -0,0 +1,112 @@
+use serde_yaml::{Mapping, Value};
+
+/// Version of the recipe file format written by this build
+///
+/// 1. the original format, with steps written as tagged values such as
+///    `!Cook [SALMON, {secs: 10, nanos: 0}]`. Files without a
+///    `format_version` are read as this version.
+/// 2. steps written as maps such as `cook: {item: salmon, for: 10s}`
+pub const FORMAT_VERSION: u32 = 2;
+
+/// Key a recipe document's format version is written under
+const FORMAT_VERSION_KEY: &str = "format_version";
+
+/// Upgrades a document written in the given format version to the next one
+type Migration = fn(Value) -> Result<Value, String>;
+
+/// Migrations between format versions, the first upgrading version 1 to 2
+const MIGRATIONS: [Migration; FORMAT_VERSION as usize - 1] = [map_steps];
+
+/// Format version a recipe document was written in
+fn document_version(document: &Value) -> Result<u32, String> {
+    match document.get(FORMAT_VERSION_KEY) {
+        None => Ok(1),
+        Some(version) => version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .filter(|version| *version > 0)
+            .ok_or_else(|| format!("Invalid format version: {:?}", version)),
+    }
+}
+
+/// Upgrades a parsed recipe document from the format version it was written
+/// in to the current one
+pub(crate) fn migrate(mut document: Value) -> Result<Value, String> {
+    let version = document_version(&document)?;
+    if version > FORMAT_VERSION {
+        return Err(format!(
+            "Recipe is written in format version {}, but only versions up to {} are supported",
+            version, FORMAT_VERSION
+        ));
+    }
+    for migration in &MIGRATIONS[version as usize - 1..] {
+        document = migration(document)?;
+    }
+    if let Value::Mapping(mapping) = &mut document {
+        mapping.insert(FORMAT_VERSION_KEY.into(), FORMAT_VERSION.into());
+    }
+    Ok(document)
+}
+
+/// Rewrites tagged steps as maps, so that `!Prepare SALMON` becomes
+/// `prepare: SALMON` and `!Cook [SALMON, 10]` becomes
+/// `cook: {item: SALMON, for: 10}`
+fn map_steps(mut document: Value) -> Result<Value, String> {
+    let Some(Value::Sequence(steps)) = document.get_mut("steps") else {
+        return Ok(document);
+    };
+    for step in steps.iter_mut() {
+        let Value::Tagged(tagged) = step else {
+            continue;
+        };
+        let kind = tagged
+            .tag
+            .to_string()
+            .trim_start_matches('!')
+            .to_lowercase();
+        let body = match (kind.as_str(), tagged.value.clone()) {
+            ("cook", Value::Sequence(values)) if values.len() == 2 => {
+                let mut fields = Mapping::new();
+                let mut values = values.into_iter();
+                fields.insert("item".into(), values.next().unwrap_or_default());
+                fields.insert("for".into(), values.next().unwrap_or_default());
+                Value::Mapping(fields)
+            }
+            (_, body) => body,
+        };
+        let mut mapping = Mapping::new();
+        mapping.insert(kind.into(), body);
+        *step = Value::Mapping(mapping);
+    }
+    Ok(document)
+}
+
+/// Test module for recipe format migrations
+#[cfg(test)]
+mod migrations_tests {
+    use serde_yaml::Value;
+
+    use crate::migrations::{migrate, FORMAT_VERSION};
+
+    /// Checks original format documents are upgraded and documents from newer
+    /// builds are rejected
+    #[test]
+    fn upgrade_original_format() {
+        let original: Value = serde_yaml::from_str(
+            "title: t\nsteps:\n- !Prepare SALMON\n- !Cook [SALMON, {secs: 10, nanos: 0}]\n- !Include {recipe: a.yaml}\n",
+        )
+        .unwrap();
+        let expected: Value = serde_yaml::from_str(&format!(
+            "title: t\nsteps:\n- prepare: SALMON\n- cook: {{item: SALMON, for: {{secs: 10, nanos: 0}}}}\n- include: {{recipe: a.yaml}}\nformat_version: {}\n",
+            FORMAT_VERSION
+        ))
+        .unwrap();
+        assert_eq!(migrate(original).unwrap(), expected);
+        assert_eq!(migrate(expected.clone()).unwrap(), expected);
+
+        let newer: Value =
+            serde_yaml::from_str(&format!("format_version: {}\n", FORMAT_VERSION + 1)).unwrap();
+        assert!(migrate(newer).is_err());
+        assert!(migrate(serde_yaml::from_str("format_version: 0").unwrap()).is_err());
+    }
+}
@@ -1,21 +1,81 @@
-use std::time::Duration;
+use std::{fmt::Display, str::FromStr, time::Duration};
 
 use serde::{Deserialize, Serialize};
 
-use crate::steps::FoodItem;
+use crate::{
+    appliance::{Appliance, ApplianceKind},
+    protocol,
+    state::{State, Transition},
+    steps::FoodItem,
+    stock::{Shortfall, StockLevel},
+    trace::TraceContext,
+    units::Temperature,
+};
 
 /// Message indicating completion of a command
-#[derive(Debug, Serialize, Deserialize)]
-pub struct CommandDone;
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandDone {
+    run_id: u32,
+    command_id: u32,
+    trace: TraceContext,
+}
+impl CommandDone {
+    pub fn new(run_id: u32, command_id: u32, trace: TraceContext) -> Self {
+        Self {
+            run_id,
+            command_id,
+            trace,
+        }
+    }
+    pub fn get_trace(&self) -> TraceContext {
+        self.trace
+    }
+    pub fn get_run_id(&self) -> u32 {
+        self.run_id
+    }
+    pub fn get_command_id(&self) -> u32 {
+        self.command_id
+    }
+}
 
 /// Command initiating preparation actions
-#[derive(Debug, Serialize, Deserialize)]
+///
+/// `recipe_hash` is the fingerprint of the recipe the command belongs to
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PrepareCommand {
+    run_id: u32,
+    recipe_hash: String,
+    id: u32,
     item: FoodItem,
+    trace: TraceContext,
 }
 impl PrepareCommand {
-    pub fn new(item: FoodItem) -> Self {
-        Self { item }
+    pub fn new(
+        run_id: u32,
+        recipe_hash: String,
+        id: u32,
+        item: FoodItem,
+        trace: TraceContext,
+    ) -> Self {
+        Self {
+            run_id,
+            recipe_hash,
+            id,
+            item,
+            trace,
+        }
+    }
+    pub fn get_trace(&self) -> TraceContext {
+        self.trace
+    }
+    pub fn get_run_id(&self) -> u32 {
+        self.run_id
+    }
+    pub fn get_recipe_hash(&self) -> &str {
+        &self.recipe_hash
+    }
+    pub fn get_id(&self) -> u32 {
+        self.id
     }
     pub fn get_item(&self) -> FoodItem {
         self.item
@@ -23,18 +83,75 @@ impl PrepareCommand {
 }
 
 /// CommandAck indicating reception of a prepare command
-#[derive(Debug, Serialize, Deserialize)]
-pub struct PrepareCommandAck;
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrepareCommandAck {
+    run_id: u32,
+    command_id: u32,
+    trace: TraceContext,
+}
+impl PrepareCommandAck {
+    pub fn new(run_id: u32, command_id: u32, trace: TraceContext) -> Self {
+        Self {
+            run_id,
+            command_id,
+            trace,
+        }
+    }
+    pub fn get_trace(&self) -> TraceContext {
+        self.trace
+    }
+    pub fn get_run_id(&self) -> u32 {
+        self.run_id
+    }
+    pub fn get_command_id(&self) -> u32 {
+        self.command_id
+    }
+}
 
 /// Command initiating cooking actions
-#[derive(Debug, Serialize, Deserialize)]
+///
+/// `recipe_hash` is the fingerprint of the recipe the command belongs to
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CookCommand {
+    run_id: u32,
+    recipe_hash: String,
+    id: u32,
     item: FoodItem,
     time: Duration,
+    temperature: Option<Temperature>,
+    trace: TraceContext,
 }
 impl CookCommand {
-    pub fn new(item: FoodItem, time: Duration) -> Self {
-        Self { item, time }
+    pub fn new(
+        run_id: u32,
+        recipe_hash: String,
+        id: u32,
+        item: FoodItem,
+        time: Duration,
+        temperature: Option<Temperature>,
+        trace: TraceContext,
+    ) -> Self {
+        Self {
+            run_id,
+            recipe_hash,
+            id,
+            item,
+            time,
+            temperature,
+            trace,
+        }
+    }
+    pub fn get_trace(&self) -> TraceContext {
+        self.trace
+    }
+    pub fn get_run_id(&self) -> u32 {
+        self.run_id
+    }
+    pub fn get_recipe_hash(&self) -> &str {
+        &self.recipe_hash
+    }
+    pub fn get_id(&self) -> u32 {
+        self.id
     }
     pub fn get_item(&self) -> FoodItem {
         self.item
@@ -42,8 +159,427 @@ impl CookCommand {
     pub fn get_duration(&self) -> Duration {
         self.time
     }
+    pub fn get_temperature(&self) -> Option<Temperature> {
+        self.temperature
+    }
 }
 
 /// CommandAck indicating reception of a cooking command
-#[derive(Debug, Serialize, Deserialize)]
-pub struct CookCommandAck;
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CookCommandAck {
+    run_id: u32,
+    command_id: u32,
+    trace: TraceContext,
+}
+impl CookCommandAck {
+    pub fn new(run_id: u32, command_id: u32, trace: TraceContext) -> Self {
+        Self {
+            run_id,
+            command_id,
+            trace,
+        }
+    }
+    pub fn get_trace(&self) -> TraceContext {
+        self.trace
+    }
+    pub fn get_run_id(&self) -> u32 {
+        self.run_id
+    }
+    pub fn get_command_id(&self) -> u32 {
+        self.command_id
+    }
+}
+
+/// Sub-phases a worker chef moves through while carrying out a command
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum StepPhase {
+    GATHERING,
+    PREPARING,
+    PREHEATING,
+    COOKING,
+}
+
+impl StepPhase {
+    /// Lowercase name of the phase
+    pub fn name(&self) -> &'static str {
+        match self {
+            StepPhase::GATHERING => "gathering",
+            StepPhase::PREPARING => "preparing",
+            StepPhase::PREHEATING => "preheating",
+            StepPhase::COOKING => "cooking",
+        }
+    }
+}
+
+impl Display for StepPhase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Progress report periodically published by a worker chef executing a command
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StepProgress {
+    run_id: u32,
+    command_id: u32,
+    percent: u8,
+    elapsed: Duration,
+    remaining: Duration,
+    phase: StepPhase,
+}
+impl StepProgress {
+    pub fn new(
+        run_id: u32,
+        command_id: u32,
+        percent: u8,
+        elapsed: Duration,
+        remaining: Duration,
+        phase: StepPhase,
+    ) -> Self {
+        Self {
+            run_id,
+            command_id,
+            percent,
+            elapsed,
+            remaining,
+            phase,
+        }
+    }
+    pub fn get_run_id(&self) -> u32 {
+        self.run_id
+    }
+    pub fn get_command_id(&self) -> u32 {
+        self.command_id
+    }
+    pub fn get_percent(&self) -> u8 {
+        self.percent
+    }
+    pub fn get_elapsed(&self) -> Duration {
+        self.elapsed
+    }
+    pub fn get_remaining(&self) -> Duration {
+        self.remaining
+    }
+    pub fn get_phase(&self) -> StepPhase {
+        self.phase
+    }
+}
+
+/// Periodic liveness message published by every chef service
+///
+/// `service` uniquely names the publishing instance so that any number of
+/// chefs of the same kind can be told apart, `history` lists the latest state
+/// transitions the service has made, and `protocols` advertises the
+/// message protocol versions it can read
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Heartbeat {
+    service: String,
+    state: State,
+    history: Vec<Transition>,
+    protocols: Vec<u32>,
+}
+impl Heartbeat {
+    pub fn new(service: String, state: State, history: Vec<Transition>) -> Self {
+        Self {
+            service,
+            state,
+            history,
+            protocols: protocol::supported_versions(),
+        }
+    }
+    pub fn get_service(&self) -> &str {
+        &self.service
+    }
+    pub fn get_state(&self) -> &State {
+        &self.state
+    }
+    /// Returns the transitions the service has made so far, oldest first
+    pub fn get_history(&self) -> &[Transition] {
+        &self.history
+    }
+    /// Returns the protocol versions the service can read messages in
+    pub fn get_protocols(&self) -> &[u32] {
+        &self.protocols
+    }
+}
+
+/// Actions which can be taken on a running recipe through control messages
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ControlAction {
+    PAUSE,
+    RESUME,
+    CANCEL,
+}
+
+impl ControlAction {
+    /// Lowercase name of the action
+    pub fn name(&self) -> &'static str {
+        match self {
+            ControlAction::PAUSE => "pause",
+            ControlAction::RESUME => "resume",
+            ControlAction::CANCEL => "cancel",
+        }
+    }
+}
+
+impl Display for ControlAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for ControlAction {
+    type Err = String;
+
+    /// Parses an action from its name, e.g. `pause`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [
+            ControlAction::PAUSE,
+            ControlAction::RESUME,
+            ControlAction::CANCEL,
+        ]
+        .into_iter()
+        .find(|action| action.name() == s.trim().to_lowercase())
+        .ok_or_else(|| format!("Unknown control action: {}", s.trim()))
+    }
+}
+
+/// Message holding, resuming or cancelling a running recipe, sent to the head
+/// chef and worker chefs alike
+///
+/// Applies to the run with the given `run_id`, or to any run if `None`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Control {
+    run_id: Option<u32>,
+    action: ControlAction,
+}
+impl Control {
+    pub fn new(run_id: Option<u32>, action: ControlAction) -> Self {
+        Self { run_id, action }
+    }
+    pub fn get_run_id(&self) -> Option<u32> {
+        self.run_id
+    }
+    pub fn get_action(&self) -> ControlAction {
+        self.action
+    }
+    /// Whether the message applies to the run with the given id
+    pub fn applies_to(&self, run_id: u32) -> bool {
+        self.run_id.is_none_or(|id| id == run_id)
+    }
+}
+
+/// Status of one of a cook chef's appliances, published as it heats, cools
+/// and is loaded
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApplianceStatus {
+    service: String,
+    name: String,
+    kind: ApplianceKind,
+    celsius: f64,
+    target_celsius: Option<f64>,
+    capacity: u32,
+    occupied: u32,
+    queued: u32,
+}
+impl ApplianceStatus {
+    /// Describes the given appliance, belonging to the given service, with the
+    /// given number of commands queued waiting for it
+    pub fn new(service: String, appliance: &Appliance, queued: u32) -> Self {
+        Self {
+            service,
+            name: appliance.get_name().to_string(),
+            kind: appliance.get_kind(),
+            celsius: appliance.get_current(),
+            target_celsius: appliance.get_target(),
+            capacity: appliance.get_capacity(),
+            occupied: appliance.get_occupied(),
+            queued,
+        }
+    }
+    pub fn get_service(&self) -> &str {
+        &self.service
+    }
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+    pub fn get_kind(&self) -> ApplianceKind {
+        self.kind
+    }
+    pub fn get_celsius(&self) -> f64 {
+        self.celsius
+    }
+    pub fn get_target_celsius(&self) -> Option<f64> {
+        self.target_celsius
+    }
+    pub fn get_capacity(&self) -> u32 {
+        self.capacity
+    }
+    pub fn get_occupied(&self) -> u32 {
+        self.occupied
+    }
+    pub fn get_queued(&self) -> u32 {
+        self.queued
+    }
+}
+
+/// Actions the pantry can be asked to take on a run's ingredients
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum StockAction {
+    /// Check there is enough of each item, without setting any aside
+    QUERY,
+    /// Set aside enough of each item for the run, if there is
+    RESERVE,
+    /// Take the run's reserved items out of stock, once it completes
+    CONSUME,
+    /// Return the run's reserved items to stock, unused
+    RELEASE,
+}
+
+impl StockAction {
+    /// Lowercase name of the action
+    pub fn name(&self) -> &'static str {
+        match self {
+            StockAction::QUERY => "query",
+            StockAction::RESERVE => "reserve",
+            StockAction::CONSUME => "consume",
+            StockAction::RELEASE => "release",
+        }
+    }
+}
+
+impl Display for StockAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Message asking the pantry to take an action on the given levels of stock
+/// for a run
+///
+/// Levels are only needed to query or reserve, as consuming and releasing act
+/// on what the run reserved.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StockRequest {
+    run_id: u32,
+    action: StockAction,
+    levels: Vec<StockLevel>,
+}
+impl StockRequest {
+    pub fn new(run_id: u32, action: StockAction, levels: Vec<StockLevel>) -> Self {
+        Self {
+            run_id,
+            action,
+            levels,
+        }
+    }
+    pub fn get_run_id(&self) -> u32 {
+        self.run_id
+    }
+    pub fn get_action(&self) -> StockAction {
+        self.action
+    }
+    pub fn get_levels(&self) -> &[StockLevel] {
+        &self.levels
+    }
+}
+
+/// Message answering a `StockRequest`, listing any items there was not enough
+/// of
+///
+/// A query or reservation succeeded if there are no shortfalls.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StockReply {
+    run_id: u32,
+    action: StockAction,
+    shortfalls: Vec<Shortfall>,
+}
+impl StockReply {
+    pub fn new(run_id: u32, action: StockAction, shortfalls: Vec<Shortfall>) -> Self {
+        Self {
+            run_id,
+            action,
+            shortfalls,
+        }
+    }
+    pub fn get_run_id(&self) -> u32 {
+        self.run_id
+    }
+    pub fn get_action(&self) -> StockAction {
+        self.action
+    }
+    pub fn get_shortfalls(&self) -> &[Shortfall] {
+        &self.shortfalls
+    }
+}
+
+/// Message telling the expediter which items make up a run's dish, sent as the
+/// run starts
+///
+/// `items` holds the item each of the recipe's steps works on, indexed by the
+/// step's command id.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlateOrder {
+    run_id: u32,
+    recipe: String,
+    items: Vec<FoodItem>,
+}
+impl PlateOrder {
+    pub fn new(run_id: u32, recipe: String, items: Vec<FoodItem>) -> Self {
+        Self {
+            run_id,
+            recipe,
+            items,
+        }
+    }
+    pub fn get_run_id(&self) -> u32 {
+        self.run_id
+    }
+    pub fn get_recipe(&self) -> &str {
+        &self.recipe
+    }
+    pub fn get_items(&self) -> &[FoodItem] {
+        &self.items
+    }
+}
+
+/// A finished component of a plate, with how long it was held once ready
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PlatedComponent {
+    item: FoodItem,
+    held: Duration,
+    cold: bool,
+}
+impl PlatedComponent {
+    pub fn new(item: FoodItem, held: Duration, cold: bool) -> Self {
+        Self { item, held, cold }
+    }
+    pub fn get_item(&self) -> FoodItem {
+        self.item
+    }
+    pub fn get_held(&self) -> Duration {
+        self.held
+    }
+    /// Whether the component was held too long and went cold
+    pub fn is_cold(&self) -> bool {
+        self.cold
+    }
+}
+
+/// Message from the expediter announcing a run's dish has been plated
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Plate {
+    run_id: u32,
+    components: Vec<PlatedComponent>,
+}
+impl Plate {
+    pub fn new(run_id: u32, components: Vec<PlatedComponent>) -> Self {
+        Self { run_id, components }
+    }
+    pub fn get_run_id(&self) -> u32 {
+        self.run_id
+    }
+    pub fn get_components(&self) -> &[PlatedComponent] {
+        &self.components
+    }
+}
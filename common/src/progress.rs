@@ -0,0 +1,159 @@
+use std::time::{Duration, Instant};
+
+use rustdds::dds::WriteError;
+
+use crate::{
+    clock,
+    io::Sender,
+    msgs::{StepPhase, StepProgress},
+};
+
+/// Default interval between progress reports published by worker chefs
+pub const DEFAULT_PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Tracks the progress of a single command and periodically reports it
+///
+/// A worker creates one tracker per command, knowing up front the total time
+/// the command should take across all of its phases. Each phase is then waited
+/// out through `wait`, which publishes a `StepProgress` every `interval`.
+/// Time spent paused is not counted towards the command's progress.
+pub struct ProgressTracker {
+    run_id: u32,
+    command_id: u32,
+    total: Duration,
+    interval: Duration,
+    started: Instant,
+    paused_for: Duration,
+    paused_at: Option<Instant>,
+}
+
+impl ProgressTracker {
+    /// Creates a new tracker, starting the clock for the given command
+    pub fn new(run_id: u32, command_id: u32, total: Duration, interval: Duration) -> Self {
+        Self {
+            run_id,
+            command_id,
+            total,
+            interval,
+            started: clock::now(),
+            paused_for: Duration::ZERO,
+            paused_at: None,
+        }
+    }
+
+    /// Stops the clock until `resume` is called
+    pub fn pause(&mut self) {
+        self.paused_at.get_or_insert_with(clock::now);
+    }
+
+    /// Restarts the clock after a `pause`
+    pub fn resume(&mut self) {
+        if let Some(paused_at) = self.paused_at.take() {
+            self.paused_for += clock::since(paused_at);
+        }
+    }
+
+    /// Time spent carrying out the command so far, excluding time paused
+    pub fn elapsed(&self) -> Duration {
+        let paused = self.paused_for + self.paused_at.map_or(Duration::ZERO, clock::since);
+        clock::since(self.started).saturating_sub(paused)
+    }
+
+    /// Builds a progress report for the given phase based on the time elapsed
+    pub fn report(&self, phase: StepPhase) -> StepProgress {
+        let elapsed = self.elapsed().min(self.total);
+        let remaining = self.total - elapsed;
+        let percent = if self.total.is_zero() {
+            100
+        } else {
+            (elapsed.as_millis() * 100 / self.total.as_millis().max(1)) as u8
+        };
+        StepProgress::new(
+            self.run_id,
+            self.command_id,
+            percent,
+            elapsed,
+            remaining,
+            phase,
+        )
+    }
+
+    /// Blocks for the given duration while publishing progress reports for the
+    /// given phase every interval, and once more when the phase ends
+    pub fn wait(
+        &self,
+        phase: StepPhase,
+        duration: Duration,
+        sender: &Sender<StepProgress>,
+    ) -> Result<(), WriteError<StepProgress>> {
+        self.wait_or_pause(phase, duration, sender, || false)
+            .map(|_| ())
+    }
+
+    /// Like `wait`, but checks `should_pause` after each progress report and
+    /// stops waiting early if it returns true
+    ///
+    /// Returns the time left in the phase if it was cut short
+    pub fn wait_or_pause(
+        &self,
+        phase: StepPhase,
+        duration: Duration,
+        sender: &Sender<StepProgress>,
+        mut should_pause: impl FnMut() -> bool,
+    ) -> Result<Option<Duration>, WriteError<StepProgress>> {
+        let phase_end = clock::now() + duration;
+        loop {
+            let now = clock::now();
+            if now >= phase_end {
+                break;
+            }
+            clock::sleep(self.interval.min(phase_end - now));
+            sender.send(self.report(phase))?;
+            if should_pause() {
+                return Ok(Some(phase_end.saturating_duration_since(clock::now())));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Test module for progress reporting
+#[cfg(test)]
+mod progress_tests {
+    use std::{thread::sleep, time::Duration};
+
+    use crate::{msgs::StepPhase, progress::ProgressTracker};
+
+    /// Checks a fresh tracker reports no elapsed time
+    #[test]
+    fn report_at_start() {
+        let tracker = ProgressTracker::new(7, 3, Duration::from_secs(60), Duration::from_secs(1));
+        let progress = tracker.report(StepPhase::PREHEATING);
+        assert_eq!(progress.get_command_id(), 3);
+        assert_eq!(progress.get_percent(), 0);
+        assert_eq!(progress.get_phase(), StepPhase::PREHEATING);
+        assert!(progress.get_remaining() > Duration::from_secs(59));
+    }
+
+    /// Checks a command with no duration is immediately complete
+    #[test]
+    fn report_zero_total() {
+        let tracker = ProgressTracker::new(7, 0, Duration::ZERO, Duration::from_secs(1));
+        let progress = tracker.report(StepPhase::COOKING);
+        assert_eq!(progress.get_percent(), 100);
+        assert_eq!(progress.get_remaining(), Duration::ZERO);
+    }
+
+    /// Checks time spent paused is left out of a command's progress
+    #[test]
+    fn report_excludes_pause() {
+        let mut tracker =
+            ProgressTracker::new(7, 1, Duration::from_secs(60), Duration::from_secs(1));
+        tracker.pause();
+        sleep(Duration::from_millis(50));
+        let paused = tracker.report(StepPhase::COOKING);
+        tracker.resume();
+        assert!(paused.get_elapsed() < Duration::from_millis(10));
+        assert!(tracker.elapsed() < Duration::from_millis(25));
+    }
+}
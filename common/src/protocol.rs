@@ -0,0 +1,353 @@
+use std::{fmt, marker::PhantomData};
+
+use serde::{
+    de::{Error as _, SeqAccess, Visitor},
+    ser::{Error as _, SerializeTuple},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+/// Version of the message protocol this build speaks, sent ahead of every
+/// message
+///
+/// Bumped whenever the layout of any message changes, as CDR carries no
+/// field names to tell layouts apart by.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Oldest protocol version messages are still read in
+///
+/// Raised alongside `PROTOCOL_VERSION` unless messages written in the older
+/// layout can still be read as the current one.
+pub const OLDEST_PROTOCOL_VERSION: u32 = 1;
+
+/// Every protocol version messages can be read in, as advertised in heartbeats
+pub fn supported_versions() -> Vec<u32> {
+    (OLDEST_PROTOCOL_VERSION..=PROTOCOL_VERSION).collect()
+}
+
+/// Whether messages written in the given protocol version can be read
+pub fn is_supported(version: u32) -> bool {
+    (OLDEST_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&version)
+}
+
+/// Describes a set of protocol versions, e.g. `1` or `1 to 3`
+fn describe(versions: &[u32]) -> String {
+    match (versions.iter().min(), versions.iter().max()) {
+        (Some(oldest), Some(newest)) if oldest == newest => oldest.to_string(),
+        (Some(oldest), Some(newest)) => format!("{} to {}", oldest, newest),
+        _ => "none".to_string(),
+    }
+}
+
+/// Checks a service advertising the given protocol versions can be worked
+/// with, reading the messages sent to it and writing ones that can be read
+pub fn check_peer(service: &str, versions: &[u32]) -> Result<(), String> {
+    let newest = versions.iter().max().copied().unwrap_or_default();
+    if versions.contains(&PROTOCOL_VERSION) && is_supported(newest) {
+        return Ok(());
+    }
+    Err(format!(
+        "{} speaks protocol versions {}, which is incompatible with {} spoken here",
+        service,
+        describe(versions),
+        describe(&supported_versions())
+    ))
+}
+
+/// A message as sent over DDS, preceded by the protocol version it was
+/// written in
+///
+/// Messages in versions which can't be read are left undecoded, so they can
+/// be reported rather than failing to deserialize.
+pub(crate) struct Envelope<T> {
+    version: u32,
+    message: Option<T>,
+}
+
+impl<T> Envelope<T> {
+    /// Wraps a message to be sent in the current protocol version
+    pub(crate) fn new(message: T) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            message: Some(message),
+        }
+    }
+    /// The message, if it was written in a version which can be read
+    pub(crate) fn into_message(self) -> Option<T> {
+        self.message
+    }
+
+    /// The message, failing if it was written in a version which can't be
+    /// read
+    pub(crate) fn open(self, topic: &str) -> Result<T, String> {
+        self.message.ok_or_else(|| unsupported(topic, self.version))
+    }
+}
+
+/// Error for a message on the given topic written in an unsupported version
+pub(crate) fn unsupported(topic: &str, version: u32) -> String {
+    format!(
+        "Received a {} message in protocol version {}, but only {} can be read here",
+        topic,
+        version,
+        describe(&supported_versions())
+    )
+}
+
+impl<T: Serialize> Serialize for Envelope<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let message = self
+            .message
+            .as_ref()
+            .ok_or_else(|| S::Error::custom("Unable to resend an unread message"))?;
+        let mut tuple = serializer.serialize_tuple(2)?;
+        tuple.serialize_element(&self.version)?;
+        tuple.serialize_element(message)?;
+        tuple.end()
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Envelope<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_tuple(2, EnvelopeVisitor(PhantomData))
+    }
+}
+
+struct EnvelopeVisitor<T>(PhantomData<T>);
+
+impl<'de, T: Deserialize<'de>> Visitor<'de> for EnvelopeVisitor<T> {
+    type Value = Envelope<T>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a protocol version followed by a message")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let version = seq
+            .next_element()?
+            .ok_or_else(|| A::Error::invalid_length(0, &self))?;
+        // the layout of a message in an unknown version can't be known, so it
+        // is left unread
+        let message = match is_supported(version) {
+            true => Some(
+                seq.next_element()?
+                    .ok_or_else(|| A::Error::invalid_length(1, &self))?,
+            ),
+            false => None,
+        };
+        Ok(Envelope { version, message })
+    }
+}
+
+/// Test module for message protocol versions, including a compatibility
+/// suite reading messages recorded by earlier versions
+#[cfg(test)]
+mod protocol_tests {
+    use std::{env, fmt::Debug, fs, path::Path, time::Duration};
+
+    use rustdds::{
+        no_key::{DeserializerAdapter, SerializerAdapter},
+        CDRDeserializerAdapter, CDRSerializerAdapter, RepresentationIdentifier,
+    };
+    use serde::{de::DeserializeOwned, Serialize};
+    use serde_json::{json, Value};
+
+    use crate::{
+        io::Receiver,
+        msgs::{
+            ApplianceStatus, CommandDone, Control, CookCommand, CookCommandAck, Heartbeat, Plate,
+            PlateOrder, PrepareCommand, PrepareCommandAck, StepProgress, StockReply, StockRequest,
+        },
+        protocol::{check_peer, Envelope, PROTOCOL_VERSION},
+        sim::{self, Simulation},
+        topics::TopicName,
+    };
+
+    /// Directory holding messages recorded in each protocol version, as
+    /// `v<version>/<topic>.cdr`
+    const FIXTURES: &str = "../fixtures/protocol";
+
+    /// Environment variable which, when set, records any fixtures missing for
+    /// the current protocol version
+    const WRITE_FIXTURES_VAR: &str = "CHEF_WRITE_FIXTURES";
+
+    fn to_cdr<T: Serialize>(envelope: &Envelope<T>) -> Vec<u8> {
+        CDRSerializerAdapter::<Envelope<T>>::to_bytes(envelope)
+            .unwrap()
+            .to_vec()
+    }
+
+    fn from_cdr<T: DeserializeOwned>(bytes: &[u8]) -> Result<Envelope<T>, String> {
+        CDRDeserializerAdapter::<Envelope<T>>::from_bytes(bytes, RepresentationIdentifier::CDR_LE)
+            .map_err(|e| e.to_string())
+    }
+
+    /// Message recorded as a fixture for the given topic
+    fn sample(topic: TopicName) -> Value {
+        let trace = json!({"trace_id_high": 1, "trace_id_low": 2, "span_id": 3});
+        match topic {
+            TopicName::PrepareCommand => json!({
+                "run_id": 7, "recipe_hash": "ab12", "id": 0, "item": "CHICKEN", "trace": trace
+            }),
+            TopicName::PrepareCommandAck
+            | TopicName::PrepareCommandDone
+            | TopicName::CookCommandAck
+            | TopicName::CookCommandDone => {
+                json!({"run_id": 7, "command_id": 1, "trace": trace})
+            }
+            TopicName::CookCommand => json!({
+                "run_id": 7, "recipe_hash": "ab12", "id": 1, "item": "SALMON",
+                "time": {"secs": 90, "nanos": 0}, "temperature": "215C", "trace": trace
+            }),
+            TopicName::StepProgress => json!({
+                "run_id": 7, "command_id": 1, "percent": 40,
+                "elapsed": {"secs": 36, "nanos": 0}, "remaining": {"secs": 54, "nanos": 0},
+                "phase": "COOKING"
+            }),
+            TopicName::Heartbeat => json!({
+                "service": "cook-chef-42",
+                "state": {"FAILED": "Oven on fire"},
+                "history": [{
+                    "from": "CREATED", "to": "IDLE",
+                    "at": {"secs_since_epoch": 1700000000, "nanos_since_epoch": 0}
+                }],
+                "protocols": [1]
+            }),
+            TopicName::Control => json!({"run_id": 7, "action": "PAUSE"}),
+            TopicName::ApplianceStatus => json!({
+                "service": "cook-chef-42", "name": "oven", "kind": "oven", "celsius": 180.5,
+                "target_celsius": 215.0, "capacity": 2, "occupied": 1, "queued": 0
+            }),
+            TopicName::StockRequest => json!({
+                "run_id": 7, "action": "RESERVE",
+                "levels": [{"item": "salmon", "quantity": {"amount": 300.0, "unit": "GRAMS"}}]
+            }),
+            TopicName::StockReply => json!({
+                "run_id": 7, "action": "RESERVE",
+                "shortfalls": [{
+                    "item": "SALMON", "needed": {"amount": 300.0, "unit": "GRAMS"},
+                    "available": 120.0
+                }]
+            }),
+            TopicName::PlateOrder => json!({
+                "run_id": 7, "recipe": "Salmon for two", "items": ["SALMON", "CHICKEN"]
+            }),
+            TopicName::Plate => json!({
+                "run_id": 7,
+                "components": [{"item": "SALMON", "held": {"secs": 4, "nanos": 0}, "cold": false}]
+            }),
+        }
+    }
+
+    /// Checks the fixture recorded for a topic in the given version reads as
+    /// the topic's message, and that those recorded in the current version
+    /// are written the same way today, recording it first if asked to
+    fn check_fixture<T: Serialize + DeserializeOwned + Debug>(topic: TopicName, version: u32) {
+        let path = Path::new(FIXTURES)
+            .join(format!("v{}", version))
+            .join(format!("{}.cdr", topic));
+        if version == PROTOCOL_VERSION && !path.exists() && env::var(WRITE_FIXTURES_VAR).is_ok() {
+            let message: T = serde_json::from_value(sample(topic)).unwrap();
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, to_cdr(&Envelope::new(message))).unwrap();
+        }
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            // older versions need not have used every topic
+            Err(_) if version < PROTOCOL_VERSION => return,
+            Err(e) => panic!(
+                "{}: {}, record it by running the tests with {} set",
+                path.display(),
+                e,
+                WRITE_FIXTURES_VAR
+            ),
+        };
+        let message: T = from_cdr::<T>(&bytes)
+            .and_then(|envelope| envelope.open(&topic.to_string()))
+            .unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+        if version == PROTOCOL_VERSION {
+            assert_eq!(
+                to_cdr(&Envelope::new(message)),
+                bytes,
+                "{} is written differently, bump PROTOCOL_VERSION",
+                path.display()
+            );
+        }
+    }
+
+    /// Checks messages recorded in every protocol version still read
+    #[test]
+    fn fixtures() {
+        for version in super::OLDEST_PROTOCOL_VERSION..=PROTOCOL_VERSION {
+            for topic in TopicName::ALL {
+                match topic {
+                    TopicName::PrepareCommand => check_fixture::<PrepareCommand>(topic, version),
+                    TopicName::PrepareCommandAck => {
+                        check_fixture::<PrepareCommandAck>(topic, version)
+                    }
+                    TopicName::PrepareCommandDone | TopicName::CookCommandDone => {
+                        check_fixture::<CommandDone>(topic, version)
+                    }
+                    TopicName::CookCommand => check_fixture::<CookCommand>(topic, version),
+                    TopicName::CookCommandAck => check_fixture::<CookCommandAck>(topic, version),
+                    TopicName::StepProgress => check_fixture::<StepProgress>(topic, version),
+                    TopicName::Heartbeat => check_fixture::<Heartbeat>(topic, version),
+                    TopicName::Control => check_fixture::<Control>(topic, version),
+                    TopicName::ApplianceStatus => check_fixture::<ApplianceStatus>(topic, version),
+                    TopicName::StockRequest => check_fixture::<StockRequest>(topic, version),
+                    TopicName::StockReply => check_fixture::<StockReply>(topic, version),
+                    TopicName::PlateOrder => check_fixture::<PlateOrder>(topic, version),
+                    TopicName::Plate => check_fixture::<Plate>(topic, version),
+                }
+            }
+        }
+    }
+
+    /// Checks messages in versions which can't be read are reported rather
+    /// than failing to decode
+    #[test]
+    fn unsupported_versions() {
+        let sample = sample(TopicName::Control);
+        let control: Control = serde_json::from_value(sample.clone()).unwrap();
+        let mut bytes = to_cdr(&Envelope::new(control));
+        bytes[..4].copy_from_slice(&(PROTOCOL_VERSION + 1).to_le_bytes());
+        let error = from_cdr::<Control>(&bytes)
+            .unwrap()
+            .open("control")
+            .unwrap_err();
+        assert!(
+            error.contains("control message in protocol version 2"),
+            "{}",
+            error
+        );
+
+        let _sim = Simulation::new(0);
+        let mut receiver = Receiver::<Control>::new("control".to_string(), None);
+        let bus = sim::bus().unwrap();
+        bus.lock().unwrap().publish(
+            "control",
+            Duration::ZERO,
+            PROTOCOL_VERSION + 1,
+            sample.clone(),
+        );
+        assert!(receiver.try_receive().is_err());
+        bus.lock()
+            .unwrap()
+            .publish("control", Duration::ZERO, PROTOCOL_VERSION, sample);
+        assert!(receiver.try_receive().unwrap().is_some());
+        assert!(receiver.receive().is_none());
+    }
+
+    /// Checks services are only worked with when they can read the current
+    /// version and write one which can be read
+    #[test]
+    fn peers() {
+        assert!(check_peer("prep-chef-1", &[PROTOCOL_VERSION]).is_ok());
+        assert!(check_peer("prep-chef-1", &[PROTOCOL_VERSION, PROTOCOL_VERSION + 1]).is_err());
+        let error = check_peer("prep-chef-1", &[PROTOCOL_VERSION + 1]).unwrap_err();
+        assert_eq!(
+            error,
+            "prep-chef-1 speaks protocol versions 2, which is incompatible with 1 spoken here"
+        );
+        assert!(check_peer("prep-chef-1", &[]).is_err());
+    }
+}
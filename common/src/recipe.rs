@@ -1,67 +1,403 @@
+use std::{collections::BTreeMap, path::Path, time::Duration};
+
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
+use sha2::{Digest, Sha256};
 
-use crate::steps::Step;
+use crate::{
+    estimate::Estimate,
+    formats::{self, Format},
+    includes::{self, StepEntry, StepOrigin},
+    ingredients::Ingredient,
+    migrations::FORMAT_VERSION,
+    steps::Step,
+};
 
 /// Represents a cooking recipe
 ///
 /// #### Fields
+/// - `format_version` of the recipe file format the recipe is written in.
+///   Recipes are migrated to the current version when read.
 /// - `title` of the recipe
 /// - `description` of the recipe
-/// - `steps` a vector of cooking `Step`s required to complete the recipe
-#[derive(Debug, Serialize, Deserialize)]
+/// - `version` of the recipe itself, as chosen by its author
+/// - `servings` the recipe's quantities and steps make, 1 if unspecified
+/// - `ingredients` the quantities of each food item used, along with their
+///   scaling rules
+/// - `steps` a vector of cooking `Step`s required to complete the recipe. Cook
+///   steps describe cooking a single batch of their item.
+/// - `origins` where each step was written, once includes have been expanded
+/// - `scaled` whether the recipe was scaled from the servings it was written
+///   for, splitting its cook steps into batches
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct Recipe {
+    #[serde(default = "default_format_version")]
+    format_version: u32,
     title: String,
     description: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    version: Option<String>,
+    #[serde(default = "default_servings")]
+    servings: u32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    ingredients: Vec<Ingredient>,
+    #[schemars(with = "Vec<StepEntry>")]
     steps: Vec<Step>,
+    #[serde(skip)]
+    origins: Vec<StepOrigin>,
+    #[serde(skip)]
+    scaled: bool,
+}
+
+/// Format version of recipes which don't state one, which predate versioning
+fn default_format_version() -> u32 {
+    1
+}
+
+/// Servings assumed for recipes which don't state them
+fn default_servings() -> u32 {
+    1
 }
 
 impl Recipe {
+    pub fn new(
+        title: String,
+        description: String,
+        servings: u32,
+        ingredients: Vec<Ingredient>,
+        steps: Vec<Step>,
+    ) -> Self {
+        Self {
+            format_version: FORMAT_VERSION,
+            title,
+            description,
+            version: None,
+            servings,
+            ingredients,
+            steps,
+            origins: Vec::new(),
+            scaled: false,
+        }
+    }
+
+    /// Sets the version of the recipe
+    pub fn with_version(mut self, version: String) -> Self {
+        self.version = Some(version);
+        self
+    }
+
+    pub fn get_format_version(&self) -> u32 {
+        self.format_version
+    }
     pub fn get_title(&self) -> &String {
         &self.title
     }
     pub fn get_description(&self) -> &String {
         &self.description
     }
+    pub fn get_version(&self) -> Option<&String> {
+        self.version.as_ref()
+    }
+    pub fn get_servings(&self) -> u32 {
+        self.servings
+    }
+    pub fn get_ingredients(&self) -> &Vec<Ingredient> {
+        &self.ingredients
+    }
     pub fn get_steps(&self) -> &Vec<Step> {
         &self.steps
     }
+    /// Where each step was written, empty if the recipe wasn't read from file
+    pub fn get_origins(&self) -> &Vec<StepOrigin> {
+        &self.origins
+    }
 
-    /// Attempts to create a recipe from the given file path
-    pub fn from_file(in_file_path: &str) -> Result<Self, String> {
-        // ensure given file is a YAML
-        if !Self::is_yaml(in_file_path) {
-            return Err("Provided recipe file must be a YAML file".to_string());
+    /// Ingredient entry for the given step's food item, if the recipe has one
+    fn ingredient_for(&self, step: &Step) -> Option<&Ingredient> {
+        self.ingredients
+            .iter()
+            .find(|ingredient| ingredient.get_item() == step.item())
+    }
+
+    /// Creates a copy of this recipe adjusted to make the given servings
+    ///
+    /// Ingredient quantities scale in proportion to the servings. Each cook
+    /// step is repeated once per batch its ingredient now needs, and its time
+    /// adjusted for the change in quantity per batch following the
+    /// ingredient's cook time exponent. Steps for items without an ingredient
+    /// entry are kept as they are. Fails if any ingredient's quantity, batch
+    /// capacity or cook time exponent can't be scaled by, if an ingredient
+    /// would need more than `MAX_BATCHES` batches, or if the recipe was
+    /// already scaled, as its cook steps would be split into batches again.
+    pub fn scale(&self, servings: u32) -> Result<Self, String> {
+        if servings == 0 || self.servings == 0 {
+            return Err("Recipes can only be scaled between non-zero servings".to_string());
+        }
+        if self.scaled {
+            return Err("Recipes can only be scaled from the servings written".to_string());
+        }
+        for ingredient in &self.ingredients {
+            ingredient.check()?;
         }
+        let factor = servings as f64 / self.servings as f64;
+
+        let mut steps = Vec::new();
+        let mut origins = Vec::new();
+        for (i, step) in self.steps.iter().enumerate() {
+            let origin = self.origins.get(i);
+            match (step, self.ingredient_for(step)) {
+                (Step::Cook(item, time, temperature), Some(ingredient)) => {
+                    let scaled = ingredient.scaled(factor);
+                    scaled.check()?;
+                    let ratio = scaled.batch_amount() / ingredient.batch_amount();
+                    let time =
+                        scale_duration(*time, ratio.powf(ingredient.get_cook_time_exponent()));
+                    for _ in 0..scaled.batches() {
+                        steps.push(Step::Cook(*item, time, *temperature));
+                        origins.extend(origin.cloned());
+                    }
+                }
+                _ => {
+                    steps.push(step.clone());
+                    origins.extend(origin.cloned());
+                }
+            }
+        }
+
+        Ok(Self {
+            format_version: self.format_version,
+            title: self.title.clone(),
+            description: self.description.clone(),
+            version: self.version.clone(),
+            servings,
+            ingredients: self
+                .ingredients
+                .iter()
+                .map(|ingredient| ingredient.scaled(factor))
+                .collect(),
+            steps,
+            origins,
+            scaled: true,
+        })
+    }
+
+    /// Estimates how long the recipe takes, scheduling each step as early as
+    /// the steps it depends on allow. Fails if any step can't be carried out.
+    pub fn estimate(&self) -> Result<Estimate, String> {
+        Estimate::new(&self.steps)
+    }
+
+    /// Canonical hash of the recipe's content, as a hex encoded SHA-256
+    ///
+    /// Covers everything which affects how the recipe is carried out, once
+    /// includes are expanded, parameters substituted and the recipe scaled, but
+    /// not where its steps were written. Recipes read from files of different
+    /// formats or format versions hash the same if their content does.
+    pub fn fingerprint(&self) -> String {
+        // fields serialize in declaration order, so the JSON form is canonical
+        let canonical = serde_json::to_vec(self)
+            .expect("recipes have no maps with non-string keys, so always serialize to JSON");
+        Sha256::digest(canonical)
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    /// Attempts to create a recipe from the given file path, in the format
+    /// given by its extension
+    pub fn from_file(in_file_path: &str) -> Result<Self, String> {
+        Self::from_file_with(in_file_path, &BTreeMap::new())
+    }
+
+    /// Attempts to create a recipe from the given file path, in the format
+    /// given by its extension, overriding the defaults of the named recipe
+    /// parameters
+    pub fn from_file_with(
+        in_file_path: &str,
+        overrides: &BTreeMap<String, String>,
+    ) -> Result<Self, String> {
+        let format = Format::from_path(Path::new(in_file_path))?;
+        Self::from_file_as(in_file_path, format, overrides)
+    }
+
+    /// Attempts to create a recipe from the given file path, in the given
+    /// format, overriding the defaults of the named recipe parameters
+    ///
+    /// The recipe's parameters are substituted for the `$name` and `${name}`
+    /// references in its steps before the steps are read, so overridden values
+    /// are checked like any other. Include steps are replaced by the steps of
+    /// the recipe file they name, relative to the including file, with any
+    /// parameters given substituted in the same way. Included files are read
+    /// in the format given by their extension.
+    pub fn from_file_as(
+        in_file_path: &str,
+        format: Format,
+        overrides: &BTreeMap<String, String>,
+    ) -> Result<Self, String> {
         // check that file exists
-        if !std::path::Path::new(in_file_path).exists() {
+        let path = Path::new(in_file_path);
+        if !path.exists() {
             return Err("Unable to locate file at: ".to_string() + in_file_path);
         }
-        // read file contents
-        match std::fs::read_to_string(in_file_path) {
-            Ok(contents) => serde_yaml::from_str::<Self>(&contents).map_err(|e| e.to_string()),
-            Err(e) => Err(e.to_string()),
+        // read file contents and expand any included recipes
+        let mut document = formats::load(path, format)?;
+        let mut stack = vec![path.canonicalize().map_err(|e| e.to_string())?];
+        let params: Mapping = overrides
+            .iter()
+            .map(|(name, value)| (name.as_str().into(), value.as_str().into()))
+            .collect();
+        let (steps, origins) = includes::expand(document.clone(), path, &params, None, &mut stack)?
+            .into_iter()
+            .unzip();
+        // steps have already been read, leaving the rest of the recipe
+        document["steps"] = Value::Sequence(Vec::new());
+        let recipe: Self = serde_yaml::from_value(document).map_err(|e| e.to_string())?;
+        for ingredient in &recipe.ingredients {
+            ingredient.check()?;
         }
+        Ok(Self {
+            steps,
+            origins,
+            ..recipe
+        })
     }
 
-    /// Attempts to write this recipe to file at the given location
+    /// Attempts to write this recipe to file at the given location, in the
+    /// format given by its extension
     pub fn to_file(&self, out_file_path: &str) -> Result<(), String> {
-        // ensure given file is a YAML
-        if !Self::is_yaml(out_file_path) {
-            return Err("Provided recipe file must be a YAML file".to_string());
-        }
+        self.to_file_as(out_file_path, Format::from_path(Path::new(out_file_path))?)
+    }
+
+    /// Attempts to write this recipe to file at the given location, in the
+    /// given format
+    pub fn to_file_as(&self, out_file_path: &str, format: Format) -> Result<(), String> {
         // ensure no file already exists
-        if std::path::Path::new(out_file_path).exists() {
+        if Path::new(out_file_path).exists() {
             return Err("Another file already exists at: ".to_string() + out_file_path);
         }
         // serialize and write to file
-        match serde_yaml::to_string(&self) {
-            Ok(yaml_str) => std::fs::write(out_file_path, yaml_str).map_err(|e| e.to_string()),
-            Err(e) => Err(e.to_string()),
+        let contents = format.write(self)?;
+        std::fs::write(out_file_path, contents).map_err(|e| e.to_string())
+    }
+}
+
+/// Multiplies a duration by the given factor, rounded to the millisecond
+fn scale_duration(duration: Duration, factor: f64) -> Duration {
+    Duration::from_millis((duration.as_secs_f64() * factor * 1000.0).round() as u64)
+}
+
+/// Test module for recipe scaling
+#[cfg(test)]
+mod recipe_tests {
+    use std::time::Duration;
+
+    use crate::{
+        ingredients::{Ingredient, Quantity, Unit},
+        migrations::FORMAT_VERSION,
+        recipe::Recipe,
+        steps::{FoodItem, Step},
+    };
+
+    /// Two serving salmon recipe, cooked in a pan holding up to 500g
+    fn salmon_recipe(cook_time_exponent: f64) -> Recipe {
+        Recipe {
+            format_version: FORMAT_VERSION,
+            title: "salmon".to_string(),
+            description: "pan fried salmon".to_string(),
+            version: None,
+            servings: 2,
+            ingredients: vec![
+                Ingredient::new(FoodItem::SALMON, Quantity::new(300.0, Unit::GRAMS))
+                    .with_batch_capacity(500.0)
+                    .with_cook_time_exponent(cook_time_exponent),
+            ],
+            steps: vec![
+                Step::Prepare(FoodItem::SALMON),
+                Step::Cook(FoodItem::SALMON, Duration::from_secs(600), None),
+            ],
+            origins: Vec::new(),
+            scaled: false,
+        }
+    }
+
+    /// Checks quantities and cook times scale within a single batch
+    #[test]
+    fn scale_within_batch() {
+        let scaled = salmon_recipe(1.0).scale(3).unwrap();
+        assert_eq!(scaled.get_servings(), 3);
+        assert_eq!(
+            scaled.get_ingredients()[0].get_quantity(),
+            Quantity::new(450.0, Unit::GRAMS)
+        );
+        assert_eq!(
+            scaled.get_steps(),
+            &vec![
+                Step::Prepare(FoodItem::SALMON),
+                Step::Cook(FoodItem::SALMON, Duration::from_secs(900), None),
+            ]
+        );
+    }
+
+    /// Checks cook steps are split into batches once the capacity is exceeded
+    #[test]
+    fn scale_into_batches() {
+        // 1200g needs three 400g batches, each a third larger than the original
+        let scaled = salmon_recipe(0.0).scale(8).unwrap();
+        assert_eq!(scaled.get_steps().len(), 4);
+        assert_eq!(
+            scaled.get_steps()[3],
+            Step::Cook(FoodItem::SALMON, Duration::from_secs(600), None)
+        );
+
+        let scaled = salmon_recipe(1.0).scale(8).unwrap();
+        assert_eq!(
+            scaled.get_steps()[1],
+            Step::Cook(FoodItem::SALMON, Duration::from_secs(800), None)
+        );
+        assert!(salmon_recipe(1.0).scale(0).is_err());
+        // more batches than the kitchen takes, or splitting batches again
+        assert!(salmon_recipe(1.0).scale(1000).is_err());
+        assert!(salmon_recipe(1.0).scale(8).unwrap().scale(2).is_err());
+    }
+
+    /// Checks recipes aren't scaled by quantities, capacities or exponents
+    /// which would make nonsense of their cook times
+    #[test]
+    fn scale_rejects_invalid_ingredients() {
+        let salmon = |amount| Ingredient::new(FoodItem::SALMON, Quantity::new(amount, Unit::GRAMS));
+        for ingredient in [
+            salmon(0.0),
+            salmon(f64::NAN),
+            salmon(300.0).with_batch_capacity(-500.0),
+            salmon(300.0).with_batch_capacity(0.0),
+            salmon(300.0).with_cook_time_exponent(-1.0),
+            salmon(300.0).with_cook_time_exponent(f64::INFINITY),
+        ] {
+            let recipe = Recipe {
+                ingredients: vec![ingredient.clone()],
+                ..salmon_recipe(1.0)
+            };
+            assert!(recipe.scale(3).is_err(), "{:?}", ingredient);
         }
     }
 
-    /// Checks end of given filename for .yaml or .yml
-    fn is_yaml(file_path: &str) -> bool {
-        file_path.ends_with(".yaml") || file_path.ends_with(".yml")
+    /// Checks the fingerprint follows the recipe's content
+    #[test]
+    fn fingerprint_content() {
+        let fingerprint = salmon_recipe(1.0).fingerprint();
+        assert_eq!(fingerprint.len(), 64);
+        assert_eq!(salmon_recipe(1.0).fingerprint(), fingerprint);
+        assert_eq!(
+            salmon_recipe(1.0).scale(2).unwrap().fingerprint(),
+            fingerprint
+        );
+        assert_ne!(salmon_recipe(0.0).fingerprint(), fingerprint);
+        assert_ne!(
+            salmon_recipe(1.0).scale(3).unwrap().fingerprint(),
+            fingerprint
+        );
+        let versioned = salmon_recipe(1.0).with_version("2".to_string());
+        assert_ne!(versioned.fingerprint(), fingerprint);
     }
 }
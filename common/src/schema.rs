@@ -0,0 +1,90 @@
+use std::collections::BTreeMap;
+
+use schemars::{generate::SchemaSettings, JsonSchema};
+use serde_json::Value;
+
+use crate::recipe::Recipe;
+
+/// A recipe as written in a file, whose steps may include other recipes
+#[derive(JsonSchema)]
+#[schemars(title = "Recipe")]
+#[allow(dead_code)]
+struct RecipeFile {
+    #[schemars(flatten)]
+    recipe: Recipe,
+    /// Parameters accepted by the recipe when included by another, with their
+    /// default values. Parameters defaulting to null must be given.
+    params: Option<BTreeMap<String, Value>>,
+}
+
+/// Generates the JSON Schema describing recipe files, in the draft 7 dialect
+/// most editors support
+///
+/// The schema covers the readable form recipes are written in, rather than
+/// the older tagged form of steps
+pub fn recipe_schema() -> Value {
+    SchemaSettings::draft07()
+        .into_generator()
+        .into_root_schema_for::<RecipeFile>()
+        .to_value()
+}
+
+/// Test module for the recipe schema
+#[cfg(test)]
+mod schema_tests {
+    use std::path::{Path, PathBuf};
+
+    use serde_json::Value;
+
+    use crate::schema::recipe_schema;
+
+    /// Location of the checked-in schema, relative to this crate
+    const SCHEMA_FILE: &str = "../schema/recipe.schema.json";
+
+    /// Lists every recipe file below the given directory
+    fn recipe_files(dir: &Path) -> Vec<PathBuf> {
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                files.extend(recipe_files(&path));
+            } else {
+                files.push(path);
+            }
+        }
+        files
+    }
+
+    /// Checks the checked-in schema matches the one generated from the types
+    #[test]
+    fn schema_is_current() {
+        let generated = serde_json::to_string_pretty(&recipe_schema()).unwrap() + "\n";
+        let checked_in = std::fs::read_to_string(SCHEMA_FILE).unwrap_or_default();
+        assert!(
+            generated == checked_in,
+            "{} is out of date, regenerate it with `recipe-tool schema`",
+            SCHEMA_FILE
+        );
+    }
+
+    /// Checks every recipe in the repository validates against the schema
+    #[test]
+    fn recipes_validate() {
+        let validator = jsonschema::draft7::new(&recipe_schema()).unwrap();
+        let files = recipe_files(Path::new("../recipes"));
+        assert!(!files.is_empty());
+        for file in files {
+            // sketches a future recipe format rather than being a recipe
+            if file.ends_with("concept.yaml") {
+                continue;
+            }
+            let recipe: Value =
+                serde_yaml::from_str(&std::fs::read_to_string(&file).unwrap()).unwrap();
+            let errors: Vec<String> = validator
+                .iter_errors(&recipe)
+                .map(|error| format!("{}: {}", error.instance_path(), error))
+                .collect();
+            assert!(errors.is_empty(), "{}: {:#?}", file.display(), errors);
+        }
+    }
+}
@@ -0,0 +1,294 @@
+use tracing::{error, info, warn, Span};
+
+use crate::{
+    faults::{self, StateFault, CRASH_EXIT_CODE},
+    heartbeat::{HeartbeatPublisher, DEFAULT_HEARTBEAT_INTERVAL},
+    metrics::StateTimer,
+    state::{State, StateMachine, Transition},
+};
+
+/// Lifecycle hooks of a chef service, which a `ServiceRunner` moves through
+/// its states
+///
+/// The hook for the service's current state is called once per cycle, and
+/// returns the state to move to, which may be the same state to keep waiting.
+/// Errors fail the service, with the error as the reason.
+pub trait Service {
+    /// Kind of service, naming it in heartbeats, e.g. `prep-chef`
+    const KIND: &'static str;
+
+    /// Log span entered while the service cycles, e.g. covering the command
+    /// being carried out
+    fn span(&self) -> Span {
+        Span::none()
+    }
+
+    /// Called each cycle while `CREATED`, to set up the service
+    fn on_created(&mut self) -> Result<State, String>;
+    /// Called each cycle while `IDLE`, typically to await instructions. By
+    /// default the service is issued straight away.
+    fn on_idle(&mut self) -> Result<State, String> {
+        Ok(State::ISSUED)
+    }
+    /// Called each cycle while `ISSUED`, to get ready to carry them out
+    fn on_issued(&mut self) -> Result<State, String>;
+    /// Called each cycle while `EXECUTING`, to carry them out
+    fn on_executing(&mut self) -> Result<State, String>;
+    /// Called each cycle while `PAUSED`. By default the service stays paused.
+    fn on_paused(&mut self) -> Result<State, String> {
+        Ok(State::PAUSED)
+    }
+    /// Called each cycle while `SHUTTING_DOWN`, to wind down any work in
+    /// hand. By default the service is cancelled straight away.
+    fn on_shutting_down(&mut self) -> Result<State, String> {
+        Ok(State::CANCELLED)
+    }
+
+    /// Called on the cycle the service completes
+    fn on_completed(&mut self) {}
+    /// Called on the cycle the service fails, with the reason it failed
+    fn on_failed(&mut self, _reason: &str) {}
+    /// Called on the cycle the service is cancelled
+    fn on_cancelled(&mut self) {}
+    /// Called once the runner stops running the service, whether it completed
+    /// or failed
+    fn on_shutdown(&mut self) {}
+}
+
+/// Runs a service through its lifecycle, publishing its heartbeats and timing
+/// the states it spends its time in
+///
+/// Only the transitions allowed by `State::can_transition_to` are made; a hook
+/// asking for any other fails the service.
+pub struct ServiceRunner<S: Service> {
+    service: S,
+    machine: StateMachine,
+    state_timer: StateTimer,
+    heartbeat: HeartbeatPublisher,
+}
+
+impl<S: Service> ServiceRunner<S> {
+    pub fn new(service: S) -> Self {
+        Self {
+            service,
+            machine: StateMachine::new(),
+            state_timer: StateTimer::default(),
+            heartbeat: HeartbeatPublisher::new(S::KIND, DEFAULT_HEARTBEAT_INTERVAL),
+        }
+    }
+
+    pub fn get_service(&self) -> &S {
+        &self.service
+    }
+    pub fn get_state(&self) -> &State {
+        self.machine.get_state()
+    }
+    /// Returns the transitions the service has made so far, oldest first
+    pub fn get_history(&self) -> &[Transition] {
+        self.machine.get_history()
+    }
+
+    /// Returns boolean indicating completion status of the service
+    pub fn check_completed(&self) -> bool {
+        matches!(self.get_state(), State::COMPLETED)
+    }
+    /// Returns option indicating whether the service has failed
+    /// ### Returns
+    /// - `None` if service is not in `FAILED` state
+    /// - `Some(message)` describing the failure if `FAILED`
+    pub fn check_failed(&self) -> Option<&str> {
+        if let State::FAILED(error_message) = self.get_state() {
+            return Some(error_message);
+        }
+        None
+    }
+
+    /// Asks the service to wind down and be cancelled over the following
+    /// cycles
+    pub fn cancel(&mut self) -> Result<(), String> {
+        let _span = self.service.span().entered();
+        self.machine.transition_to(State::SHUTTING_DOWN)?;
+        info!(state = self.get_state().name(), "State changed");
+        Ok(())
+    }
+
+    /// Work to be carried out each iteration of the service
+    pub fn cycle(&mut self) {
+        let previous = self.get_state().clone();
+        let next = {
+            let _span = self.service.span().entered();
+            match &previous {
+                State::CREATED => self.service.on_created(),
+                State::IDLE => self.service.on_idle(),
+                State::ISSUED => self.service.on_issued(),
+                State::EXECUTING => self.service.on_executing(),
+                State::PAUSED => self.service.on_paused(),
+                State::SHUTTING_DOWN => self.service.on_shutting_down(),
+                // nothing left to do once finished
+                State::COMPLETED | State::FAILED(_) | State::CANCELLED => Ok(previous.clone()),
+            }
+        };
+        // the hook may have moved on to a new span, e.g. for a new command
+        let _span = self.service.span().entered();
+        if let Err(reason) = next.and_then(|next| self.machine.transition_to(next)) {
+            self.machine.fail(reason);
+        }
+        if &previous != self.get_state() {
+            self.inject_fault();
+        }
+
+        // publish liveness, reflecting any state change made above
+        if let Err(e) = self.heartbeat.beat(&self.machine) {
+            self.machine.fail(e);
+        }
+        let state = self.machine.get_state();
+        if &previous != state {
+            match state {
+                State::COMPLETED => {
+                    info!(state = state.name(), "State changed");
+                    self.service.on_completed();
+                }
+                State::FAILED(reason) => {
+                    error!(state = state.name(), reason, "State changed");
+                    self.service.on_failed(reason);
+                }
+                State::CANCELLED => {
+                    warn!(state = state.name(), "State changed");
+                    self.service.on_cancelled();
+                }
+                state => info!(state = state.name(), "State changed"),
+            }
+        }
+        self.state_timer.update(self.machine.get_state());
+    }
+
+    /// Crashes or fails the service if the fault scenario asks for it on
+    /// entering its current state
+    fn inject_fault(&mut self) {
+        let state = self.machine.get_state();
+        let entry = self.machine.entries(state);
+        match faults::state_fault(S::KIND, state, entry) {
+            Some(StateFault::CRASH) => {
+                error!(state = state.name(), entry, "Injected fault, crashing");
+                std::process::exit(CRASH_EXIT_CODE);
+            }
+            Some(StateFault::FAIL) => {
+                let reason = format!("Injected fault on entering {}", state.name());
+                self.machine.fail(reason);
+            }
+            None => (),
+        }
+    }
+
+    /// Cycles the service until it completes, fails or is cancelled, then
+    /// shuts it down
+    ///
+    /// Returns the reason the service failed or was cancelled, if it was
+    pub fn run(&mut self) -> Result<(), String> {
+        while !self.get_state().is_terminal() {
+            self.cycle();
+        }
+        self.service.on_shutdown();
+        match self.get_state() {
+            State::FAILED(reason) => Err(reason.clone()),
+            State::CANCELLED => Err("Cancelled".to_string()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Test module for the service lifecycle runner
+#[cfg(test)]
+mod service_tests {
+    use crate::{
+        service::{Service, ServiceRunner},
+        state::State,
+    };
+
+    /// Service moving straight through its states, or skipping from created to
+    /// completed when `skip` is set
+    #[derive(Default)]
+    struct Toy {
+        skip: bool,
+        hooks: Vec<&'static str>,
+    }
+
+    impl Service for Toy {
+        const KIND: &'static str = "toy";
+
+        fn on_created(&mut self) -> Result<State, String> {
+            self.hooks.push("created");
+            Ok(if self.skip {
+                State::COMPLETED
+            } else {
+                State::ISSUED
+            })
+        }
+        fn on_issued(&mut self) -> Result<State, String> {
+            self.hooks.push("issued");
+            Ok(State::EXECUTING)
+        }
+        fn on_executing(&mut self) -> Result<State, String> {
+            self.hooks.push("executing");
+            Ok(State::COMPLETED)
+        }
+        fn on_completed(&mut self) {
+            self.hooks.push("completed");
+        }
+        fn on_failed(&mut self, _reason: &str) {
+            self.hooks.push("failed");
+        }
+        fn on_cancelled(&mut self) {
+            self.hooks.push("cancelled");
+        }
+        fn on_shutdown(&mut self) {
+            self.hooks.push("shutdown");
+        }
+    }
+
+    /// Checks a service is run through each hook in turn
+    #[test]
+    fn run_to_completion() {
+        let mut runner = ServiceRunner::new(Toy::default());
+        assert_eq!(runner.run(), Ok(()));
+        assert_eq!(
+            runner.get_service().hooks,
+            vec!["created", "issued", "executing", "completed", "shutdown"]
+        );
+    }
+
+    /// Checks skipping states is reported as a failure
+    #[test]
+    fn illegal_transition() {
+        let mut runner = ServiceRunner::new(Toy {
+            skip: true,
+            ..Toy::default()
+        });
+        let error = runner.run().unwrap_err();
+        assert_eq!(error, "Illegal state transition from CREATED to COMPLETED");
+        assert_eq!(
+            runner.get_service().hooks,
+            vec!["created", "failed", "shutdown"]
+        );
+    }
+
+    /// Checks a cancelled service winds down through `SHUTTING_DOWN`
+    #[test]
+    fn cancel() {
+        let mut runner = ServiceRunner::new(Toy::default());
+        runner.cycle();
+        runner.cancel().unwrap();
+        assert_eq!(runner.run(), Err("Cancelled".to_string()));
+        assert_eq!(
+            runner.get_service().hooks,
+            vec!["created", "cancelled", "shutdown"]
+        );
+        let states: Vec<&str> = runner
+            .get_history()
+            .iter()
+            .map(|transition| transition.get_to().name())
+            .collect();
+        assert_eq!(states, vec!["ISSUED", "SHUTTING_DOWN", "CANCELLED"]);
+        assert!(runner.cancel().is_err());
+    }
+}
@@ -0,0 +1,307 @@
+use std::{
+    cell::RefCell,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use serde_json::Value;
+
+use crate::{
+    clock,
+    faults::Rng,
+    service::{Service, ServiceRunner},
+    state::State,
+    units::format_duration,
+};
+
+/// Default least time a simulated service spends on each cycle, standing in
+/// for the time a real one spends polling its topics
+pub const DEFAULT_POLL: Duration = Duration::from_millis(50);
+
+thread_local! {
+    /// Bus standing in for DDS on this thread while a `Simulation` runs
+    static BUS: RefCell<Option<Arc<Mutex<Bus>>>> = const { RefCell::new(None) };
+}
+
+/// Bus senders and receivers created on this thread should use in place of
+/// DDS, if a simulation is running
+pub(crate) fn bus() -> Option<Arc<Mutex<Bus>>> {
+    BUS.with_borrow(Clone::clone)
+}
+
+/// A message published during a simulation, and when
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimMessage {
+    at: Duration,
+    topic: String,
+    version: u32,
+    body: Value,
+}
+
+impl SimMessage {
+    /// Time on the sender's clock the message was sent at
+    pub fn get_at(&self) -> Duration {
+        self.at
+    }
+    pub fn get_topic(&self) -> &str {
+        &self.topic
+    }
+    /// Protocol version the message was written in
+    pub fn get_version(&self) -> u32 {
+        self.version
+    }
+    /// The message, as JSON
+    pub fn get_body(&self) -> &Value {
+        &self.body
+    }
+}
+
+/// In-memory stand-in for DDS, delivering each message to every receiver
+/// subscribed to its topic once the receiver's clock reaches the time it was
+/// sent
+#[derive(Debug, Default)]
+pub(crate) struct Bus {
+    /// Topic of each subscriber, and indices in `log` of messages it has yet
+    /// to take
+    subscribers: Vec<(String, Vec<usize>)>,
+    log: Vec<SimMessage>,
+}
+
+impl Bus {
+    /// Subscribes to the given topic, returning the subscriber to take its
+    /// messages with
+    pub(crate) fn subscribe(&mut self, topic: &str) -> usize {
+        self.subscribers.push((topic.to_string(), Vec::new()));
+        self.subscribers.len() - 1
+    }
+
+    /// Sends a message, written in the given protocol version, on the given
+    /// topic at the given time
+    pub(crate) fn publish(&mut self, topic: &str, at: Duration, version: u32, body: Value) {
+        for (subscribed, pending) in &mut self.subscribers {
+            if subscribed == topic {
+                pending.push(self.log.len());
+            }
+        }
+        self.log.push(SimMessage {
+            at,
+            topic: topic.to_string(),
+            version,
+            body,
+        });
+    }
+
+    /// Takes the subscriber's earliest message sent by the given time, if any,
+    /// along with the protocol version it was written in
+    pub(crate) fn take(&mut self, subscriber: usize, now: Duration) -> Option<(u32, Value)> {
+        let pending = &mut self.subscribers[subscriber].1;
+        let (position, _) = pending
+            .iter()
+            .enumerate()
+            .filter(|(_, index)| self.log[**index].at <= now)
+            .min_by_key(|(_, index)| self.log[**index].at)?;
+        let index = pending.remove(position);
+        let msg = &self.log[index];
+        Some((msg.version, msg.body.clone()))
+    }
+}
+
+/// A service as stepped by a simulation, whatever its type
+trait Cycle {
+    fn cycle(&mut self);
+    fn get_state(&self) -> &State;
+}
+
+impl<S: Service> Cycle for ServiceRunner<S> {
+    fn cycle(&mut self) {
+        ServiceRunner::cycle(self)
+    }
+    fn get_state(&self) -> &State {
+        ServiceRunner::get_state(self)
+    }
+}
+
+/// A service taking part in a simulation, with its own clock
+struct Member {
+    runner: Box<dyn Cycle>,
+    clock: Duration,
+    /// Builds the service afresh once it finishes, if it is restarted
+    restart: Option<Box<dyn FnMut() -> Box<dyn Cycle>>>,
+}
+
+/// Runs services together within one thread, on an in-memory transport and
+/// a virtual clock, cycling them in an order fixed by a seed
+///
+/// Each service has its own clock, which moves on as the service sleeps and
+/// by at least `poll` each cycle. The service furthest behind is always
+/// cycled next, with ties broken from the seed, and only receives messages
+/// sent by then on its own clock. The same services given the same seed and
+/// inputs therefore always send the same messages in the same order, at the
+/// same simulated times.
+///
+/// Services, and any senders or receivers a test talks to them through, must
+/// be created on the same thread after the simulation. A service blocking
+/// within a hook, such as a prep chef waiting out a phase, only sees messages
+/// sent during the wait from its next cycle.
+pub struct Simulation {
+    start: Instant,
+    poll: Duration,
+    rng: Rng,
+    bus: Arc<Mutex<Bus>>,
+    members: Vec<Member>,
+}
+
+impl Simulation {
+    /// Starts a simulation on this thread, cycling services in an order
+    /// drawn from the given seed
+    pub fn new(seed: u64) -> Self {
+        let start = Instant::now();
+        let bus = Arc::new(Mutex::new(Bus::default()));
+        BUS.set(Some(bus.clone()));
+        clock::set_virtual(start, Duration::ZERO);
+        Self {
+            start,
+            poll: DEFAULT_POLL,
+            rng: Rng(seed),
+            bus,
+            members: Vec::new(),
+        }
+    }
+
+    /// Sets the least time a service spends on each cycle
+    pub fn with_poll(mut self, poll: Duration) -> Self {
+        self.poll = poll;
+        self
+    }
+
+    /// Adds a service, starting now, returning its index
+    pub fn add<S: Service + 'static>(&mut self, service: S) -> usize {
+        self.add_member(Box::new(ServiceRunner::new(service)), None)
+    }
+
+    /// Adds a service built by `build`, starting now, which is built afresh
+    /// each time it finishes, as a supervisor restarting its process would.
+    /// Returns its index.
+    pub fn add_restarting<S: Service + 'static>(
+        &mut self,
+        mut build: impl FnMut() -> S + 'static,
+    ) -> usize {
+        let runner = Box::new(ServiceRunner::new(build()));
+        let restart = move || Box::new(ServiceRunner::new(build())) as Box<dyn Cycle>;
+        self.add_member(runner, Some(Box::new(restart)))
+    }
+
+    fn add_member(
+        &mut self,
+        runner: Box<dyn Cycle>,
+        restart: Option<Box<dyn FnMut() -> Box<dyn Cycle>>>,
+    ) -> usize {
+        self.members.push(Member {
+            runner,
+            clock: self.now(),
+            restart,
+        });
+        self.members.len() - 1
+    }
+
+    /// State of the service with the given index
+    pub fn get_state(&self, member: usize) -> &State {
+        self.members[member].runner.get_state()
+    }
+
+    /// Every message published so far, in the order they were sent
+    pub fn get_messages(&self) -> Vec<SimMessage> {
+        self.bus.lock().unwrap().log.clone()
+    }
+
+    /// Time on the clock of the service furthest behind, or of the last to
+    /// finish once every service has
+    pub fn now(&self) -> Duration {
+        let running = self
+            .members
+            .iter()
+            .filter(|member| !member.runner.get_state().is_terminal());
+        match running.map(|member| member.clock).min() {
+            Some(clock) => clock,
+            None => self
+                .members
+                .iter()
+                .map(|member| member.clock)
+                .max()
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Cycles the service furthest behind, returning whether any are still
+    /// running
+    pub fn step(&mut self) -> bool {
+        let now = self.now();
+        let behind: Vec<usize> = (0..self.members.len())
+            .filter(|i| {
+                let member = &self.members[*i];
+                member.clock == now && !member.runner.get_state().is_terminal()
+            })
+            .collect();
+        if behind.is_empty() {
+            return false;
+        }
+        let index = behind[(self.rng.next_f64() * behind.len() as f64) as usize];
+        let poll = self.poll;
+        let member = &mut self.members[index];
+
+        clock::set_virtual(self.start, member.clock);
+        member.runner.cycle();
+        let slept = clock::virtual_elapsed().unwrap_or(member.clock);
+        member.clock = slept.max(member.clock + poll);
+        if member.runner.get_state().is_terminal() {
+            if let Some(restart) = &mut member.restart {
+                clock::set_virtual(self.start, member.clock);
+                member.runner = restart();
+            }
+        }
+        // anything sent between steps is sent at the simulation's time
+        clock::set_virtual(self.start, self.now());
+        true
+    }
+
+    /// Cycles the services until every one not restarted has finished,
+    /// returning the time the last finished at
+    ///
+    /// Fails if any are still running once the given time has passed.
+    pub fn run(&mut self, limit: Duration) -> Result<Duration, String> {
+        while self
+            .members
+            .iter()
+            .any(|member| member.restart.is_none() && !member.runner.get_state().is_terminal())
+        {
+            if self.now() > limit {
+                return Err(format!(
+                    "Simulation still running after {}",
+                    format_duration(limit)
+                ));
+            }
+            self.step();
+        }
+        Ok(self
+            .members
+            .iter()
+            .filter(|member| member.restart.is_none())
+            .map(|member| member.clock)
+            .max()
+            .unwrap_or_default())
+    }
+
+    /// Cycles the services until the given time has passed, or all have
+    /// finished
+    pub fn run_for(&mut self, duration: Duration) {
+        while self.now() < duration && self.step() {}
+    }
+}
+
+impl Drop for Simulation {
+    /// Returns this thread to DDS and the real clock
+    fn drop(&mut self) {
+        BUS.set(None);
+        clock::clear_virtual();
+    }
+}
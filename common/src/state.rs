@@ -1,9 +1,244 @@
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::clock;
+
 /// Defines the states a control service transitions through during execution
-#[derive(Debug, Clone, PartialEq)]
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum State {
     CREATED,
+    IDLE,
     ISSUED,
     EXECUTING,
+    PAUSED,
+    SHUTTING_DOWN,
     COMPLETED,
     FAILED(String),
+    CANCELLED,
+}
+
+impl State {
+    /// Name of the state, excluding any failure message
+    pub fn name(&self) -> &'static str {
+        match self {
+            State::CREATED => "CREATED",
+            State::IDLE => "IDLE",
+            State::ISSUED => "ISSUED",
+            State::EXECUTING => "EXECUTING",
+            State::PAUSED => "PAUSED",
+            State::SHUTTING_DOWN => "SHUTTING_DOWN",
+            State::COMPLETED => "COMPLETED",
+            State::FAILED(_) => "FAILED",
+            State::CANCELLED => "CANCELLED",
+        }
+    }
+
+    /// Whether the service has finished, leaving no further transitions
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, State::COMPLETED | State::FAILED(_) | State::CANCELLED)
+    }
+
+    /// Whether a service may move from this state to the given one
+    ///
+    /// Services may always stay in their state. Otherwise they move along the
+    /// table below, and may fail, be cancelled or begin shutting down from any
+    /// state until they have finished.
+    pub fn can_transition_to(&self, next: &State) -> bool {
+        if self == next {
+            return true;
+        }
+        match (self, next) {
+            (from, _) if from.is_terminal() => false,
+            (_, State::FAILED(_) | State::CANCELLED) => true,
+            (State::SHUTTING_DOWN, _) => false,
+            (_, State::SHUTTING_DOWN) => true,
+            (State::CREATED, State::IDLE | State::ISSUED)
+            | (State::IDLE, State::ISSUED)
+            | (State::ISSUED, State::EXECUTING)
+            | (State::EXECUTING, State::IDLE | State::PAUSED | State::COMPLETED)
+            | (State::PAUSED, State::IDLE | State::EXECUTING) => true,
+            _ => false,
+        }
+    }
+}
+
+/// A change in a service's state, and when it was made
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Transition {
+    from: State,
+    to: State,
+    at: SystemTime,
+}
+impl Transition {
+    pub fn get_from(&self) -> &State {
+        &self.from
+    }
+    pub fn get_to(&self) -> &State {
+        &self.to
+    }
+    pub fn get_at(&self) -> SystemTime {
+        self.at
+    }
+}
+
+/// Most recent transitions kept in a state machine's history, and so sent in
+/// each heartbeat
+pub const HISTORY_CAPACITY: usize = 32;
+
+/// Holds a service's state, only allowing the transitions permitted by
+/// `State::can_transition_to` and keeping a history of the latest made
+#[derive(Debug, Clone)]
+pub struct StateMachine {
+    state: State,
+    history: Vec<Transition>,
+    /// Times each state has been entered, by name
+    entries: Vec<(&'static str, usize)>,
+}
+
+impl Default for StateMachine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StateMachine {
+    /// Creates a state machine in the `CREATED` state
+    pub fn new() -> Self {
+        Self {
+            state: State::CREATED,
+            history: Vec::new(),
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn get_state(&self) -> &State {
+        &self.state
+    }
+    /// Returns up to the last `HISTORY_CAPACITY` transitions made, oldest
+    /// first
+    pub fn get_history(&self) -> &[Transition] {
+        &self.history
+    }
+
+    /// Number of times the given state has been entered
+    pub fn entries(&self, state: &State) -> usize {
+        self.entries
+            .iter()
+            .find(|(name, _)| *name == state.name())
+            .map_or(0, |(_, count)| *count)
+    }
+
+    /// Moves to the given state, recording the transition unless the state is
+    /// unchanged
+    pub fn transition_to(&mut self, next: State) -> Result<(), String> {
+        if !self.state.can_transition_to(&next) {
+            return Err(format!(
+                "Illegal state transition from {} to {}",
+                self.state.name(),
+                next.name()
+            ));
+        }
+        if next != self.state {
+            let from = std::mem::replace(&mut self.state, next.clone());
+            match self
+                .entries
+                .iter_mut()
+                .find(|(name, _)| *name == next.name())
+            {
+                Some((_, count)) => *count += 1,
+                None => self.entries.push((next.name(), 1)),
+            }
+            if self.history.len() == HISTORY_CAPACITY {
+                self.history.remove(0);
+            }
+            self.history.push(Transition {
+                from,
+                to: next,
+                at: clock::system_now(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Fails with the given reason, unless already finished
+    pub fn fail(&mut self, reason: String) {
+        if !self.state.is_terminal() {
+            // failing is allowed from every state which hasn't finished
+            let _ = self.transition_to(State::FAILED(reason));
+        }
+    }
+}
+
+/// Test module for service states
+#[cfg(test)]
+mod state_tests {
+    use crate::state::{State, StateMachine, HISTORY_CAPACITY};
+
+    /// Checks legal transitions are recorded and illegal ones rejected
+    #[test]
+    fn transition_history() {
+        let mut machine = StateMachine::new();
+        for next in [State::IDLE, State::IDLE, State::ISSUED, State::EXECUTING] {
+            machine.transition_to(next).unwrap();
+        }
+        machine.transition_to(State::PAUSED).unwrap();
+        machine.transition_to(State::EXECUTING).unwrap();
+        machine.transition_to(State::COMPLETED).unwrap();
+        assert_eq!(
+            machine.transition_to(State::EXECUTING),
+            Err("Illegal state transition from COMPLETED to EXECUTING".to_string())
+        );
+        machine.fail("burnt".to_string());
+        assert_eq!(machine.get_state(), &State::COMPLETED);
+
+        let moves: Vec<(&str, &str)> = machine
+            .get_history()
+            .iter()
+            .map(|transition| (transition.get_from().name(), transition.get_to().name()))
+            .collect();
+        assert_eq!(
+            moves,
+            vec![
+                ("CREATED", "IDLE"),
+                ("IDLE", "ISSUED"),
+                ("ISSUED", "EXECUTING"),
+                ("EXECUTING", "PAUSED"),
+                ("PAUSED", "EXECUTING"),
+                ("EXECUTING", "COMPLETED"),
+            ]
+        );
+        let times: Vec<_> = machine.get_history().iter().map(|t| t.get_at()).collect();
+        assert!(times.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+
+    /// Checks only the latest transitions are kept, while entries into each
+    /// state are still all counted
+    #[test]
+    fn history_capacity() {
+        let mut machine = StateMachine::new();
+        machine.transition_to(State::IDLE).unwrap();
+        for _ in 0..HISTORY_CAPACITY {
+            machine.transition_to(State::ISSUED).unwrap();
+            machine.transition_to(State::EXECUTING).unwrap();
+            machine.transition_to(State::IDLE).unwrap();
+        }
+        assert_eq!(machine.get_history().len(), HISTORY_CAPACITY);
+        assert_eq!(machine.get_history().last().unwrap().get_to(), &State::IDLE);
+        assert_eq!(machine.entries(&State::IDLE), HISTORY_CAPACITY + 1);
+        assert_eq!(machine.entries(&State::EXECUTING), HISTORY_CAPACITY);
+        assert_eq!(machine.entries(&State::PAUSED), 0);
+    }
+
+    /// Checks services may only shut down into being cancelled or failing
+    #[test]
+    fn shutting_down() {
+        assert!(State::PAUSED.can_transition_to(&State::SHUTTING_DOWN));
+        assert!(State::SHUTTING_DOWN.can_transition_to(&State::CANCELLED));
+        assert!(!State::SHUTTING_DOWN.can_transition_to(&State::EXECUTING));
+        assert!(!State::CANCELLED.can_transition_to(&State::SHUTTING_DOWN));
+        assert!(!State::CREATED.can_transition_to(&State::COMPLETED));
+        assert!(State::EXECUTING.can_transition_to(&State::IDLE));
+    }
 }
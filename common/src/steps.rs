@@ -1,22 +1,240 @@
-use std::{fmt::Display, time::Duration};
+use std::{borrow::Cow, fmt::Display, str::FromStr, time::Duration};
 
-use serde::{Deserialize, Serialize};
+use schemars::{json_schema, JsonSchema, Schema, SchemaGenerator};
+use serde::{de::Error, ser::SerializeMap, Deserialize, Deserializer, Serialize, Serializer};
+use serde_yaml::{Mapping, Value};
+
+use crate::units::{format_duration, parse_duration, FriendlyDuration, Temperature};
 
 /// Represents possible steps in a cooking recipe
-#[derive(Debug, Serialize, Deserialize)]
+///
+/// Steps are written in recipe files in a readable map form, such as
+/// `prepare: salmon` or `cook: { item: salmon, for: 12m, at: 215C }`. The
+/// older tagged form, e.g. `!Cook [SALMON, {secs: 720, nanos: 0}]`, is still
+/// read.
+#[derive(Debug, Clone, PartialEq)]
 pub enum Step {
     Prepare(FoodItem),
-    /// Cook given food item for given time
-    Cook(FoodItem, Duration),
+    /// Cook given food item for given time, optionally at a given temperature
+    Cook(FoodItem, Duration, Option<Temperature>),
+}
+
+impl Step {
+    /// Short name for the kind of step, independent of its parameters
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Step::Prepare(_) => "prepare",
+            Step::Cook(_, _, _) => "cook",
+        }
+    }
+
+    /// Food item the step acts on
+    pub fn item(&self) -> FoodItem {
+        match self {
+            Step::Prepare(item) | Step::Cook(item, _, _) => *item,
+        }
+    }
+
+    /// Reads a step from any of the forms it may be written in
+    fn from_value(value: Value) -> Result<Self, String> {
+        // tagged and map forms both name the kind of step then give its body
+        let (kind, body) = match value {
+            Value::Tagged(tagged) => (tagged.tag.to_string(), tagged.value),
+            Value::Mapping(mapping) if mapping.len() == 1 => match mapping.into_iter().next() {
+                Some((Value::String(kind), body)) => (kind, body),
+                _ => return Err("Step kinds must be strings".to_string()),
+            },
+            other => return Err(format!("Expected a step but found: {:?}", other)),
+        };
+        match kind.trim_start_matches('!').to_lowercase().as_str() {
+            "prepare" => match body {
+                Value::Mapping(mut fields) => Ok(Step::Prepare(item_field(&mut fields)?)),
+                item => Ok(Step::Prepare(item_value(item)?)),
+            },
+            "cook" => match body {
+                Value::Mapping(mut fields) => {
+                    let item = item_field(&mut fields)?;
+                    let time = fields
+                        .remove("for")
+                        .ok_or_else(|| "Cook steps need a time to cook `for`".to_string())?;
+                    let temperature = fields
+                        .remove("at")
+                        .map(|at| serde_yaml::from_value(at).map_err(|e| e.to_string()))
+                        .transpose()?;
+                    no_extra_fields(&fields)?;
+                    Ok(Step::Cook(item, duration_value(time)?, temperature))
+                }
+                Value::Sequence(values) if values.len() == 2 => {
+                    let mut values = values.into_iter();
+                    let item = item_value(values.next().unwrap_or_default())?;
+                    let time = duration_value(values.next().unwrap_or_default())?;
+                    Ok(Step::Cook(item, time, None))
+                }
+                _ => Err("Cook steps need an item and a time to cook for".to_string()),
+            },
+            _ => Err(format!("Unknown kind of step: {}", kind)),
+        }
+    }
+}
+
+/// Short human readable description of a step
+impl Display for Step {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Step::Prepare(item) => write!(f, "Prepare {}", item),
+            Step::Cook(item, time, temperature) => {
+                write!(f, "Cook {} for {}", item, format_duration(*time))?;
+                if let Some(temperature) = temperature {
+                    write!(f, " at {}", temperature)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Reads a food item given by name
+fn item_value(value: Value) -> Result<FoodItem, String> {
+    match value {
+        Value::String(name) => name.parse(),
+        other => Err(format!("Expected a food item but found: {:?}", other)),
+    }
+}
+
+/// Takes the food item out of the fields of a step written as a map
+fn item_field(fields: &mut Mapping) -> Result<FoodItem, String> {
+    let item = fields
+        .remove("item")
+        .ok_or_else(|| "Steps need an `item` to act on".to_string())?;
+    item_value(item)
+}
+
+/// Checks every field of a step written as a map has been used
+fn no_extra_fields(fields: &Mapping) -> Result<(), String> {
+    match fields.keys().next() {
+        Some(field) => Err(format!("Unknown step field: {:?}", field)),
+        None => Ok(()),
+    }
+}
+
+/// Reads a duration written as a friendly string such as `12m30s`, a number
+/// of seconds, or the `secs` and `nanos` of a serialized `Duration`
+fn duration_value(value: Value) -> Result<Duration, String> {
+    match value {
+        Value::String(s) => parse_duration(&s),
+        Value::Number(secs) => secs
+            .as_f64()
+            .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+            .ok_or_else(|| format!("Invalid duration: {}", secs)),
+        other => serde_yaml::from_value(other).map_err(|e| e.to_string()),
+    }
+}
+
+impl Serialize for Step {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(1))?;
+        match self {
+            Step::Prepare(item) => map.serialize_entry("prepare", &item.name())?,
+            Step::Cook(item, time, temperature) => {
+                let mut fields = Mapping::new();
+                fields.insert("item".into(), item.name().into());
+                fields.insert("for".into(), format_duration(*time).into());
+                if let Some(temperature) = temperature {
+                    fields.insert("at".into(), temperature.to_string().into());
+                }
+                map.serialize_entry("cook", &fields)?
+            }
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Step {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // every form is read through the generic document model
+        Step::from_value(Value::deserialize(deserializer)?).map_err(D::Error::custom)
+    }
+}
+
+/// Schema of the readable form steps are written in
+impl JsonSchema for Step {
+    fn schema_name() -> Cow<'static, str> {
+        "Step".into()
+    }
+
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        // recipe parameters may stand in for any value, or be interpolated
+        // into one such as `${minutes}m`
+        let param = json_schema!({ "type": "string", "pattern": "^\\$|\\$\\{" });
+        let item = json_schema!({ "anyOf": [generator.subschema_for::<FoodItem>(), param] });
+        let duration = json_schema!({
+            "anyOf": [generator.subschema_for::<FriendlyDuration>(), param]
+        });
+        let temperature = json_schema!({
+            "anyOf": [generator.subschema_for::<Temperature>(), param]
+        });
+        json_schema!({
+            "oneOf": [
+                {
+                    "type": "object",
+                    "properties": {
+                        "prepare": {
+                            "anyOf": [
+                                item,
+                                {
+                                    "type": "object",
+                                    "properties": { "item": item },
+                                    "required": ["item"],
+                                    "additionalProperties": false
+                                }
+                            ]
+                        }
+                    },
+                    "required": ["prepare"],
+                    "additionalProperties": false
+                },
+                {
+                    "type": "object",
+                    "properties": {
+                        "cook": {
+                            "type": "object",
+                            "properties": {
+                                "item": item,
+                                "for": duration,
+                                "at": temperature
+                            },
+                            "required": ["item", "for"],
+                            "additionalProperties": false
+                        }
+                    },
+                    "required": ["cook"],
+                    "additionalProperties": false
+                }
+            ]
+        })
+    }
 }
 
 /// Describes the space of possible food items that may make up a recipe
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum FoodItem {
     CHICKEN,
     SALMON,
 }
 
+impl FoodItem {
+    /// Every food item
+    pub const ALL: [FoodItem; 2] = [FoodItem::CHICKEN, FoodItem::SALMON];
+
+    /// Name of the item as written in recipe files
+    pub fn name(&self) -> &'static str {
+        match self {
+            FoodItem::CHICKEN => "chicken",
+            FoodItem::SALMON => "salmon",
+        }
+    }
+}
+
 impl Display for FoodItem {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -25,3 +243,92 @@ impl Display for FoodItem {
         }
     }
 }
+
+/// Schema of the names food items are written by in recipe files
+impl JsonSchema for FoodItem {
+    fn schema_name() -> Cow<'static, str> {
+        "FoodItem".into()
+    }
+
+    fn json_schema(_generator: &mut SchemaGenerator) -> Schema {
+        let names: Vec<&str> = FoodItem::ALL.iter().map(|item| item.name()).collect();
+        json_schema!({ "type": "string", "enum": names })
+    }
+}
+
+impl FromStr for FoodItem {
+    type Err = String;
+
+    /// Parses a food item from its name, ignoring case
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "chicken" => Ok(FoodItem::CHICKEN),
+            "salmon" => Ok(FoodItem::SALMON),
+            _ => Err(format!("Unknown food item: {}", s)),
+        }
+    }
+}
+
+/// Serde helpers writing food items in recipe files by their friendly name,
+/// for use with `#[serde(with = "item_name")]`. Messages keep the derived form.
+pub(crate) mod item_name {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    use crate::steps::FoodItem;
+
+    pub fn serialize<S: Serializer>(item: &FoodItem, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(item.name())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<FoodItem, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(D::Error::custom)
+    }
+}
+
+/// Test module for recipe steps
+#[cfg(test)]
+mod steps_tests {
+    use std::time::Duration;
+
+    use crate::{
+        steps::{FoodItem, Step},
+        units::{Temperature, TemperatureScale},
+    };
+
+    /// Checks every way of writing a step is read, and the friendly form is
+    /// written back
+    #[test]
+    fn step_forms() {
+        let cook = Step::Cook(
+            FoodItem::SALMON,
+            Duration::from_secs(750),
+            Some(Temperature::new(215.0, TemperatureScale::CELSIUS)),
+        );
+        let friendly = "cook:\n  item: salmon\n  for: 12m30s\n  at: 215C\n";
+        assert_eq!(serde_yaml::from_str::<Step>(friendly).unwrap(), cook);
+        assert_eq!(serde_yaml::to_string(&cook).unwrap(), friendly);
+
+        let untimed = Step::Cook(FoodItem::SALMON, Duration::from_secs(10), None);
+        for step in [
+            "!Cook [SALMON, {secs: 10, nanos: 0}]",
+            "Cook: [SALMON, 10]",
+            "cook: {item: Salmon, for: 10s}",
+        ] {
+            assert_eq!(
+                serde_yaml::from_str::<Step>(step).unwrap(),
+                untimed,
+                "{}",
+                step
+            );
+        }
+        assert_eq!(
+            serde_yaml::from_str::<Step>("prepare: chicken").unwrap(),
+            Step::Prepare(FoodItem::CHICKEN)
+        );
+        assert!(serde_yaml::from_str::<Step>("cook: {item: salmon}").is_err());
+        assert!(serde_yaml::from_str::<Step>("cook: {item: salmon, for: 1m, on: pan}").is_err());
+        assert!(serde_yaml::from_str::<Step>("fry: salmon").is_err());
+    }
+}
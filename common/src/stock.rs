@@ -0,0 +1,253 @@
+use std::{fmt::Display, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ingredients::{Quantity, Unit},
+    recipe::Recipe,
+    steps::FoodItem,
+};
+
+/// An amount of a food item, as kept in stock or needed by a run
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct StockLevel {
+    #[serde(with = "crate::steps::item_name")]
+    item: FoodItem,
+    quantity: Quantity,
+}
+
+impl StockLevel {
+    pub fn new(item: FoodItem, quantity: Quantity) -> Self {
+        Self { item, quantity }
+    }
+    pub fn get_item(&self) -> FoodItem {
+        self.item
+    }
+    pub fn get_quantity(&self) -> Quantity {
+        self.quantity
+    }
+
+    /// Levels of every ingredient the given recipe uses
+    pub fn for_recipe(recipe: &Recipe) -> Vec<Self> {
+        recipe
+            .get_ingredients()
+            .iter()
+            .map(|ingredient| Self::new(ingredient.get_item(), ingredient.get_quantity()))
+            .collect()
+    }
+}
+
+/// A food item there is not enough of in stock, with how much is needed and
+/// how much is free to use
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Shortfall {
+    item: FoodItem,
+    needed: Quantity,
+    available: f64,
+}
+
+impl Shortfall {
+    pub fn get_item(&self) -> FoodItem {
+        self.item
+    }
+    pub fn get_needed(&self) -> Quantity {
+        self.needed
+    }
+    /// Amount free to use, in the needed quantity's unit
+    pub fn get_available(&self) -> Quantity {
+        Quantity::new(self.available, self.needed.get_unit())
+    }
+}
+
+impl Display for Shortfall {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} (needs {}, {} available)",
+            self.item,
+            self.needed,
+            self.get_available()
+        )
+    }
+}
+
+/// Food items kept in the pantry, and the amounts of them reserved by runs
+///
+/// Quantities are only compared in the same unit, so an item stocked in grams
+/// cannot be used by a recipe measuring it in pieces.
+#[derive(Debug, Default)]
+pub struct Stock {
+    on_hand: Vec<StockLevel>,
+    reservations: Vec<(u32, Vec<StockLevel>)>,
+}
+
+impl Stock {
+    pub fn new(on_hand: Vec<StockLevel>) -> Self {
+        Self {
+            on_hand,
+            reservations: Vec::new(),
+        }
+    }
+
+    /// Reads the stock kept on hand from the YAML list of levels at the given
+    /// path
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+        serde_yaml::from_str(&contents)
+            .map(Self::new)
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn get_on_hand(&self) -> &[StockLevel] {
+        &self.on_hand
+    }
+
+    /// Amount of the item in the given unit on hand and not reserved by any
+    /// run
+    pub fn available(&self, item: FoodItem, unit: Unit) -> f64 {
+        let total = |levels: &[StockLevel]| -> f64 {
+            levels
+                .iter()
+                .filter(|level| level.item == item && level.quantity.get_unit() == unit)
+                .map(|level| level.quantity.get_amount())
+                .sum()
+        };
+        let reserved: f64 = self
+            .reservations
+            .iter()
+            .map(|(_, levels)| total(levels))
+            .sum();
+        total(&self.on_hand) - reserved
+    }
+
+    /// Items of which there is not enough free to use for the given levels,
+    /// counting every level of the same item and unit together
+    pub fn shortfalls(&self, needed: &[StockLevel]) -> Vec<Shortfall> {
+        let mut shortfalls: Vec<Shortfall> = Vec::new();
+        for level in needed {
+            let unit = level.quantity.get_unit();
+            match shortfalls
+                .iter_mut()
+                .find(|s| s.item == level.item && s.needed.get_unit() == unit)
+            {
+                Some(shortfall) => {
+                    shortfall.needed = Quantity::new(
+                        shortfall.needed.get_amount() + level.quantity.get_amount(),
+                        unit,
+                    )
+                }
+                None => shortfalls.push(Shortfall {
+                    item: level.item,
+                    needed: level.quantity,
+                    available: self.available(level.item, unit).max(0.0),
+                }),
+            }
+        }
+        shortfalls.retain(|s| s.needed.get_amount() > s.available);
+        shortfalls
+    }
+
+    /// Sets aside the given levels for a run, unless there is not enough of
+    /// any of them, in which case nothing is reserved and the shortfalls are
+    /// returned
+    ///
+    /// Reserving again for a run already holding a reservation keeps the
+    /// original one.
+    pub fn reserve(&mut self, run_id: u32, needed: &[StockLevel]) -> Result<(), Vec<Shortfall>> {
+        if self.reservations.iter().any(|(id, _)| *id == run_id) {
+            return Ok(());
+        }
+        let shortfalls = self.shortfalls(needed);
+        if !shortfalls.is_empty() {
+            return Err(shortfalls);
+        }
+        self.reservations.push((run_id, needed.to_vec()));
+        Ok(())
+    }
+
+    /// Takes the run's reserved levels out of stock, returning whether it had
+    /// a reservation
+    pub fn consume(&mut self, run_id: u32) -> bool {
+        let Some(reserved) = self.take_reservation(run_id) else {
+            return false;
+        };
+        for used in reserved {
+            let mut amount = used.quantity.get_amount();
+            for level in self.on_hand.iter_mut().filter(|level| {
+                level.item == used.item && level.quantity.get_unit() == used.quantity.get_unit()
+            }) {
+                let taken = amount.min(level.quantity.get_amount());
+                level.quantity = Quantity::new(
+                    level.quantity.get_amount() - taken,
+                    level.quantity.get_unit(),
+                );
+                amount -= taken;
+            }
+        }
+        true
+    }
+
+    /// Returns the run's reserved levels to stock unused, returning whether it
+    /// had a reservation
+    pub fn release(&mut self, run_id: u32) -> bool {
+        self.take_reservation(run_id).is_some()
+    }
+
+    /// Removes and returns the run's reservation, if it has one
+    fn take_reservation(&mut self, run_id: u32) -> Option<Vec<StockLevel>> {
+        let index = self.reservations.iter().position(|(id, _)| *id == run_id)?;
+        Some(self.reservations.remove(index).1)
+    }
+}
+
+/// Test module for pantry stock
+#[cfg(test)]
+mod stock_tests {
+    use crate::{
+        ingredients::{Quantity, Unit},
+        steps::FoodItem,
+        stock::{Stock, StockLevel},
+    };
+
+    fn grams(item: FoodItem, amount: f64) -> StockLevel {
+        StockLevel::new(item, Quantity::new(amount, Unit::GRAMS))
+    }
+
+    /// Checks reservations hold stock back from other runs until consumed or
+    /// released
+    #[test]
+    fn reserve_consume_release() {
+        let mut stock = Stock::new(vec![grams(FoodItem::SALMON, 500.0)]);
+        stock.reserve(1, &[grams(FoodItem::SALMON, 300.0)]).unwrap();
+
+        let shortfalls = stock
+            .reserve(2, &[grams(FoodItem::SALMON, 300.0)])
+            .unwrap_err();
+        assert_eq!(
+            shortfalls[0].to_string(),
+            "Salmon (needs 300 g, 200 g available)"
+        );
+
+        assert!(stock.release(1));
+        assert!(!stock.release(1));
+        stock.reserve(2, &[grams(FoodItem::SALMON, 300.0)]).unwrap();
+        assert!(stock.consume(2));
+        assert_eq!(stock.available(FoodItem::SALMON, Unit::GRAMS), 200.0);
+    }
+
+    /// Checks levels of the same item are needed together, and items are only
+    /// counted in the unit they are stocked in
+    #[test]
+    fn shortfalls() {
+        let stock = Stock::new(vec![grams(FoodItem::CHICKEN, 400.0)]);
+        let shortfalls = stock.shortfalls(&[
+            grams(FoodItem::CHICKEN, 250.0),
+            grams(FoodItem::CHICKEN, 250.0),
+            StockLevel::new(FoodItem::CHICKEN, Quantity::new(1.0, Unit::PIECES)),
+        ]);
+        assert_eq!(shortfalls.len(), 2);
+        assert_eq!(shortfalls[0].get_needed().get_amount(), 500.0);
+        assert_eq!(shortfalls[1].get_available().get_amount(), 0.0);
+    }
+}
@@ -0,0 +1,89 @@
+use std::time::Duration;
+
+use proptest::{option, prelude::*, sample::select};
+
+use crate::{
+    ingredients::{Ingredient, Quantity, Unit, MAX_BATCHES},
+    recipe::Recipe,
+    steps::{FoodItem, Step},
+    units::{Temperature, TemperatureScale},
+};
+
+/// Any food item
+pub fn food_item() -> impl Strategy<Value = FoodItem> {
+    select(FoodItem::ALL.to_vec())
+}
+
+/// Any temperature from freezing to a hot oven, in either scale
+pub fn temperature() -> impl Strategy<Value = Temperature> {
+    prop_oneof![
+        (0.0..300.0).prop_map(|degrees| Temperature::new(degrees, TemperatureScale::CELSIUS)),
+        (32.0..570.0).prop_map(|degrees| Temperature::new(degrees, TemperatureScale::FAHRENHEIT)),
+    ]
+}
+
+/// Any step, cooking for up to the given time. Cook times are whole
+/// milliseconds, as written in recipe files.
+pub fn step(max_cook_time: Duration) -> impl Strategy<Value = Step> {
+    prop_oneof![
+        food_item().prop_map(Step::Prepare),
+        (
+            food_item(),
+            (0..=max_cook_time.as_millis() as u64).prop_map(Duration::from_millis),
+            option::of(temperature()),
+        )
+            .prop_map(|(item, time, temperature)| Step::Cook(item, time, temperature)),
+    ]
+}
+
+/// Any valid ingredient, with or without a batch capacity
+pub fn ingredient() -> impl Strategy<Value = Ingredient> {
+    let unit = prop_oneof![
+        Just(Unit::GRAMS),
+        Just(Unit::MILLILITRES),
+        Just(Unit::PIECES)
+    ];
+    (
+        food_item(),
+        0.1..5000.0,
+        unit,
+        option::of(1.0..5000.0f64),
+        0.0..2.0,
+    )
+        .prop_map(|(item, amount, unit, capacity, exponent)| {
+            let ingredient = Ingredient::new(item, Quantity::new(amount, unit))
+                .with_cook_time_exponent(exponent);
+            match capacity {
+                // leaving no more batches than the kitchen takes
+                Some(capacity) => {
+                    ingredient.with_batch_capacity(capacity.max(amount / MAX_BATCHES as f64))
+                }
+                None => ingredient,
+            }
+        })
+}
+
+/// Any recipe with the given range of numbers of steps, each cooking for up
+/// to the given time
+pub fn recipe(
+    steps: std::ops::Range<usize>,
+    max_cook_time: Duration,
+) -> impl Strategy<Value = Recipe> {
+    (
+        "\\PC{0,24}",
+        "\\PC{0,48}",
+        option::of("[0-9]{1,2}\\.[0-9]{1,2}"),
+        1..12u32,
+        prop::collection::vec(ingredient(), 0..3),
+        prop::collection::vec(step(max_cook_time), steps),
+    )
+        .prop_map(
+            |(title, description, version, servings, ingredients, steps)| {
+                let recipe = Recipe::new(title, description, servings, ingredients, steps);
+                match version {
+                    Some(version) => recipe.with_version(version),
+                    None => recipe,
+                }
+            },
+        )
+}
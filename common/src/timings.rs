@@ -0,0 +1,72 @@
+use std::time::Duration;
+
+use crate::{
+    appliance::{target_celsius, Appliance, ApplianceKind},
+    steps::{FoodItem, Step},
+    units::Temperature,
+};
+
+/// Time spent gathering prep materials for the given item
+pub fn gathering_time(item: FoodItem) -> Duration {
+    match item {
+        FoodItem::CHICKEN => Duration::from_secs(2),
+        FoodItem::SALMON => Duration::from_secs(3),
+    }
+}
+
+/// Time spent preparing the given item once materials are gathered
+pub fn preparing_time(item: FoodItem) -> Duration {
+    match item {
+        FoodItem::CHICKEN => Duration::from_secs(5),
+        FoodItem::SALMON => Duration::from_secs(3),
+    }
+}
+
+/// Time spent heating the appliance used to cook the given item to the given
+/// temperature, from however hot it is among the given appliances, or from
+/// cold if there are none of its kind. Fails if the temperature can't be
+/// reached.
+pub fn preheating_time(
+    appliances: &[Appliance],
+    item: FoodItem,
+    temperature: Option<Temperature>,
+) -> Result<Duration, String> {
+    let kind = ApplianceKind::for_item(item);
+    let celsius = target_celsius(temperature);
+    match appliances
+        .iter()
+        .find(|appliance| appliance.get_kind() == kind)
+    {
+        Some(appliance) => appliance.heating_time(celsius),
+        None => Appliance::default_for(kind).heating_time(celsius),
+    }
+}
+
+/// Time a worker chef takes to carry out the given step, from receiving its
+/// command to reporting it done, on appliances as hot as the steps before
+/// left them. The appliances are moved on past the step, heating the one it
+/// cooks on and cooling the rest. Fails if the step can't be carried out.
+pub fn step_time(step: &Step, appliances: &mut [Appliance]) -> Result<Duration, String> {
+    let (duration, cooked_on) = match step {
+        Step::Prepare(item) => (gathering_time(*item) + preparing_time(*item), None),
+        Step::Cook(item, time, temperature) => {
+            let duration = preheating_time(appliances, *item, *temperature)?
+                .checked_add(*time)
+                .ok_or_else(|| format!("Unable to cook {} for so long", item))?;
+            let celsius = target_celsius(*temperature);
+            (duration, Some((ApplianceKind::for_item(*item), celsius)))
+        }
+    };
+    for appliance in appliances.iter_mut() {
+        match cooked_on {
+            // held at temperature for the cook, then left to cool once empty
+            Some((kind, celsius)) if appliance.get_kind() == kind => {
+                appliance.occupy(celsius);
+                appliance.update(duration);
+                appliance.release();
+            }
+            _ => appliance.update(duration),
+        }
+    }
+    Ok(duration)
+}
@@ -1,5 +1,6 @@
-use core::fmt;
+use core::{fmt, str::FromStr};
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum TopicName {
     PrepareCommand,
     PrepareCommandAck,
@@ -7,6 +8,34 @@ pub enum TopicName {
     CookCommand,
     CookCommandAck,
     CookCommandDone,
+    StepProgress,
+    Heartbeat,
+    Control,
+    ApplianceStatus,
+    StockRequest,
+    StockReply,
+    PlateOrder,
+    Plate,
+}
+
+impl TopicName {
+    /// Every topic used within the kitchen
+    pub const ALL: [TopicName; 14] = [
+        TopicName::PrepareCommand,
+        TopicName::PrepareCommandAck,
+        TopicName::PrepareCommandDone,
+        TopicName::CookCommand,
+        TopicName::CookCommandAck,
+        TopicName::CookCommandDone,
+        TopicName::StepProgress,
+        TopicName::Heartbeat,
+        TopicName::Control,
+        TopicName::ApplianceStatus,
+        TopicName::StockRequest,
+        TopicName::StockReply,
+        TopicName::PlateOrder,
+        TopicName::Plate,
+    ];
 }
 
 impl fmt::Display for TopicName {
@@ -18,7 +47,27 @@ impl fmt::Display for TopicName {
             TopicName::CookCommand => "cook_command",
             TopicName::CookCommandAck => "cook_command_ack",
             TopicName::CookCommandDone => "cook_command_done",
+            TopicName::StepProgress => "step_progress",
+            TopicName::Heartbeat => "heartbeat",
+            TopicName::Control => "control",
+            TopicName::ApplianceStatus => "appliance_status",
+            TopicName::StockRequest => "stock_request",
+            TopicName::StockReply => "stock_reply",
+            TopicName::PlateOrder => "plate_order",
+            TopicName::Plate => "plate",
         };
         write!(f, "{topic}")
     }
 }
+
+impl FromStr for TopicName {
+    type Err = String;
+
+    /// Parses a topic from the name it is published under
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        TopicName::ALL
+            .into_iter()
+            .find(|topic| topic.to_string() == s)
+            .ok_or_else(|| format!("Unknown topic: {}", s))
+    }
+}
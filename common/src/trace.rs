@@ -0,0 +1,398 @@
+use std::{
+    collections::hash_map::RandomState,
+    env,
+    fs::{File, OpenOptions},
+    hash::{BuildHasher, Hasher},
+    io::{Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{sync_channel, SyncSender, TrySendError},
+        Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::warn;
+
+use crate::clock;
+
+/// Environment variable naming a file finished spans are appended to, one
+/// OTLP/JSON export request per line
+pub const TRACE_FILE_VAR: &str = "CHEF_TRACE_FILE";
+
+/// Environment variable giving the base URL of an OTLP/HTTP collector, e.g.
+/// `http://localhost:4318`, which finished spans are posted to
+pub const OTLP_ENDPOINT_VAR: &str = "CHEF_OTLP_ENDPOINT";
+
+/// Finished spans waiting to be exported, configured once per process by
+/// `init`
+static EXPORTER: Mutex<Option<(SyncSender<Value>, JoinHandle<()>)>> = Mutex::new(None);
+
+/// Number of finished spans held while the exporter is busy, beyond which
+/// further spans are dropped
+const EXPORT_QUEUE: usize = 1024;
+
+/// How long connecting to, writing to or reading from a collector may take
+const COLLECTOR_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Distinguishes ids generated within the same process
+static ID_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Identifies a span within a distributed trace, in the form propagated
+/// between services inside DDS messages
+///
+/// Ids follow the W3C trace context format, so a whole recipe run can be
+/// viewed as one trace by any OpenTelemetry compatible backend. The 128 bit
+/// trace id is held as two halves as CDR cannot deserialize a `u128`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TraceContext {
+    trace_id_high: u64,
+    trace_id_low: u64,
+    span_id: u64,
+}
+
+impl TraceContext {
+    pub fn get_trace_id(&self) -> u128 {
+        ((self.trace_id_high as u128) << 64) | self.trace_id_low as u128
+    }
+    pub fn get_span_id(&self) -> u64 {
+        self.span_id
+    }
+
+    /// Hex encoded trace id, as used by OpenTelemetry
+    pub fn trace_id_hex(&self) -> String {
+        format!("{:032x}", self.get_trace_id())
+    }
+
+    /// Formats the context as a W3C `traceparent` header value
+    pub fn traceparent(&self) -> String {
+        format!("00-{:032x}-{:016x}-01", self.get_trace_id(), self.span_id)
+    }
+}
+
+/// Generates a random, non-zero id
+fn random_id() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(ID_COUNTER.fetch_add(1, Ordering::Relaxed));
+    hasher.write_u128(
+        clock::system_now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos(),
+    );
+    hasher.finish().max(1)
+}
+
+/// Time since the UNIX epoch in nanoseconds
+fn unix_nanos(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+}
+
+/// A unit of work within a trace, exported once ended
+pub struct TraceSpan {
+    name: &'static str,
+    context: TraceContext,
+    parent_span_id: Option<u64>,
+    start: SystemTime,
+    attributes: Vec<(&'static str, String)>,
+}
+
+impl TraceSpan {
+    /// Starts a span beginning a new trace
+    pub fn root(name: &'static str) -> Self {
+        Self::start(name, (random_id(), random_id()), None)
+    }
+
+    /// Starts a span continuing the trace of the given parent, which may have
+    /// been received from another service
+    pub fn child_of(name: &'static str, parent: &TraceContext) -> Self {
+        Self::start(
+            name,
+            (parent.trace_id_high, parent.trace_id_low),
+            Some(parent.span_id),
+        )
+    }
+
+    /// Starts a span within the trace identified by the high and low halves of
+    /// its id
+    fn start(name: &'static str, trace_id: (u64, u64), parent_span_id: Option<u64>) -> Self {
+        Self {
+            name,
+            context: TraceContext {
+                trace_id_high: trace_id.0,
+                trace_id_low: trace_id.1,
+                span_id: random_id(),
+            },
+            parent_span_id,
+            start: clock::system_now(),
+            attributes: Vec::new(),
+        }
+    }
+
+    /// Context identifying this span, to be propagated to other services
+    pub fn context(&self) -> TraceContext {
+        self.context
+    }
+
+    /// Attaches a key value attribute to the span
+    pub fn set_attribute(&mut self, key: &'static str, value: impl ToString) {
+        self.attributes.push((key, value.to_string()));
+    }
+
+    /// Ends the span, queueing it for export if an exporter has been
+    /// configured
+    pub fn end(self) {
+        if let Ok(exporter) = EXPORTER.lock() {
+            if let Some((exporter, _)) = exporter.as_ref() {
+                match exporter.try_send(self.to_otlp(clock::system_now())) {
+                    Ok(()) | Err(TrySendError::Disconnected(_)) => {}
+                    Err(TrySendError::Full(_)) => {
+                        warn!("Dropped trace span {}: export queue is full", self.name)
+                    }
+                }
+            }
+        }
+    }
+
+    /// Encodes the span as an OTLP/JSON span
+    fn to_otlp(&self, end: SystemTime) -> Value {
+        let attributes: Vec<Value> = self
+            .attributes
+            .iter()
+            .map(|(key, value)| json!({ "key": key, "value": { "stringValue": value } }))
+            .collect();
+        json!({
+            "traceId": self.context.trace_id_hex(),
+            "spanId": format!("{:016x}", self.context.span_id),
+            "parentSpanId": self
+                .parent_span_id
+                .map(|id| format!("{:016x}", id))
+                .unwrap_or_default(),
+            "name": self.name,
+            // SPAN_KIND_INTERNAL
+            "kind": 1,
+            "startTimeUnixNano": unix_nanos(self.start).to_string(),
+            "endTimeUnixNano": unix_nanos(end).to_string(),
+            "attributes": attributes,
+        })
+    }
+}
+
+/// Where finished spans are sent
+enum Destination {
+    File(File),
+    /// Host, port and path of an OTLP/HTTP traces endpoint
+    Otlp(String, u16, String),
+}
+
+/// Exports finished spans as OTLP/JSON
+struct Exporter {
+    service: &'static str,
+    destination: Destination,
+}
+
+impl Exporter {
+    /// Exports a single OTLP/JSON encoded span in its own export request
+    fn export(&mut self, span: Value) -> Result<(), String> {
+        let request = json!({
+            "resourceSpans": [{
+                "resource": {
+                    "attributes": [
+                        { "key": "service.name", "value": { "stringValue": self.service } }
+                    ]
+                },
+                "scopeSpans": [{
+                    "scope": { "name": "dds-chef" },
+                    "spans": [span]
+                }]
+            }]
+        })
+        .to_string();
+
+        match &mut self.destination {
+            Destination::File(file) => writeln!(file, "{}", request).map_err(|e| e.to_string()),
+            Destination::Otlp(host, port, path) => {
+                let address = (host.as_str(), *port)
+                    .to_socket_addrs()
+                    .map_err(|e| e.to_string())?
+                    .next()
+                    .ok_or_else(|| format!("Unable to resolve collector {}", host))?;
+                let mut stream = TcpStream::connect_timeout(&address, COLLECTOR_TIMEOUT)
+                    .map_err(|e| e.to_string())?;
+                stream
+                    .set_write_timeout(Some(COLLECTOR_TIMEOUT))
+                    .and_then(|()| stream.set_read_timeout(Some(COLLECTOR_TIMEOUT)))
+                    .map_err(|e| e.to_string())?;
+                write!(
+                    stream,
+                    "POST {} HTTP/1.1\r\nHost: {}:{}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    path,
+                    host,
+                    port,
+                    request.len(),
+                    request
+                )
+                .map_err(|e| e.to_string())?;
+                // only the status line matters
+                let mut response = [0; 64];
+                let read = stream.read(&mut response).map_err(|e| e.to_string())?;
+                let response = String::from_utf8_lossy(&response[..read]);
+                match response.split(' ').nth(1) {
+                    Some(status) if status.starts_with('2') => Ok(()),
+                    _ => Err(format!("Collector responded with: {}", response.trim_end())),
+                }
+            }
+        }
+    }
+}
+
+/// Parses an `http://host[:port][/path]` collector URL into the host, port and
+/// path spans should be posted to
+fn parse_endpoint(endpoint: &str) -> Result<(String, u16, String), String> {
+    let rest = endpoint.strip_prefix("http://").ok_or_else(|| {
+        format!(
+            "Only http:// collector endpoints are supported: {}",
+            endpoint
+        )
+    })?;
+    let (authority, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], rest[i..].trim_end_matches('/')),
+        None => (rest, ""),
+    };
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (
+            host,
+            port.parse()
+                .map_err(|_| format!("Invalid collector port: {}", port))?,
+        ),
+        None => (authority, 4318),
+    };
+    Ok((host.to_string(), port, format!("{}/v1/traces", path)))
+}
+
+/// Configures span export for a service from the environment
+///
+/// Spans are appended to the file named by `CHEF_TRACE_FILE` if set, otherwise
+/// posted to the collector at `CHEF_OTLP_ENDPOINT` if set. With neither set,
+/// trace contexts are still propagated but spans are not exported. Spans are
+/// exported on a background thread so a slow collector never holds up a
+/// service.
+pub fn init(service: &'static str) -> Result<(), String> {
+    let destination = if let Ok(path) = env::var(TRACE_FILE_VAR) {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| format!("Unable to open trace file {}: {}", path, e))?;
+        Destination::File(file)
+    } else if let Ok(endpoint) = env::var(OTLP_ENDPOINT_VAR) {
+        let (host, port, path) = parse_endpoint(&endpoint)?;
+        Destination::Otlp(host, port, path)
+    } else {
+        return Ok(());
+    };
+    let mut exporter = Exporter {
+        service,
+        destination,
+    };
+    let (sender, spans) = sync_channel(EXPORT_QUEUE);
+    let thread = thread::Builder::new()
+        .name("trace-exporter".to_string())
+        .spawn(move || {
+            for span in spans {
+                if let Err(e) = exporter.export(span) {
+                    warn!("Failed to export trace span: {}", e);
+                }
+            }
+        })
+        .map_err(|e| format!("Unable to start trace exporter: {}", e))?;
+    if let Ok(mut exporter) = EXPORTER.lock() {
+        *exporter = Some((sender, thread));
+    }
+    Ok(())
+}
+
+/// Waits for spans already ended to be exported, after which further spans
+/// are not exported
+pub fn flush() {
+    let exporter = EXPORTER
+        .lock()
+        .ok()
+        .and_then(|mut exporter| exporter.take());
+    if let Some((sender, thread)) = exporter {
+        drop(sender);
+        let _ = thread.join();
+    }
+}
+
+/// Test module for trace context propagation
+#[cfg(test)]
+mod trace_tests {
+    use std::time::SystemTime;
+
+    use crate::trace::{parse_endpoint, TraceSpan};
+
+    /// Checks child spans continue their parent's trace
+    #[test]
+    fn child_continues_trace() {
+        let root = TraceSpan::root("run");
+        let child = TraceSpan::child_of("step", &root.context());
+        assert_eq!(
+            child.context().get_trace_id(),
+            root.context().get_trace_id()
+        );
+        assert_ne!(child.context().get_span_id(), root.context().get_span_id());
+        assert_eq!(child.parent_span_id, Some(root.context().get_span_id()));
+        assert!(root.parent_span_id.is_none());
+    }
+
+    /// Checks the W3C traceparent encoding
+    #[test]
+    fn traceparent_format() {
+        let root = TraceSpan::root("run");
+        let traceparent = root.context().traceparent();
+        let parts: Vec<&str> = traceparent.split('-').collect();
+        assert_eq!(parts.len(), 4);
+        assert_eq!(parts[0], "00");
+        assert_eq!(parts[1].len(), 32);
+        assert_eq!(parts[2].len(), 16);
+        assert_eq!(parts[3], "01");
+    }
+
+    /// Checks spans encode to OTLP/JSON with hex ids and parent links
+    #[test]
+    fn otlp_encoding() {
+        let root = TraceSpan::root("run");
+        let mut child = TraceSpan::child_of("cook", &root.context());
+        child.set_attribute("item", "Salmon");
+        let encoded = child.to_otlp(SystemTime::now());
+        assert_eq!(encoded["name"], "cook");
+        assert_eq!(encoded["traceId"], root.context().trace_id_hex());
+        assert_eq!(
+            encoded["parentSpanId"],
+            format!("{:016x}", root.context().get_span_id())
+        );
+        assert_eq!(encoded["attributes"][0]["key"], "item");
+        assert_eq!(encoded["attributes"][0]["value"]["stringValue"], "Salmon");
+    }
+
+    /// Checks collector URLs are split into host, port and traces path
+    #[test]
+    fn endpoint_parsing() {
+        assert_eq!(
+            parse_endpoint("http://localhost:4318").unwrap(),
+            ("localhost".to_string(), 4318, "/v1/traces".to_string())
+        );
+        assert_eq!(
+            parse_endpoint("http://collector/otlp/").unwrap(),
+            ("collector".to_string(), 4318, "/otlp/v1/traces".to_string())
+        );
+        assert!(parse_endpoint("https://collector").is_err());
+    }
+}
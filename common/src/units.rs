@@ -0,0 +1,246 @@
+use std::{borrow::Cow, fmt::Display, str::FromStr, time::Duration};
+
+use schemars::{json_schema, JsonSchema, Schema, SchemaGenerator};
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+/// Units a duration may be written in, largest first, as used by
+/// `parse_duration` and `format_duration`
+const DURATION_UNITS: [(&str, Duration); 4] = [
+    ("h", Duration::from_secs(3600)),
+    ("m", Duration::from_secs(60)),
+    ("s", Duration::from_secs(1)),
+    ("ms", Duration::from_millis(1)),
+];
+
+/// Parses a human friendly duration such as `12m30s`, `1.5h` or `500ms`
+///
+/// A bare number is read as seconds
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let invalid = || format!("Invalid duration: {} (expected e.g. 12m30s)", s);
+    let s = s.trim();
+    if s.is_empty() {
+        return Err(invalid());
+    }
+    if let Ok(secs) = s.parse::<f64>() {
+        return Duration::try_from_secs_f64(secs).map_err(|_| invalid());
+    }
+
+    let mut total = Duration::ZERO;
+    let mut rest = s;
+    while !rest.is_empty() {
+        let number_end = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .ok_or_else(invalid)?;
+        let unit_end = rest[number_end..]
+            .find(|c: char| c.is_ascii_digit() || c == '.')
+            .map_or(rest.len(), |i| number_end + i);
+        let amount: f64 = rest[..number_end].parse().map_err(|_| invalid())?;
+        let unit = DURATION_UNITS
+            .iter()
+            .find(|(name, _)| *name == &rest[number_end..unit_end])
+            .map(|(_, unit)| *unit)
+            .ok_or_else(invalid)?;
+        total = Duration::try_from_secs_f64(unit.as_secs_f64() * amount)
+            .ok()
+            .and_then(|duration| total.checked_add(duration))
+            .ok_or_else(invalid)?;
+        rest = &rest[unit_end..];
+    }
+    Ok(total)
+}
+
+/// Formats a duration in the form read by `parse_duration`, e.g. `12m30s`
+pub fn format_duration(duration: Duration) -> String {
+    if duration.is_zero() {
+        return "0s".to_string();
+    }
+    let mut formatted = String::new();
+    let mut rest = duration;
+    for (name, unit) in DURATION_UNITS {
+        let count = rest.as_nanos() / unit.as_nanos();
+        if count > 0 {
+            formatted += &format!("{}{}", count, name);
+            rest -= unit * count as u32;
+        }
+    }
+    // sub-millisecond remainders are too small to matter in a kitchen
+    if formatted.is_empty() {
+        formatted = format!("{}s", duration.as_secs_f64());
+    }
+    formatted
+}
+
+/// Schema of the ways a duration may be written in a recipe file: a friendly
+/// string, a number of seconds, or the fields of a serialized `Duration`
+pub(crate) struct FriendlyDuration;
+
+impl JsonSchema for FriendlyDuration {
+    fn schema_name() -> Cow<'static, str> {
+        "Duration".into()
+    }
+
+    fn json_schema(_generator: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "description": "A duration such as 12m30s, 1.5h or 500ms, or a number of seconds",
+            "anyOf": [
+                { "type": "string", "pattern": r"^\s*((\d+(\.\d*)?|\.\d+)(ms|h|m|s))+\s*$" },
+                { "type": "number", "minimum": 0 },
+                {
+                    "type": "object",
+                    "properties": {
+                        "secs": { "type": "integer", "minimum": 0 },
+                        "nanos": { "type": "integer", "minimum": 0 }
+                    },
+                    "required": ["secs", "nanos"]
+                }
+            ]
+        })
+    }
+}
+
+/// Scales a temperature may be given in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TemperatureScale {
+    CELSIUS,
+    FAHRENHEIT,
+}
+
+/// A cooking temperature, kept in the scale it was written in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Temperature {
+    degrees: f64,
+    scale: TemperatureScale,
+}
+
+impl Temperature {
+    pub fn new(degrees: f64, scale: TemperatureScale) -> Self {
+        Self { degrees, scale }
+    }
+    pub fn get_degrees(&self) -> f64 {
+        self.degrees
+    }
+    pub fn get_scale(&self) -> TemperatureScale {
+        self.scale
+    }
+
+    /// The temperature in degrees Celsius
+    pub fn celsius(&self) -> f64 {
+        match self.scale {
+            TemperatureScale::CELSIUS => self.degrees,
+            TemperatureScale::FAHRENHEIT => (self.degrees - 32.0) * 5.0 / 9.0,
+        }
+    }
+
+    /// The temperature in degrees Fahrenheit
+    pub fn fahrenheit(&self) -> f64 {
+        match self.scale {
+            TemperatureScale::CELSIUS => self.degrees * 9.0 / 5.0 + 32.0,
+            TemperatureScale::FAHRENHEIT => self.degrees,
+        }
+    }
+}
+
+impl Display for Temperature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.scale {
+            TemperatureScale::CELSIUS => write!(f, "{}C", self.degrees),
+            TemperatureScale::FAHRENHEIT => write!(f, "{}F", self.degrees),
+        }
+    }
+}
+
+impl FromStr for Temperature {
+    type Err = String;
+
+    /// Parses temperatures such as `420F`, `215C` or `215 °C`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid temperature: {} (expected e.g. 420F or 215C)", s);
+        let s = s.trim();
+        let (degrees, scale) = match s.char_indices().last() {
+            Some((i, 'C' | 'c')) => (&s[..i], TemperatureScale::CELSIUS),
+            Some((i, 'F' | 'f')) => (&s[..i], TemperatureScale::FAHRENHEIT),
+            _ => return Err(invalid()),
+        };
+        let degrees: f64 = degrees
+            .trim_end()
+            .trim_end_matches('°')
+            .trim_end()
+            .parse()
+            .map_err(|_| invalid())?;
+        if !degrees.is_finite() {
+            return Err(invalid());
+        }
+        Ok(Self::new(degrees, scale))
+    }
+}
+
+/// Temperatures are written as strings such as `420F` in recipe files
+impl Serialize for Temperature {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Temperature {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(D::Error::custom)
+    }
+}
+
+impl JsonSchema for Temperature {
+    fn schema_name() -> Cow<'static, str> {
+        "Temperature".into()
+    }
+
+    fn json_schema(_generator: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "description": "A temperature such as 420F or 215C",
+            "type": "string",
+            "pattern": r"^\s*-?(\d+(\.\d*)?|\.\d+)\s*°?\s*[CcFf]\s*$"
+        })
+    }
+}
+
+/// Test module for human friendly units
+#[cfg(test)]
+mod units_tests {
+    use std::time::Duration;
+
+    use crate::units::{format_duration, parse_duration, Temperature, TemperatureScale};
+
+    /// Checks durations parse from and format to their friendly form
+    #[test]
+    fn duration_syntax() {
+        assert_eq!(parse_duration("12m30s"), Ok(Duration::from_secs(750)));
+        assert_eq!(parse_duration("1.5h"), Ok(Duration::from_secs(5400)));
+        assert_eq!(parse_duration("2s500ms"), Ok(Duration::from_millis(2500)));
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        for invalid in ["", "m", "10x", "5m-3s", "99999999999999999999h"] {
+            assert!(parse_duration(invalid).is_err(), "{}", invalid);
+        }
+        for duration in [750, 5400, 2500, 1, 3_600_001] {
+            let duration = Duration::from_millis(duration);
+            assert_eq!(parse_duration(&format_duration(duration)), Ok(duration));
+        }
+        assert_eq!(format_duration(Duration::from_secs(750)), "12m30s");
+        assert_eq!(format_duration(Duration::ZERO), "0s");
+    }
+
+    /// Checks temperatures parse in either scale and convert between them
+    #[test]
+    fn temperature_syntax() {
+        let oven: Temperature = "420F".parse().unwrap();
+        assert_eq!(oven.get_scale(), TemperatureScale::FAHRENHEIT);
+        assert!((oven.celsius() - 215.56).abs() < 0.01);
+        let pan: Temperature = "215 °C".parse().unwrap();
+        assert_eq!(pan, Temperature::new(215.0, TemperatureScale::CELSIUS));
+        assert_eq!(pan.to_string(), "215C");
+        assert!("420".parse::<Temperature>().is_err());
+        assert!("hotF".parse::<Temperature>().is_err());
+        for invalid in ["NaNC", "infF", "1e400C"] {
+            assert!(invalid.parse::<Temperature>().is_err(), "{}", invalid);
+        }
+    }
+}
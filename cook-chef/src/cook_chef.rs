@@ -1,115 +1,516 @@
-use std::{thread::sleep, time::Duration};
+use std::time::{Duration, Instant};
 
 use common::{
+    appliance::{default_appliances, target_celsius, Appliance, ApplianceKind},
+    clock,
+    control::ControlListener,
+    faults,
     io::{Receiver, Sender},
-    msgs::{CommandDone, CookCommand, CookCommandAck},
+    metrics::{self, CommandEvent},
+    msgs::{
+        ApplianceStatus, CommandDone, Control, ControlAction, CookCommand, CookCommandAck,
+        StepPhase, StepProgress,
+    },
+    progress::ProgressTracker,
+    service::Service,
     state::State,
     steps::FoodItem,
     topics::TopicName,
+    trace::TraceSpan,
+    units::format_duration,
 };
+use tracing::{info, info_span, warn, Span};
+
+/// Kind of step this chef carries out, as labelled in metrics
+const STEP_KIND: &str = "cook";
+
+/// Time between cycles, as cooks are checked on rather than waited out
+const TICK: Duration = Duration::from_millis(50);
+
+/// Interval between appliance status reports, which are also sent as soon as
+/// an appliance is loaded or emptied
+const STATUS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A cook command being carried out, from waiting for an appliance to done
+struct Cook {
+    run_id: u32,
+    command_id: u32,
+    item: FoodItem,
+    /// Cooking time left once the appliance is hot
+    remaining: Duration,
+    celsius: f64,
+    /// Index of the appliance the item is on, once one was free
+    appliance: Option<usize>,
+    phase: StepPhase,
+    received_at: Instant,
+    span: Span,
+    trace_span: TraceSpan,
+    phase_span: Option<TraceSpan>,
+    progress: ProgressTracker,
+    next_report: Instant,
+    /// Whether the cook's run has been paused, holding its timer
+    paused: bool,
+}
+
+impl Cook {
+    /// Moves on to the given phase, tracing it as a span of its own
+    fn begin_phase(&mut self, phase: StepPhase) {
+        self.end_phase();
+        self.phase = phase;
+        self.phase_span = Some(TraceSpan::child_of(
+            phase.name(),
+            &self.trace_span.context(),
+        ));
+    }
+
+    /// Ends the span of the current phase, if one was begun
+    fn end_phase(&mut self) {
+        if let Some(phase_span) = self.phase_span.take() {
+            phase_span.end();
+        }
+    }
+
+    /// Holds or restarts the cook's timer, as its run is paused or resumed
+    fn set_paused(&mut self, paused: bool) {
+        if self.paused == paused {
+            return;
+        }
+        self.paused = paused;
+        let _command = self.span.clone().entered();
+        let remaining = format_duration(self.remaining);
+        if paused {
+            self.progress.pause();
+            info!(%remaining, "Pausing cooking");
+        } else {
+            self.progress.resume();
+            info!(%remaining, "Resuming cooking");
+        }
+    }
+}
 
 pub struct CookChefService {
-    cooking_item: Option<FoodItem>,
-    cooking_time: Option<Duration>,
-    service_state: State,
+    service: String,
+    appliances: Vec<Appliance>,
+    /// Commands in the order they arrived, queued until an appliance is free
+    cooks: Vec<Cook>,
+    progress_interval: Duration,
+    expected_recipe: Option<String>,
+    command_limit: Option<u32>,
+    commands_taken: u32,
+    last_tick: Instant,
+    last_status: Option<Instant>,
+    control: ControlListener,
     command_receiver: Receiver<CookCommand>,
     command_ack_sender: Sender<CookCommandAck>,
     command_done_sender: Sender<CommandDone>,
+    progress_sender: Sender<StepProgress>,
+    status_sender: Sender<ApplianceStatus>,
 }
 
 impl CookChefService {
-    /// Creates new CookChefService instance
-    pub fn new() -> Self {
+    /// Creates new CookChefService instance with an oven and a pan, which
+    /// reports its progress on a command every `progress_interval`
+    pub fn new(progress_interval: Duration) -> Self {
         Self {
-            cooking_item: None,
-            cooking_time: None,
-            service_state: State::CREATED,
+            service: format!("cook-chef-{}", std::process::id()),
+            appliances: default_appliances(),
+            cooks: Vec::new(),
+            progress_interval,
+            expected_recipe: None,
+            command_limit: None,
+            commands_taken: 0,
+            last_tick: clock::now(),
+            last_status: None,
+            control: ControlListener::new(),
             command_receiver: Receiver::new(TopicName::CookCommand.to_string(), None),
             command_ack_sender: Sender::new(TopicName::CookCommandAck.to_string(), None),
             command_done_sender: Sender::new(TopicName::CookCommandDone.to_string(), None),
+            progress_sender: Sender::new(TopicName::StepProgress.to_string(), None),
+            status_sender: Sender::new(TopicName::ApplianceStatus.to_string(), None),
         }
     }
 
-    /// Returns boolean indicating completion status of the service
-    pub fn check_completed(&self) -> bool {
-        matches!(self.service_state, State::COMPLETED)
+    /// Limits the chef to commands from the recipe with the given fingerprint,
+    /// or any fingerprint starting with it, refusing any others
+    pub fn with_expected_recipe(mut self, recipe_hash: String) -> Self {
+        self.expected_recipe = Some(recipe_hash);
+        self
     }
-    /// Returns option indicating whether the service failed while "cooking"
-    /// ### Returns
-    /// - `None` if service is not in `FAILED` state
-    /// - `Some(message)` describing the failure if `FAILED`
-    pub fn check_failed(&self) -> Option<&str> {
-        if let State::FAILED(error_message) = &self.service_state {
-            return Some(error_message);
-        }
-        None
+
+    /// Cooks on the given appliances in place of the default oven and pan
+    pub fn with_appliances(mut self, appliances: Vec<Appliance>) -> Self {
+        self.appliances = appliances;
+        self
+    }
+
+    /// Completes once the given number of commands have been cooked, rather
+    /// than awaiting more indefinitely
+    pub fn with_command_limit(mut self, limit: u32) -> Self {
+        self.command_limit = Some(limit);
+        self
     }
 
-    /// Delegates necessary tasks per cycle based on current service state.
-    /// Expects helper methods to manage necessary state updates.
-    pub fn cycle(&mut self) {
-        match &self.service_state {
-            State::CREATED => self.on_created(),
-            State::ISSUED => self.on_issued(),
-            State::EXECUTING => self.on_executing(),
-            // shouldn't enter cycle() while COMPLETED
-            State::COMPLETED => (),
-            // failures checked and printed in main loop
-            State::FAILED(_) => (),
+    /// Whether no more commands will be taken
+    fn limit_reached(&self) -> bool {
+        self.command_limit
+            .is_some_and(|limit| self.commands_taken >= limit)
+    }
+
+    /// Heats and cools appliances over the time since the last tick,
+    /// returning that time
+    fn tick(&mut self) -> Duration {
+        let now = clock::now();
+        let elapsed = now.duration_since(self.last_tick);
+        self.last_tick = now;
+        for appliance in &mut self.appliances {
+            appliance.update(elapsed);
         }
+        elapsed
     }
 
-    /// After service creation, await command before moving to ISSUED
-    fn on_created(&mut self) {
-        if let Some(command) = self.command_receiver.receive() {
-            self.cooking_item = Some(command.get_item());
-            self.cooking_time = Some(command.get_duration());
-            self.service_state = State::ISSUED;
-            println!("Instructions received, beginning cooking");
+    /// Acknowledges and queues any new commands, returning whether there were
+    /// any. Fails on any command which can't be read.
+    fn receive_commands(&mut self) -> Result<bool, String> {
+        let mut received = false;
+        while !self.limit_reached() {
+            let Some(command) = self.command_receiver.try_receive()? else {
+                break;
+            };
+            let item = command.get_item();
+            if let Some(expected) = &self.expected_recipe {
+                if !command.get_recipe_hash().starts_with(expected.as_str()) {
+                    warn!(
+                        run_id = command.get_run_id(),
+                        command_id = command.get_id(),
+                        recipe_hash = command.get_recipe_hash(),
+                        "Refusing command from unexpected recipe"
+                    );
+                    metrics::record_command(CommandEvent::Refused, item, STEP_KIND);
+                    continue;
+                }
+            }
+            let kind = ApplianceKind::for_item(item);
+            // an appliance with no room would leave the item queued forever
+            let Some(appliance) = self
+                .appliances
+                .iter()
+                .find(|a| a.get_kind() == kind && a.get_capacity() > 0)
+            else {
+                warn!(
+                    run_id = command.get_run_id(),
+                    command_id = command.get_id(),
+                    "Refusing command, as there is no {} to cook the {} on",
+                    kind,
+                    item
+                );
+                metrics::record_command(CommandEvent::Refused, item, STEP_KIND);
+                continue;
+            };
+            let celsius = target_celsius(command.get_temperature());
+            let timings = appliance
+                .heating_time(celsius)
+                .and_then(|preheating| Ok((preheating, faults::stretch(command.get_duration())?)));
+            let (preheating, duration) = match timings {
+                Ok(timings) => timings,
+                Err(e) => {
+                    warn!(
+                        run_id = command.get_run_id(),
+                        command_id = command.get_id(),
+                        "Refusing command: {}",
+                        e
+                    );
+                    metrics::record_command(CommandEvent::Refused, item, STEP_KIND);
+                    continue;
+                }
+            };
+            let span = info_span!(
+                "command",
+                run_id = command.get_run_id(),
+                command_id = command.get_id(),
+                %item,
+                recipe_hash = command.get_recipe_hash(),
+                trace_id = %command.get_trace().trace_id_hex()
+            );
+            let _command = span.clone().entered();
+            // continue the head chef's trace for the duration of the command
+            let mut trace_span = TraceSpan::child_of(STEP_KIND, &command.get_trace());
+            trace_span.set_attribute("run_id", command.get_run_id());
+            trace_span.set_attribute("command_id", command.get_id());
+            trace_span.set_attribute("item", item);
+            let trace = trace_span.context();
+            metrics::record_command(CommandEvent::Received, item, STEP_KIND);
+            // expect to preheat from however hot the appliance is right now
+            let progress = ProgressTracker::new(
+                command.get_run_id(),
+                command.get_id(),
+                preheating.saturating_add(duration),
+                self.progress_interval,
+            );
+            info!("Instructions received, waiting on the {}", kind);
             // send ack
             self.command_ack_sender
-                .send(CookCommandAck)
-                .unwrap_or_else(|e| {
-                    self.service_state = State::FAILED(format!("Failed to send ack: {}", e));
-                });
+                .send(CookCommandAck::new(
+                    command.get_run_id(),
+                    command.get_id(),
+                    trace,
+                ))
+                .map_err(|e| format!("Failed to send ack: {}", e))?;
+            metrics::record_command(CommandEvent::Acked, item, STEP_KIND);
+            self.commands_taken += 1;
+            self.cooks.push(Cook {
+                run_id: command.get_run_id(),
+                command_id: command.get_id(),
+                item,
+                remaining: duration,
+                celsius,
+                appliance: None,
+                phase: StepPhase::PREHEATING,
+                received_at: clock::now(),
+                span: span.clone(),
+                trace_span,
+                phase_span: None,
+                progress,
+                next_report: clock::now(),
+                paused: false,
+            });
+            received = true;
         }
+        Ok(received)
     }
 
-    /// After service receives command, perform initialization prior to cooking
-    fn on_issued(&mut self) {
-        match self.cooking_item.unwrap() {
-            FoodItem::CHICKEN => {
-                println!("Heating pan prior to cooking the chicken");
-                sleep(Duration::from_secs(5));
+    /// Puts queued items of runs which aren't paused on any appliances free
+    /// to take them, in the order they arrived
+    fn place_cooks(&mut self) {
+        for cook in self
+            .cooks
+            .iter_mut()
+            .filter(|cook| cook.appliance.is_none() && !cook.paused)
+        {
+            let kind = ApplianceKind::for_item(cook.item);
+            let Some(index) = self
+                .appliances
+                .iter()
+                .position(|a| a.get_kind() == kind && a.can_take(cook.celsius))
+            else {
+                continue;
+            };
+            let appliance = &mut self.appliances[index];
+            let _command = cook.span.clone().entered();
+            if appliance.is_hot(cook.celsius) {
+                info!(
+                    "The {} is already hot, skipping preheating",
+                    appliance.get_name()
+                );
+            } else {
+                match cook.item {
+                    FoodItem::CHICKEN => info!("Heating pan prior to cooking the chicken"),
+                    FoodItem::SALMON => info!("Preheating oven prior to cooking the salmon"),
+                }
             }
-            FoodItem::SALMON => {
-                println!("Preheating oven prior to cooking the salmon");
-                sleep(Duration::from_secs(15));
+            appliance.occupy(cook.celsius);
+            cook.appliance = Some(index);
+            cook.begin_phase(StepPhase::PREHEATING);
+            // report appliances as soon as they are loaded
+            self.last_status = None;
+        }
+    }
+
+    /// Moves each cook of a run which isn't paused on by the given time,
+    /// reporting progress and sending done for those finished
+    fn advance_cooks(&mut self, elapsed: Duration) -> Result<(), String> {
+        let mut i = 0;
+        while i < self.cooks.len() {
+            let cook = &mut self.cooks[i];
+            let Some(index) = cook.appliance.filter(|_| !cook.paused) else {
+                i += 1;
+                continue;
+            };
+            let _command = cook.span.clone().entered();
+            match cook.phase {
+                StepPhase::PREHEATING if self.appliances[index].is_hot(cook.celsius) => {
+                    info!("Preheating completed");
+                    match cook.item {
+                        FoodItem::CHICKEN => info!("Cooking chicken on the pan"),
+                        FoodItem::SALMON => info!("Cooking salmon in the oven"),
+                    }
+                    cook.begin_phase(StepPhase::COOKING);
+                }
+                StepPhase::COOKING => cook.remaining = cook.remaining.saturating_sub(elapsed),
+                _ => (),
             }
+            let now = clock::now();
+            if now >= cook.next_report {
+                self.progress_sender
+                    .send(cook.progress.report(cook.phase))
+                    .map_err(|e| format!("Failed to send progress: {}", e))?;
+                cook.next_report = now + self.progress_interval;
+            }
+            if cook.phase != StepPhase::COOKING || !cook.remaining.is_zero() {
+                i += 1;
+                continue;
+            }
+
+            info!("Cooking completed");
+            // send done indicator
+            self.command_done_sender
+                .send(CommandDone::new(
+                    cook.run_id,
+                    cook.command_id,
+                    cook.trace_span.context(),
+                ))
+                .map_err(|e| format!("Failed to send done: {}", e))?;
+            let mut cook = self.cooks.remove(i);
+            cook.end_phase();
+            cook.trace_span.end();
+            metrics::record_command(CommandEvent::Completed, cook.item, STEP_KIND);
+            metrics::observe_step_duration(cook.item, STEP_KIND, clock::since(cook.received_at));
+            self.appliances[index].release();
+            self.last_status = None;
         }
-        println!("Preheating completed");
-        self.service_state = State::EXECUTING;
+        Ok(())
     }
 
-    /// After initialization, perform necessary cooking task
-    fn on_executing(&mut self) {
-        match self.cooking_item.unwrap() {
-            FoodItem::CHICKEN => {
-                println!("Cooking chicken on the pan");
-                sleep(self.cooking_time.unwrap());
+    /// Publishes the status of every appliance, if due
+    fn publish_status(&mut self) -> Result<(), String> {
+        if self
+            .last_status
+            .is_some_and(|sent_at| clock::since(sent_at) < STATUS_INTERVAL)
+        {
+            return Ok(());
+        }
+        for appliance in &self.appliances {
+            let queued = self
+                .cooks
+                .iter()
+                .filter(|cook| {
+                    cook.appliance.is_none()
+                        && ApplianceKind::for_item(cook.item) == appliance.get_kind()
+                })
+                .count() as u32;
+            self.status_sender
+                .send(ApplianceStatus::new(
+                    self.service.clone(),
+                    appliance,
+                    queued,
+                ))
+                .map_err(|e| format!("Failed to send appliance status: {}", e))?;
+        }
+        self.last_status = Some(clock::now());
+        Ok(())
+    }
+
+    /// Abandons every cook of the runs the given request cancels, counting
+    /// them as failed and freeing their appliances
+    fn cancel_cooks(&mut self, control: &Control) {
+        let (cancelled, cooks): (Vec<Cook>, Vec<Cook>) = std::mem::take(&mut self.cooks)
+            .into_iter()
+            .partition(|cook| control.applies_to(cook.run_id));
+        self.cooks = cooks;
+        for mut cook in cancelled {
+            let _command = cook.span.clone().entered();
+            info!("Run cancelled, abandoning cooking");
+            if let Some(index) = cook.appliance {
+                self.appliances[index].release();
+                self.last_status = None;
             }
-            FoodItem::SALMON => {
-                println!("Cooking salmon in the oven");
-                sleep(self.cooking_time.unwrap());
+            cook.end_phase();
+            cook.trace_span.set_attribute("error", "cancelled");
+            cook.trace_span.end();
+            metrics::record_command(CommandEvent::Failed, cook.item, STEP_KIND);
+        }
+    }
+
+    /// Pauses, resumes or cancels the cooks of each run as requested
+    fn receive_controls(&mut self) {
+        for control in self.control.receive_all() {
+            let paused = match control.get_action() {
+                ControlAction::PAUSE => true,
+                ControlAction::RESUME => false,
+                ControlAction::CANCEL => {
+                    self.cancel_cooks(&control);
+                    continue;
+                }
+            };
+            for cook in &mut self.cooks {
+                if control.applies_to(cook.run_id) {
+                    cook.set_paused(paused);
+                }
             }
         }
-        println!("Cooking completed");
-        self.service_state = State::COMPLETED;
-        // send done indicator
-        self.command_done_sender
-            .send(CommandDone)
-            .unwrap_or_else(|e| {
-                self.service_state = State::FAILED(format!("Failed to send done: {}", e));
-            });
+    }
+
+    /// Checks on each cook, taking on further commands, returning `PAUSED`
+    /// while every cook in hand is paused, or the state to move to once all
+    /// are done
+    ///
+    /// Appliances keep heating and cooling throughout, paused or not.
+    fn work(&mut self) -> Result<State, String> {
+        clock::sleep(TICK);
+        let elapsed = self.tick();
+        self.receive_controls();
+        self.receive_commands()?;
+        self.place_cooks();
+        self.advance_cooks(elapsed)?;
+        self.publish_status()?;
+        if self.cooks.iter().any(|cook| !cook.paused) {
+            return Ok(State::EXECUTING);
+        }
+        if !self.cooks.is_empty() {
+            return Ok(State::PAUSED);
+        }
+        if self.limit_reached() {
+            return Ok(State::COMPLETED);
+        }
+        info!("Nothing left to cook, awaiting further instructions");
+        Ok(State::IDLE)
+    }
+}
+
+impl Service for CookChefService {
+    const KIND: &'static str = "cook-chef";
+
+    /// Nothing to set up once created, so await commands straight away
+    fn on_created(&mut self) -> Result<State, String> {
+        Ok(State::IDLE)
+    }
+
+    /// While idle, let appliances cool and await commands before moving to
+    /// ISSUED
+    fn on_idle(&mut self) -> Result<State, String> {
+        clock::sleep(TICK);
+        self.tick();
+        self.publish_status()?;
+        if self.receive_commands()? {
+            return Ok(State::ISSUED);
+        }
+        Ok(State::IDLE)
+    }
+
+    /// After service receives commands, put them on any free appliances
+    fn on_issued(&mut self) -> Result<State, String> {
+        self.place_cooks();
+        Ok(State::EXECUTING)
+    }
+
+    /// Check on each cook, taking on further commands, until all are done
+    /// or paused
+    fn on_executing(&mut self) -> Result<State, String> {
+        self.work()
+    }
+
+    /// While every cook in hand is paused, hold their timers until resumed or
+    /// cancelled, still taking on commands of other runs
+    fn on_paused(&mut self) -> Result<State, String> {
+        self.work()
+    }
+
+    fn on_failed(&mut self, reason: &str) {
+        // count each command in hand as failed on the cycle the chef fails in
+        for mut cook in self.cooks.drain(..) {
+            cook.end_phase();
+            cook.trace_span.set_attribute("error", reason);
+            cook.trace_span.end();
+            metrics::record_command(CommandEvent::Failed, cook.item, STEP_KIND);
+        }
     }
 }
@@ -0,0 +1,3 @@
+mod cook_chef;
+
+pub use crate::cook_chef::CookChefService;
@@ -1,19 +1,130 @@
-mod cook_chef;
+use std::{env, path::PathBuf, time::Duration};
 
-use crate::cook_chef::CookChefService;
+use common::{
+    appliance::load_appliances, faults, logging, metrics, progress::DEFAULT_PROGRESS_INTERVAL,
+    service::ServiceRunner, trace,
+};
+
+use tracing::{error, info};
+
+use cook_chef::CookChefService;
+
+/// Helper which attempts to retrieve a progress reporting interval, given in
+/// milliseconds, from provided command line args
+fn retrieve_progress_interval() -> Option<Duration> {
+    let args: Vec<String> = env::args().collect();
+
+    let mut interval = None;
+    for (i, a) in args.iter().enumerate() {
+        if a == "-i" && i + 1 < args.len() {
+            interval = args[i + 1].parse().ok().map(Duration::from_millis);
+        }
+    }
+    interval
+}
+
+/// Helper which attempts to retrieve a port to serve metrics on from provided
+/// command line args
+fn retrieve_metrics_port() -> Option<u16> {
+    let args: Vec<String> = env::args().collect();
+
+    let mut port = None;
+    for (i, a) in args.iter().enumerate() {
+        if a == "-m" && i + 1 < args.len() {
+            port = args[i + 1].parse().ok();
+        }
+    }
+    port
+}
+
+/// Helper which attempts to retrieve the fingerprint of the only recipe to
+/// accept commands from, as printed by the driver, from provided command line
+/// args
+fn retrieve_expected_recipe() -> Option<String> {
+    let args: Vec<String> = env::args().collect();
+
+    let mut recipe_hash = None;
+    for (i, a) in args.iter().enumerate() {
+        if a == "-e" && i + 1 < args.len() {
+            recipe_hash = Some(args[i + 1].clone());
+        }
+    }
+    recipe_hash
+}
+
+/// Helper which attempts to retrieve the path of a YAML file listing the
+/// appliances to cook on from provided command line args
+fn retrieve_appliances_path() -> Option<PathBuf> {
+    let args: Vec<String> = env::args().collect();
+
+    let mut path = None;
+    for (i, a) in args.iter().enumerate() {
+        if a == "-a" && i + 1 < args.len() {
+            path = Some(PathBuf::from(&args[i + 1]));
+        }
+    }
+    path
+}
+
+/// Helper which attempts to retrieve the number of commands to cook before
+/// completing from provided command line args
+fn retrieve_command_limit() -> Option<u32> {
+    let args: Vec<String> = env::args().collect();
+
+    let mut limit = None;
+    for (i, a) in args.iter().enumerate() {
+        if a == "-n" && i + 1 < args.len() {
+            limit = args[i + 1].parse().ok();
+        }
+    }
+    limit
+}
 
 fn main() {
-    // initialize cook chef service
-    let mut cc = CookChefService::new();
+    let _service = logging::init("cook-chef");
+    if let Err(e) = trace::init("cook-chef") {
+        error!("Unable to set up trace export: {}", e);
+        return;
+    }
+    if let Err(e) = faults::init("cook-chef") {
+        error!("Unable to load fault scenario: {}", e);
+        return;
+    }
 
-    println!("Cook chef awaiting instructions");
-    while !cc.check_completed() {
-        cc.cycle();
-        if let Some(error_msg) = cc.check_failed() {
-            println!("Cook chef has failed: {}", error_msg);
+    // optionally expose metrics for scraping
+    if let Some(port) = retrieve_metrics_port() {
+        if let Err(e) = metrics::serve(port) {
+            error!("Unable to serve metrics on port {}: {}", port, e);
             return;
         }
     }
 
-    println!("Cook chef completed cooking");
+    // initialize cook chef service
+    let mut cc =
+        CookChefService::new(retrieve_progress_interval().unwrap_or(DEFAULT_PROGRESS_INTERVAL));
+    // optionally refuse commands from any recipe but the expected one
+    if let Some(recipe_hash) = retrieve_expected_recipe() {
+        cc = cc.with_expected_recipe(recipe_hash);
+    }
+    // optionally cook on appliances other than an oven and a pan
+    if let Some(path) = retrieve_appliances_path() {
+        match load_appliances(&path) {
+            Ok(appliances) => cc = cc.with_appliances(appliances),
+            Err(e) => {
+                error!("Unable to load appliances: {}", e);
+                return;
+            }
+        }
+    }
+    // optionally stop after a number of commands rather than cooking indefinitely
+    if let Some(limit) = retrieve_command_limit() {
+        cc = cc.with_command_limit(limit);
+    }
+
+    let mut runner = ServiceRunner::new(cc);
+    info!("Cook chef awaiting instructions");
+    match runner.run() {
+        Ok(()) => info!("Cook chef completed cooking"),
+        Err(e) => error!("Cook chef has failed: {}", e),
+    }
 }
@@ -0,0 +1,421 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+use common::{
+    msgs::{Heartbeat, StepPhase, StepProgress},
+    protocol,
+    recipe::Recipe,
+    state::State,
+    steps::{FoodItem, Step},
+};
+
+/// Maximum number of entries kept in the event log
+const LOG_CAPACITY: usize = 500;
+
+/// Something observed on the DDS topics watched by the dashboard, with the run
+/// and command id of any step it concerns
+pub enum KitchenEvent {
+    PrepareCommand {
+        run_id: u32,
+        id: u32,
+        item: FoodItem,
+    },
+    PrepareAck {
+        run_id: u32,
+        id: u32,
+    },
+    PrepareDone {
+        run_id: u32,
+        id: u32,
+    },
+    CookCommand {
+        run_id: u32,
+        id: u32,
+        item: FoodItem,
+        time: Duration,
+    },
+    CookAck {
+        run_id: u32,
+        id: u32,
+    },
+    CookDone {
+        run_id: u32,
+        id: u32,
+    },
+    Heartbeat(Heartbeat),
+    Progress(StepProgress),
+}
+
+/// Where a recipe step is in its lifecycle, as seen from its messages
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StepStatus {
+    /// No command has been seen for this step yet
+    Pending,
+    /// Command sent by the head chef but not yet acknowledged
+    Sent,
+    /// Command acknowledged by a worker chef
+    Acked,
+    /// Worker chef is reporting progress on the step
+    InProgress { percent: u8, phase: StepPhase },
+    /// Worker chef reported the step done
+    Done,
+}
+
+/// Dashboard view of a single recipe step
+pub struct StepView {
+    label: String,
+    status: StepStatus,
+    sent_at: Option<Instant>,
+    done_at: Option<Instant>,
+}
+
+impl StepView {
+    fn new(label: String) -> Self {
+        Self {
+            label,
+            status: StepStatus::Pending,
+            sent_at: None,
+            done_at: None,
+        }
+    }
+    pub fn get_label(&self) -> &str {
+        &self.label
+    }
+    pub fn get_status(&self) -> StepStatus {
+        self.status
+    }
+    /// Time spent on the step so far, or in total once done
+    pub fn elapsed(&self, now: Instant) -> Option<Duration> {
+        self.sent_at
+            .map(|sent| self.done_at.unwrap_or(now).duration_since(sent))
+    }
+}
+
+/// Dashboard view of a single chef service
+pub struct WorkerView {
+    state: State,
+    last_seen: Instant,
+}
+
+impl WorkerView {
+    pub fn get_state(&self) -> &State {
+        &self.state
+    }
+    pub fn get_last_seen(&self) -> Instant {
+        self.last_seen
+    }
+}
+
+/// Everything the dashboard knows about the kitchen
+///
+/// Only the steps of one run are shown, switching to a new run once a command
+/// of it is sent, so messages of earlier runs can't overwrite its steps.
+pub struct Kitchen {
+    started: Instant,
+    workers: BTreeMap<String, WorkerView>,
+    /// Labels of the recipe's steps, shown before their commands are sent
+    recipe_steps: Vec<String>,
+    /// Run whose steps are shown, once any message of one has been seen
+    run_id: Option<u32>,
+    /// Steps by command id, as seen on the topics or read from the recipe
+    steps: BTreeMap<u32, StepView>,
+    log: VecDeque<String>,
+}
+
+impl Kitchen {
+    /// Creates an empty kitchen, optionally seeded with the steps of the recipe
+    /// being run so that steps show up before their commands are sent
+    pub fn new(recipe: Option<&Recipe>, started: Instant) -> Self {
+        let recipe_steps = recipe
+            .map(|recipe| recipe.get_steps().iter().map(ToString::to_string).collect())
+            .unwrap_or_default();
+        let mut kitchen = Self {
+            started,
+            workers: BTreeMap::new(),
+            recipe_steps,
+            run_id: None,
+            steps: BTreeMap::new(),
+            log: VecDeque::new(),
+        };
+        kitchen.reset_steps();
+        kitchen
+    }
+
+    pub fn get_workers(&self) -> &BTreeMap<String, WorkerView> {
+        &self.workers
+    }
+    /// Run whose steps are shown, if any message of one has been seen
+    pub fn get_run_id(&self) -> Option<u32> {
+        self.run_id
+    }
+    pub fn get_steps(&self) -> &BTreeMap<u32, StepView> {
+        &self.steps
+    }
+    pub fn get_log(&self) -> &VecDeque<String> {
+        &self.log
+    }
+
+    /// Updates the kitchen with an event observed at the given time
+    pub fn apply(&mut self, event: KitchenEvent, now: Instant) {
+        match event {
+            KitchenEvent::PrepareCommand { run_id, id, item } => {
+                self.follow(run_id, now);
+                self.log(now, format!("prepare command {} sent: {}", id, item));
+                let step = self.step_mut(id, || Step::Prepare(item).to_string());
+                step.status = StepStatus::Sent;
+                step.sent_at = Some(now);
+            }
+            KitchenEvent::CookCommand {
+                run_id,
+                id,
+                item,
+                time,
+            } => {
+                self.follow(run_id, now);
+                self.log(now, format!("cook command {} sent: {}", id, item));
+                let step = self.step_mut(id, || Step::Cook(item, time, None).to_string());
+                step.status = StepStatus::Sent;
+                step.sent_at = Some(now);
+            }
+            KitchenEvent::PrepareAck { run_id, id } | KitchenEvent::CookAck { run_id, id } => {
+                if !self.follows(run_id) {
+                    return;
+                }
+                self.log(now, format!("command {} acknowledged", id));
+                let step = self.step_mut(id, || "unknown".to_string());
+                if step.status == StepStatus::Sent || step.status == StepStatus::Pending {
+                    step.status = StepStatus::Acked;
+                }
+            }
+            KitchenEvent::PrepareDone { run_id, id } | KitchenEvent::CookDone { run_id, id } => {
+                if !self.follows(run_id) {
+                    return;
+                }
+                self.log(now, format!("command {} done", id));
+                let step = self.step_mut(id, || "unknown".to_string());
+                step.status = StepStatus::Done;
+                step.done_at = Some(now);
+            }
+            KitchenEvent::Progress(progress) => {
+                if !self.follows(progress.get_run_id()) {
+                    return;
+                }
+                let step = self.step_mut(progress.get_command_id(), || "unknown".to_string());
+                if step.status != StepStatus::Done {
+                    step.status = StepStatus::InProgress {
+                        percent: progress.get_percent(),
+                        phase: progress.get_phase(),
+                    };
+                }
+            }
+            KitchenEvent::Heartbeat(heartbeat) => {
+                let state = heartbeat.get_state().clone();
+                if !self.workers.contains_key(heartbeat.get_service()) {
+                    if let Err(e) =
+                        protocol::check_peer(heartbeat.get_service(), heartbeat.get_protocols())
+                    {
+                        self.log(now, e);
+                    }
+                }
+                let changed = self
+                    .workers
+                    .get(heartbeat.get_service())
+                    .is_none_or(|worker| worker.state != state);
+                if changed {
+                    self.log(
+                        now,
+                        format!("{} is now {:?}", heartbeat.get_service(), state),
+                    );
+                }
+                self.workers.insert(
+                    heartbeat.get_service().to_string(),
+                    WorkerView {
+                        state,
+                        last_seen: now,
+                    },
+                );
+            }
+        }
+    }
+
+    /// Switches to showing the given run's steps, as a command of it was sent,
+    /// starting again from the recipe's steps if it is a new run
+    fn follow(&mut self, run_id: u32, now: Instant) {
+        if self.run_id == Some(run_id) {
+            return;
+        }
+        if self.run_id.is_some() {
+            self.reset_steps();
+        }
+        self.run_id = Some(run_id);
+        self.log(now, format!("following run {}", run_id));
+    }
+
+    /// Whether a message of the given run concerns the steps shown, following
+    /// the run if none was yet
+    fn follows(&mut self, run_id: u32) -> bool {
+        *self.run_id.get_or_insert(run_id) == run_id
+    }
+
+    /// Shows only the recipe's steps, none of them seen yet
+    fn reset_steps(&mut self) {
+        self.steps = self
+            .recipe_steps
+            .iter()
+            .enumerate()
+            .map(|(id, label)| (id as u32, StepView::new(label.clone())))
+            .collect();
+    }
+
+    /// Returns the step with the given command id, creating it if it hasn't
+    /// been seen yet
+    fn step_mut(&mut self, id: u32, label: impl FnOnce() -> String) -> &mut StepView {
+        self.steps
+            .entry(id)
+            .or_insert_with(|| StepView::new(label()))
+    }
+
+    /// Appends a time-stamped entry to the event log
+    fn log(&mut self, now: Instant, entry: String) {
+        let offset = now.duration_since(self.started).as_secs_f32();
+        self.log.push_back(format!("[{:>7.1}s] {}", offset, entry));
+        if self.log.len() > LOG_CAPACITY {
+            self.log.pop_front();
+        }
+    }
+}
+
+/// Test module for the dashboard's kitchen model
+#[cfg(test)]
+mod kitchen_tests {
+    use std::time::{Duration, Instant};
+
+    use common::{
+        msgs::{Heartbeat, StepPhase, StepProgress},
+        state::State,
+        steps::FoodItem,
+    };
+
+    use crate::kitchen::{Kitchen, KitchenEvent, StepStatus};
+
+    /// Walks a single step through its full lifecycle
+    #[test]
+    fn step_lifecycle() {
+        let start = Instant::now();
+        let mut kitchen = Kitchen::new(None, start);
+        kitchen.apply(
+            KitchenEvent::CookCommand {
+                run_id: 1,
+                id: 0,
+                item: FoodItem::SALMON,
+                time: Duration::from_secs(10),
+            },
+            start,
+        );
+        assert_eq!(kitchen.get_steps()[&0].get_status(), StepStatus::Sent);
+        assert_eq!(kitchen.get_steps()[&0].get_label(), "Cook Salmon for 10s");
+
+        kitchen.apply(KitchenEvent::CookAck { run_id: 1, id: 0 }, start);
+        assert_eq!(kitchen.get_steps()[&0].get_status(), StepStatus::Acked);
+
+        let progress = StepProgress::new(
+            1,
+            0,
+            40,
+            Duration::from_secs(4),
+            Duration::from_secs(6),
+            StepPhase::COOKING,
+        );
+        kitchen.apply(KitchenEvent::Progress(progress), start);
+        assert_eq!(
+            kitchen.get_steps()[&0].get_status(),
+            StepStatus::InProgress {
+                percent: 40,
+                phase: StepPhase::COOKING
+            }
+        );
+
+        let end = start + Duration::from_secs(25);
+        kitchen.apply(KitchenEvent::CookDone { run_id: 1, id: 0 }, end);
+        assert_eq!(kitchen.get_steps()[&0].get_status(), StepStatus::Done);
+        assert_eq!(
+            kitchen.get_steps()[&0].elapsed(end + Duration::from_secs(5)),
+            Some(Duration::from_secs(25))
+        );
+    }
+
+    /// Checks heartbeats from many chefs are tracked separately
+    #[test]
+    fn multiple_workers() {
+        let start = Instant::now();
+        let mut kitchen = Kitchen::new(None, start);
+        for service in ["prep-chef-1", "prep-chef-2", "cook-chef-3"] {
+            kitchen.apply(
+                KitchenEvent::Heartbeat(Heartbeat::new(
+                    service.to_string(),
+                    State::CREATED,
+                    Vec::new(),
+                )),
+                start,
+            );
+        }
+        kitchen.apply(
+            KitchenEvent::Heartbeat(Heartbeat::new(
+                "prep-chef-2".to_string(),
+                State::EXECUTING,
+                Vec::new(),
+            )),
+            start,
+        );
+        assert_eq!(kitchen.get_workers().len(), 3);
+        assert_eq!(
+            kitchen.get_workers()["prep-chef-2"].get_state(),
+            &State::EXECUTING
+        );
+        // one log entry per state change
+        assert_eq!(kitchen.get_log().len(), 4);
+    }
+
+    /// Checks steps seen out of order are kept by command id, without rows
+    /// for the steps not seen
+    #[test]
+    fn unknown_steps() {
+        let start = Instant::now();
+        let mut kitchen = Kitchen::new(None, start);
+        kitchen.apply(KitchenEvent::PrepareDone { run_id: 1, id: 2 }, start);
+        kitchen.apply(
+            KitchenEvent::CookDone {
+                run_id: 1,
+                id: u32::MAX,
+            },
+            start,
+        );
+        assert_eq!(kitchen.get_steps().len(), 2);
+        assert_eq!(kitchen.get_steps()[&2].get_status(), StepStatus::Done);
+        assert_eq!(kitchen.get_steps()[&u32::MAX].get_label(), "unknown");
+    }
+
+    /// Checks a new run's steps replace those of the last, and late messages
+    /// of the last run are ignored
+    #[test]
+    fn later_run() {
+        let start = Instant::now();
+        let mut kitchen = Kitchen::new(None, start);
+        let prepare = |run_id| KitchenEvent::PrepareCommand {
+            run_id,
+            id: 0,
+            item: FoodItem::CHICKEN,
+        };
+        kitchen.apply(prepare(1), start);
+        kitchen.apply(KitchenEvent::PrepareDone { run_id: 1, id: 1 }, start);
+        assert_eq!(kitchen.get_steps().len(), 2);
+
+        kitchen.apply(prepare(2), start);
+        kitchen.apply(KitchenEvent::PrepareDone { run_id: 1, id: 0 }, start);
+        assert_eq!(kitchen.get_run_id(), Some(2));
+        assert_eq!(kitchen.get_steps().len(), 1);
+        assert_eq!(kitchen.get_steps()[&0].get_status(), StepStatus::Sent);
+    }
+}
@@ -0,0 +1,95 @@
+use common::{
+    io::Receiver,
+    msgs::{
+        CommandDone, CookCommand, CookCommandAck, Heartbeat, PrepareCommand, PrepareCommandAck,
+        StepProgress,
+    },
+    topics::TopicName,
+};
+
+use crate::kitchen::KitchenEvent;
+
+/// Passive DDS participant which listens in on every kitchen topic
+pub struct KitchenListener {
+    prep_command_receiver: Receiver<PrepareCommand>,
+    prep_command_ack_receiver: Receiver<PrepareCommandAck>,
+    prep_command_done_receiver: Receiver<CommandDone>,
+    cook_command_receiver: Receiver<CookCommand>,
+    cook_command_ack_receiver: Receiver<CookCommandAck>,
+    cook_command_done_receiver: Receiver<CommandDone>,
+    heartbeat_receiver: Receiver<Heartbeat>,
+    progress_receiver: Receiver<StepProgress>,
+}
+
+impl KitchenListener {
+    /// Creates a new listener subscribed to all kitchen topics
+    pub fn new() -> Self {
+        Self {
+            prep_command_receiver: Receiver::new(TopicName::PrepareCommand.to_string(), None),
+            prep_command_ack_receiver: Receiver::new(
+                TopicName::PrepareCommandAck.to_string(),
+                None,
+            ),
+            prep_command_done_receiver: Receiver::new(
+                TopicName::PrepareCommandDone.to_string(),
+                None,
+            ),
+            cook_command_receiver: Receiver::new(TopicName::CookCommand.to_string(), None),
+            cook_command_ack_receiver: Receiver::new(TopicName::CookCommandAck.to_string(), None),
+            cook_command_done_receiver: Receiver::new(TopicName::CookCommandDone.to_string(), None),
+            heartbeat_receiver: Receiver::new(TopicName::Heartbeat.to_string(), None),
+            progress_receiver: Receiver::new(TopicName::StepProgress.to_string(), None),
+        }
+    }
+
+    /// Drains every topic, returning all events observed since the last poll
+    pub fn poll(&mut self) -> Vec<KitchenEvent> {
+        let mut events = Vec::new();
+        while let Some(command) = self.prep_command_receiver.receive() {
+            events.push(KitchenEvent::PrepareCommand {
+                run_id: command.get_run_id(),
+                id: command.get_id(),
+                item: command.get_item(),
+            });
+        }
+        while let Some(command) = self.cook_command_receiver.receive() {
+            events.push(KitchenEvent::CookCommand {
+                run_id: command.get_run_id(),
+                id: command.get_id(),
+                item: command.get_item(),
+                time: command.get_duration(),
+            });
+        }
+        while let Some(ack) = self.prep_command_ack_receiver.receive() {
+            events.push(KitchenEvent::PrepareAck {
+                run_id: ack.get_run_id(),
+                id: ack.get_command_id(),
+            });
+        }
+        while let Some(ack) = self.cook_command_ack_receiver.receive() {
+            events.push(KitchenEvent::CookAck {
+                run_id: ack.get_run_id(),
+                id: ack.get_command_id(),
+            });
+        }
+        while let Some(progress) = self.progress_receiver.receive() {
+            events.push(KitchenEvent::Progress(progress));
+        }
+        while let Some(done) = self.prep_command_done_receiver.receive() {
+            events.push(KitchenEvent::PrepareDone {
+                run_id: done.get_run_id(),
+                id: done.get_command_id(),
+            });
+        }
+        while let Some(done) = self.cook_command_done_receiver.receive() {
+            events.push(KitchenEvent::CookDone {
+                run_id: done.get_run_id(),
+                id: done.get_command_id(),
+            });
+        }
+        while let Some(heartbeat) = self.heartbeat_receiver.receive() {
+            events.push(KitchenEvent::Heartbeat(heartbeat));
+        }
+        events
+    }
+}
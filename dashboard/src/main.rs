@@ -0,0 +1,110 @@
+mod kitchen;
+mod listener;
+mod ui;
+
+use std::{
+    collections::BTreeMap,
+    env,
+    time::{Duration, Instant},
+};
+
+use common::recipe::Recipe;
+use ratatui::crossterm::event::{self, Event, KeyCode};
+
+use crate::{kitchen::Kitchen, listener::KitchenListener};
+
+/// How long to wait for key presses between polls of the kitchen topics
+const REFRESH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Helper which attempts to retrieve a filename from provided command line args
+fn retrieve_filename() -> Option<String> {
+    let args: Vec<String> = env::args().collect();
+
+    let mut in_file = None;
+    for (i, a) in args.iter().enumerate() {
+        if a == "-f" && i + 1 < args.len() {
+            in_file = Some(args[i + 1].clone());
+        }
+    }
+    in_file
+}
+
+/// Helper which attempts to retrieve the servings to scale the recipe to from
+/// provided command line args
+fn retrieve_servings() -> Option<u32> {
+    let args: Vec<String> = env::args().collect();
+
+    let mut servings = None;
+    for (i, a) in args.iter().enumerate() {
+        if a == "-s" && i + 1 < args.len() {
+            servings = args[i + 1].parse().ok();
+        }
+    }
+    servings
+}
+
+/// Helper which collects the recipe parameters overridden with
+/// `--set <name>=<value>` in provided command line args
+fn retrieve_overrides() -> Result<BTreeMap<String, String>, String> {
+    let args: Vec<String> = env::args().collect();
+
+    let mut overrides = BTreeMap::new();
+    for (i, a) in args.iter().enumerate() {
+        if a == "--set" {
+            let (name, value) = args
+                .get(i + 1)
+                .and_then(|setting| setting.split_once('='))
+                .ok_or_else(|| "Expected --set <name>=<value>".to_string())?;
+            overrides.insert(name.to_string(), value.to_string());
+        }
+    }
+    Ok(overrides)
+}
+
+fn main() -> std::io::Result<()> {
+    // optionally read in the recipe being run so its steps show up up front
+    let recipe = retrieve_filename().map(|file_name| {
+        retrieve_overrides()
+            .and_then(|overrides| Recipe::from_file_with(&file_name, &overrides))
+            .and_then(|recipe| match retrieve_servings() {
+                Some(servings) => recipe.scale(servings),
+                None => Ok(recipe),
+            })
+    });
+    let recipe = match recipe {
+        None => None,
+        Some(Ok(recipe)) => Some(recipe),
+        Some(Err(e)) => {
+            println!("Error reading recipe: {}", e);
+            return Ok(());
+        }
+    };
+
+    let mut listener = KitchenListener::new();
+    let mut kitchen = Kitchen::new(recipe.as_ref(), Instant::now());
+
+    let mut terminal = ratatui::init();
+    let result = loop {
+        let now = Instant::now();
+        for event in listener.poll() {
+            kitchen.apply(event, now);
+        }
+        if let Err(e) = terminal.draw(|frame| ui::draw(frame, &kitchen, now)) {
+            break Err(e);
+        }
+        // quit on q or escape
+        match event::poll(REFRESH_INTERVAL) {
+            Ok(true) => match event::read() {
+                Ok(Event::Key(key)) if matches!(key.code, KeyCode::Char('q') | KeyCode::Esc) => {
+                    break Ok(())
+                }
+                Ok(_) => (),
+                Err(e) => break Err(e),
+            },
+            Ok(false) => (),
+            Err(e) => break Err(e),
+        }
+    };
+    ratatui::restore();
+    result
+}
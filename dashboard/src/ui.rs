@@ -0,0 +1,124 @@
+use std::time::Instant;
+
+use ratatui::{
+    layout::{Constraint, Layout},
+    style::{Color, Style, Stylize},
+    text::Line,
+    widgets::{Block, List, ListItem, Row, Table},
+    Frame,
+};
+
+use common::state::State;
+
+use crate::kitchen::{Kitchen, StepStatus};
+
+/// Draws the full dashboard: workers, recipe steps and the event log
+pub fn draw(frame: &mut Frame, kitchen: &Kitchen, now: Instant) {
+    let [workers_area, steps_area, log_area] = Layout::vertical([
+        Constraint::Length(kitchen.get_workers().len() as u16 + 3),
+        Constraint::Length(kitchen.get_steps().len() as u16 + 3),
+        Constraint::Min(3),
+    ])
+    .areas(frame.area());
+
+    // one row per chef service seen on the heartbeat topic
+    let worker_rows = kitchen.get_workers().iter().map(|(service, worker)| {
+        let state = worker.get_state();
+        Row::new(vec![
+            service.clone(),
+            format!("{:?}", state),
+            format!(
+                "{:.1}s ago",
+                now.duration_since(worker.get_last_seen()).as_secs_f32()
+            ),
+        ])
+        .style(Style::default().fg(state_color(state)))
+    });
+    let workers = Table::new(
+        worker_rows,
+        [
+            Constraint::Length(24),
+            Constraint::Min(12),
+            Constraint::Length(12),
+        ],
+    )
+    .header(Row::new(vec!["Service", "State", "Last seen"]).bold())
+    .block(Block::bordered().title("Chefs"));
+    frame.render_widget(workers, workers_area);
+
+    // one row per recipe step
+    let step_rows = kitchen.get_steps().iter().map(|(id, step)| {
+        let status = match step.get_status() {
+            StepStatus::Pending => "pending".to_string(),
+            StepStatus::Sent => "sent".to_string(),
+            StepStatus::Acked => "acknowledged".to_string(),
+            StepStatus::InProgress { percent, phase } => format!("{} {}%", phase, percent),
+            StepStatus::Done => "done".to_string(),
+        };
+        let elapsed = step
+            .elapsed(now)
+            .map(|elapsed| format!("{:.1}s", elapsed.as_secs_f32()))
+            .unwrap_or_default();
+        Row::new(vec![
+            (*id as u64 + 1).to_string(),
+            step.get_label().to_string(),
+            status,
+            elapsed,
+        ])
+    });
+    let steps = Table::new(
+        step_rows,
+        [
+            Constraint::Length(4),
+            Constraint::Min(20),
+            Constraint::Length(18),
+            Constraint::Length(10),
+        ],
+    )
+    .header(Row::new(vec!["#", "Step", "Status", "Time"]).bold())
+    .block(Block::bordered().title(progress_title(kitchen)));
+    frame.render_widget(steps, steps_area);
+
+    // event log scrolled to show the most recent entries
+    let visible = log_area.height.saturating_sub(2) as usize;
+    let entries = kitchen
+        .get_log()
+        .iter()
+        .skip(kitchen.get_log().len().saturating_sub(visible))
+        .map(|entry| ListItem::new(Line::from(entry.as_str())));
+    let log = List::new(entries).block(Block::bordered().title("Events (q to quit)"));
+    frame.render_widget(log, log_area);
+}
+
+/// Title for the steps table naming the run shown and summarising how many
+/// of its steps are done
+fn progress_title(kitchen: &Kitchen) -> String {
+    let done = kitchen
+        .get_steps()
+        .values()
+        .filter(|step| step.get_status() == StepStatus::Done)
+        .count();
+    let run = kitchen
+        .get_run_id()
+        .map(|run_id| format!(" run {}", run_id))
+        .unwrap_or_default();
+    format!(
+        "Recipe{} ({}/{} steps done)",
+        run,
+        done,
+        kitchen.get_steps().len()
+    )
+}
+
+/// Colour used to display a service in the given state
+fn state_color(state: &State) -> Color {
+    match state {
+        State::CREATED | State::IDLE => Color::Gray,
+        State::ISSUED => Color::Yellow,
+        State::EXECUTING => Color::Cyan,
+        State::PAUSED => Color::Magenta,
+        State::SHUTTING_DOWN | State::CANCELLED => Color::DarkGray,
+        State::COMPLETED => Color::Green,
+        State::FAILED(_) => Color::Red,
+    }
+}
@@ -1,13 +1,27 @@
-use std::{thread::sleep, time::Duration};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use common::{
+    clock,
+    control::ControlListener,
+    estimate::Estimate,
     io::{Receiver, Sender},
-    msgs::{CommandDone, CookCommand, CookCommandAck, PrepareCommand, PrepareCommandAck},
+    metrics::{self, CommandEvent},
+    msgs::{
+        CommandDone, Control, ControlAction, CookCommand, CookCommandAck, Heartbeat, Plate,
+        PlateOrder, PrepareCommand, PrepareCommandAck, StepProgress, StockAction, StockReply,
+        StockRequest,
+    },
+    protocol,
     recipe::Recipe,
+    service::Service,
     state::State,
     steps::Step,
+    stock::StockLevel,
     topics::TopicName,
+    trace::TraceSpan,
+    units::format_duration,
 };
+use tracing::{info, info_span, warn, Span};
 
 /// Used within the HeadChefService to track progress executing a recipe
 enum RecipeState {
@@ -27,16 +41,66 @@ enum RecipeState {
     CookDone,
 }
 
+/// Progress reserving the recipe's ingredients from the pantry
+enum StockState {
+    /// Not asked the pantry for the ingredients
+    Unreserved,
+    /// Asked the pantry to reserve the ingredients at the given time,
+    /// awaiting its reply
+    Reserving(Instant),
+    /// The pantry has reserved the ingredients
+    Reserved,
+}
+
+/// Time the head chef spends opening up before working through the recipe
+const OPENING_TIME: Duration = Duration::from_secs(15);
+
+/// Longest the head chef waits for the pantry to reply to a request
+const STOCK_REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Time between checks for the pantry's reply
+const STOCK_POLL: Duration = Duration::from_millis(50);
+
+/// Longest the head chef waits for the expediter to plate the dish once the
+/// last step is done
+const PLATE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Generates an identifier for a new recipe run, unique enough to tell apart
+/// the messages of runs sharing a DDS domain
+fn new_run_id() -> u32 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.subsec_nanos())
+        .unwrap_or_default();
+    nanos ^ std::process::id().rotate_left(16)
+}
+
 /// Head Chef which oversees "cooking" of the given recipe
 ///
 /// This is done by delegating various steps to relevant appliances
 pub struct HeadChefService {
     // recipe management
     recipe: Recipe,
+    recipe_hash: String,
+    estimate: Option<Estimate>,
+    run_id: u32,
     step_index: usize,
-    // service state management
-    service_state: State,
+    command_sent_at: Option<Instant>,
+    // log spans covering the whole run and the current step
+    run_span: Span,
+    step_span: Span,
+    // distributed trace spans covering the whole run and the current step
+    run_trace: Option<TraceSpan>,
+    step_trace: Option<TraceSpan>,
+    // recipe progress management
     recipe_state: RecipeState,
+    control: ControlListener,
+    // ingredients reserved from the pantry, when checking stock
+    check_stock: bool,
+    stock: StockState,
+    // when the last step was done, when waiting for the dish to be plated
+    await_plate: bool,
+    plating_since: Option<Instant>,
     // senders / receivers
     prep_command_sender: Sender<PrepareCommand>,
     prep_command_ack_receiver: Receiver<PrepareCommandAck>,
@@ -44,16 +108,52 @@ pub struct HeadChefService {
     cook_command_sender: Sender<CookCommand>,
     cook_command_ack_receiver: Receiver<CookCommandAck>,
     cook_command_done_receiver: Receiver<CommandDone>,
+    progress_receiver: Receiver<StepProgress>,
+    stock_request_sender: Sender<StockRequest>,
+    stock_reply_receiver: Receiver<StockReply>,
+    plate_order_sender: Sender<PlateOrder>,
+    plate_receiver: Receiver<Plate>,
+    heartbeat_receiver: Receiver<Heartbeat>,
+    control_sender: Sender<Control>,
 }
 
 impl HeadChefService {
     /// Creates a new instance of the execution control service
     pub fn new(recipe: Recipe) -> Self {
+        let run_id = new_run_id();
+        let recipe_hash = recipe.fingerprint();
+        let mut run_trace = TraceSpan::root("run");
+        run_trace.set_attribute("run_id", run_id);
+        run_trace.set_attribute("recipe", recipe.get_title());
+        run_trace.set_attribute("recipe_hash", &recipe_hash);
+        if let Some(version) = recipe.get_version() {
+            run_trace.set_attribute("recipe_version", version);
+        }
+        let run_span = info_span!(
+            "run",
+            run_id,
+            recipe = %recipe.get_title(),
+            recipe_version = recipe.get_version().map(String::as_str),
+            recipe_hash = %recipe_hash,
+            trace_id = %run_trace.context().trace_id_hex()
+        );
         Self {
             recipe,
+            recipe_hash,
+            estimate: None,
+            run_id,
             step_index: 0,
-            service_state: State::CREATED,
+            command_sent_at: None,
+            run_span,
+            step_span: Span::none(),
+            run_trace: Some(run_trace),
+            step_trace: None,
             recipe_state: RecipeState::Initial,
+            control: ControlListener::new(),
+            check_stock: false,
+            stock: StockState::Unreserved,
+            await_plate: false,
+            plating_since: None,
             // senders / receivers instantiation
             prep_command_sender: Sender::new(TopicName::PrepareCommand.to_string(), None),
             prep_command_ack_receiver: Receiver::new(
@@ -67,141 +167,948 @@ impl HeadChefService {
             cook_command_sender: Sender::new(TopicName::CookCommand.to_string(), None),
             cook_command_ack_receiver: Receiver::new(TopicName::CookCommandAck.to_string(), None),
             cook_command_done_receiver: Receiver::new(TopicName::CookCommandDone.to_string(), None),
+            progress_receiver: Receiver::new(TopicName::StepProgress.to_string(), None),
+            stock_request_sender: Sender::new(TopicName::StockRequest.to_string(), None),
+            stock_reply_receiver: Receiver::new(TopicName::StockReply.to_string(), None),
+            plate_order_sender: Sender::new(TopicName::PlateOrder.to_string(), None),
+            plate_receiver: Receiver::new(TopicName::Plate.to_string(), None),
+            heartbeat_receiver: Receiver::new(TopicName::Heartbeat.to_string(), None),
+            control_sender: Sender::new(TopicName::Control.to_string(), None),
         }
     }
 
-    /// Returns boolean indicating completion status of the service
-    pub fn check_completed(&self) -> bool {
-        matches!(self.service_state, State::COMPLETED)
-    }
-    /// Returns option indicating whether the service failed while "cooking"
-    /// ### Returns
-    /// - `None` if service is not in `FAILED` state
-    /// - `Some(message)` describing the failure if `FAILED`
-    pub fn check_failed(&self) -> Option<&str> {
-        if let State::FAILED(error_message) = &self.service_state {
-            return Some(error_message);
-        }
-        None
+    /// Reserves the whole recipe's ingredients from the pantry before
+    /// starting, failing the run if any are missing
+    pub fn with_stock_check(mut self) -> Self {
+        self.check_stock = true;
+        self
     }
 
-    /// Work to be carried out each iteration of the service
-    pub fn cycle(&mut self) {
-        match &self.service_state {
-            // head chef is automatically issued upon creation
-            State::CREATED => self.service_state = State::ISSUED,
-            State::ISSUED => self.on_issued(),
-            State::EXECUTING => self.on_executing(),
-            // shouldn't enter cycle() while COMPLETED
-            State::COMPLETED => (),
-            // perhaps some future handle failure method?
-            State::FAILED(_e) => (),
-        }
+    /// Only completes the run once the expediter has plated the dish, failing
+    /// it if no plate arrives in time
+    pub fn with_plating(mut self) -> Self {
+        self.await_plate = true;
+        self
     }
 
-    /// After service creation, begin initialization prior to preparing recipe
-    fn on_issued(&mut self) {
-        println!("Head chef opening up the restaurant for the evening");
-        sleep(Duration::from_secs(15));
-        println!(
-            "The recipe for tonight appears to be {}",
-            self.recipe.get_title()
-        );
-        self.service_state = State::EXECUTING;
+    /// Returns the identifier of the run this service carries out
+    pub fn get_run_id(&self) -> u32 {
+        self.run_id
     }
 
-    /// After initialization, delegate necessary preparation and cooking tasks
-    fn on_executing(&mut self) {
+    /// Works through the recipe's steps from the given state, either
+    /// `EXECUTING` or `PAUSED`, returning the state to move to
+    ///
+    /// While paused, commands already sent are followed through, but no new
+    /// step is started and the recipe is not completed.
+    fn advance(&mut self, current: State) -> Result<State, String> {
+        self.check_peers()?;
+        let paused = current == State::PAUSED;
+        let _step = self.step_span.clone().entered();
         // check if done with recipe steps yet or not
         let curr_step = match self.recipe.get_steps().get(self.step_index) {
             // recipe completed case
-            None => {
-                self.service_state = State::COMPLETED;
-                return;
-            }
+            None if paused => return Ok(current),
+            None => return self.finish(),
             Some(step) => step,
         };
+        let starts_command = matches!(
+            self.recipe_state,
+            RecipeState::Initial | RecipeState::PrepCmd | RecipeState::CookCmd
+        );
+        if paused && starts_command {
+            return Ok(current);
+        }
 
         match self.recipe_state {
             RecipeState::Initial => {
+                // where the step was written, for steps expanded from includes
+                let origin = self
+                    .recipe
+                    .get_origins()
+                    .get(self.step_index)
+                    .map(|origin| origin.to_string());
+                // parented explicitly, as the previous step's span is entered
+                self.step_span = info_span!(
+                    parent: &self.run_span,
+                    "step",
+                    command_id = self.step_index,
+                    item = %curr_step.item(),
+                    kind = curr_step.kind(),
+                    origin = origin.as_deref()
+                );
+                let mut step_trace = TraceSpan::child_of(
+                    curr_step.kind(),
+                    &self.run_trace.as_ref().unwrap().context(),
+                );
+                step_trace.set_attribute("command_id", self.step_index);
+                step_trace.set_attribute("item", curr_step.item());
+                self.step_trace = Some(step_trace);
                 // transition to appropriate state based on first step
                 self.recipe_state = match curr_step {
                     Step::Prepare(_) => RecipeState::PrepCmd,
-                    Step::Cook(_, _) => RecipeState::CookCmd,
+                    Step::Cook(_, _, _) => RecipeState::CookCmd,
                 };
             }
             RecipeState::PrepCmd => {
                 // extract item from step and create command
                 let prep_command = match curr_step {
-                    Step::Prepare(item) => PrepareCommand::new(*item),
+                    Step::Prepare(item) => PrepareCommand::new(
+                        self.run_id,
+                        self.recipe_hash.clone(),
+                        self.step_index as u32,
+                        *item,
+                        self.step_trace.as_ref().unwrap().context(),
+                    ),
                     _ => {
-                        println!("Executing state didn't match current step type");
+                        warn!("Executing state didn't match current step type");
                         self.recipe_state = RecipeState::Initial;
-                        return;
+                        return Ok(current);
                     }
                 };
 
-                println!("Assessing requried preparation tasks");
+                info!("Assessing required preparation tasks");
                 self.recipe_state = RecipeState::PrepAck;
                 // send out command
                 self.prep_command_sender
                     .send(prep_command)
-                    .unwrap_or_else(|e| {
-                        self.service_state =
-                            State::FAILED(format!("Failed to send prep command: {}", e));
-                    });
+                    .map_err(|e| format!("Failed to send prep command: {}", e))?;
+                self.command_sent();
             }
             RecipeState::PrepAck => {
                 // check to receive ack
-                if let Some(_ack) = self.prep_command_ack_receiver.receive() {
+                if self.replied(|chef| {
+                    let ack = chef.prep_command_ack_receiver.try_receive()?;
+                    Ok(ack.map(|ack| (ack.get_run_id(), ack.get_command_id())))
+                })? {
+                    self.command_acked();
                     self.recipe_state = RecipeState::PrepDone;
-                    println!("Preparation tasks assigned to another chef");
+                    info!("Preparation tasks assigned to another chef");
                 }
             }
             RecipeState::PrepDone => {
+                self.check_progress();
                 // check for completed message
-                if let Some(_ack) = self.prep_command_done_receiver.receive() {
+                if self.replied(|chef| {
+                    let done = chef.prep_command_done_receiver.try_receive()?;
+                    Ok(done.map(|done| (done.get_run_id(), done.get_command_id())))
+                })? {
+                    self.command_completed();
+                    self.step_trace.take().unwrap().end();
                     self.step_index += 1;
                     self.recipe_state = RecipeState::Initial;
-                    println!("Preparations complete!");
+                    info!("Preparations complete!");
                 }
             }
             RecipeState::CookCmd => {
                 // extract item and duration from step and create command
                 let cook_command = match curr_step {
-                    Step::Cook(item, time) => CookCommand::new(*item, *time),
+                    Step::Cook(item, time, temperature) => {
+                        if let Some(temperature) = temperature {
+                            info!(
+                                celsius = temperature.celsius(),
+                                "Cooking at {}", temperature
+                            );
+                        }
+                        CookCommand::new(
+                            self.run_id,
+                            self.recipe_hash.clone(),
+                            self.step_index as u32,
+                            *item,
+                            *time,
+                            *temperature,
+                            self.step_trace.as_ref().unwrap().context(),
+                        )
+                    }
                     _ => {
-                        println!("Executing state didn't match current step type");
+                        warn!("Executing state didn't match current step type");
                         self.recipe_state = RecipeState::Initial;
-                        return;
+                        return Ok(current);
                     }
                 };
 
                 self.recipe_state = RecipeState::CookAck;
-                println!("Assessing requried cooking tasks");
+                info!("Assessing required cooking tasks");
                 // send out command
                 self.cook_command_sender
                     .send(cook_command)
-                    .unwrap_or_else(|e| {
-                        self.service_state =
-                            State::FAILED(format!("Failed to send cook command: {}", e));
-                    });
+                    .map_err(|e| format!("Failed to send cook command: {}", e))?;
+                self.command_sent();
             }
             RecipeState::CookAck => {
                 // check to receive ack
-                if let Some(_ack) = self.cook_command_ack_receiver.receive() {
+                if self.replied(|chef| {
+                    let ack = chef.cook_command_ack_receiver.try_receive()?;
+                    Ok(ack.map(|ack| (ack.get_run_id(), ack.get_command_id())))
+                })? {
+                    self.command_acked();
                     self.recipe_state = RecipeState::CookDone;
-                    println!("Cooking tasks assigned to another chef");
+                    info!("Cooking tasks assigned to another chef");
                 }
             }
             RecipeState::CookDone => {
+                self.check_progress();
                 // check for completed message
-                if let Some(_ack) = self.cook_command_done_receiver.receive() {
+                if self.replied(|chef| {
+                    let done = chef.cook_command_done_receiver.try_receive()?;
+                    Ok(done.map(|done| (done.get_run_id(), done.get_command_id())))
+                })? {
+                    self.command_completed();
+                    self.step_trace.take().unwrap().end();
                     self.step_index += 1;
                     self.recipe_state = RecipeState::Initial;
-                    println!("Cooking complete!");
+                    info!("Cooking complete!");
+                }
+            }
+        }
+        Ok(current)
+    }
+
+    /// Completes the run once every step is done, first waiting for the dish
+    /// to be plated if asked to
+    fn finish(&mut self) -> Result<State, String> {
+        if !self.await_plate {
+            return Ok(State::COMPLETED);
+        }
+        let since = *self.plating_since.get_or_insert_with(|| {
+            info!("Waiting for the dish to be plated");
+            clock::now()
+        });
+        while let Some(plate) = self.plate_receiver.receive() {
+            if plate.get_run_id() != self.run_id {
+                continue;
+            }
+            for component in plate.get_components() {
+                let held = format_duration(component.get_held());
+                if component.is_cold() {
+                    warn!(
+                        "{} plated cold, after being held for {}",
+                        component.get_item(),
+                        held
+                    );
+                } else {
+                    info!(
+                        "{} plated after being held for {}",
+                        component.get_item(),
+                        held
+                    );
+                }
+            }
+            return Ok(State::COMPLETED);
+        }
+        if clock::since(since) > PLATE_TIMEOUT {
+            return Err("No plate from the expediter".to_string());
+        }
+        Ok(State::EXECUTING)
+    }
+
+    /// Whether a message about the given command is for the current step of
+    /// this run, as other runs share the same topics
+    fn is_for_step(&self, run_id: u32, command_id: u32) -> bool {
+        run_id == self.run_id && command_id == self.step_index as u32
+    }
+
+    /// Takes replies through `receive`, giving the run and command each is
+    /// about, until one is for the current step, returning whether one was.
+    /// Fails on any reply which can't be read.
+    fn replied(
+        &mut self,
+        mut receive: impl FnMut(&mut Self) -> Result<Option<(u32, u32)>, String>,
+    ) -> Result<bool, String> {
+        while let Some((run_id, command_id)) = receive(self)? {
+            if self.is_for_step(run_id, command_id) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Refuses to run alongside any service advertising, through its
+    /// heartbeats, protocol versions incompatible with this one's
+    fn check_peers(&mut self) -> Result<(), String> {
+        let refuse = |e| format!("Refusing to run: {}", e);
+        while let Some(heartbeat) = self.heartbeat_receiver.try_receive().map_err(refuse)? {
+            protocol::check_peer(heartbeat.get_service(), heartbeat.get_protocols())
+                .map_err(refuse)?;
+        }
+        Ok(())
+    }
+
+    /// Drains progress reports for the current step, printing the latest one
+    /// along with an estimate of the time remaining in the overall recipe
+    fn check_progress(&mut self) {
+        let mut latest = None;
+        while let Some(progress) = self.progress_receiver.receive() {
+            // ignore stale reports from previous steps
+            if self.is_for_step(progress.get_run_id(), progress.get_command_id()) {
+                latest = Some(progress);
+            }
+        }
+        if let Some(progress) = latest {
+            info!(
+                percent = progress.get_percent(),
+                phase = %progress.get_phase(),
+                step_remaining_secs = progress.get_remaining().as_secs(),
+                recipe_eta_secs = self.recipe_eta(progress.get_remaining()).as_secs(),
+                "Step progress"
+            );
+        }
+    }
+
+    /// Estimates the time left in the recipe given the time left in the
+    /// current step, as steps are dispatched one after another
+    fn recipe_eta(&self, step_remaining: Duration) -> Duration {
+        let Some(estimate) = &self.estimate else {
+            return step_remaining;
+        };
+        estimate
+            .get_steps()
+            .iter()
+            .skip(self.step_index + 1)
+            .fold(step_remaining, |eta, step| {
+                eta.saturating_add(step.get_duration())
+            })
+    }
+
+    /// Asks the pantry to take the given action on this run's ingredients
+    fn request_stock(&mut self, action: StockAction) -> Result<(), String> {
+        let levels = match action {
+            StockAction::QUERY | StockAction::RESERVE => StockLevel::for_recipe(&self.recipe),
+            StockAction::CONSUME | StockAction::RELEASE => Vec::new(),
+        };
+        self.stock_request_sender
+            .send(StockRequest::new(self.run_id, action, levels))
+            .map_err(|e| format!("Failed to send stock request: {}", e))
+    }
+
+    /// Reserves the recipe's ingredients, returning whether the pantry has
+    /// reserved them yet, and failing with a list of those there is not
+    /// enough of
+    fn reserve_stock(&mut self) -> Result<bool, String> {
+        let since = match self.stock {
+            StockState::Reserved => return Ok(true),
+            StockState::Reserving(since) => since,
+            StockState::Unreserved => {
+                self.request_stock(StockAction::RESERVE)?;
+                // the pantry may reserve the ingredients even if its reply is
+                // lost, so always release them should the run not go ahead
+                let since = clock::now();
+                self.stock = StockState::Reserving(since);
+                since
+            }
+        };
+        while let Some(reply) = self.stock_reply_receiver.receive() {
+            if reply.get_run_id() != self.run_id || reply.get_action() != StockAction::RESERVE {
+                continue;
+            }
+            if !reply.get_shortfalls().is_empty() {
+                let missing: Vec<String> = reply
+                    .get_shortfalls()
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect();
+                return Err(format!("Missing ingredients: {}", missing.join(", ")));
+            }
+            self.stock = StockState::Reserved;
+            info!("Ingredients reserved from the pantry");
+            return Ok(true);
+        }
+        if clock::since(since) > STOCK_REPLY_TIMEOUT {
+            return Err(format!(
+                "No reply from the pantry to {} ingredients",
+                StockAction::RESERVE
+            ));
+        }
+        clock::sleep(STOCK_POLL);
+        Ok(false)
+    }
+
+    /// Has the pantry consume or release the ingredients reserved for this
+    /// run, if any were asked for
+    fn settle_stock(&mut self, action: StockAction) {
+        if let StockState::Unreserved = std::mem::replace(&mut self.stock, StockState::Unreserved) {
+            return;
+        }
+        // both are idempotent, so no reply is waited for
+        match self.request_stock(action) {
+            Ok(()) => info!("Asked the pantry to {} the reserved ingredients", action),
+            Err(e) => warn!("Unable to {} the reserved ingredients: {}", action, e),
+        }
+    }
+
+    /// Counts a run of this service's recipe reaching the given outcome
+    fn record_run(&self, outcome: &str) {
+        metrics::inc(
+            "chef_recipe_runs_total",
+            "Recipe runs started, completed and failed, per recipe",
+            &[("recipe", self.recipe.get_title()), ("outcome", outcome)],
+        );
+    }
+
+    /// Records metrics for the current step's command having been sent
+    fn command_sent(&mut self) {
+        self.command_sent_at = Some(clock::now());
+        let step = &self.recipe.get_steps()[self.step_index];
+        metrics::record_command(CommandEvent::Sent, step.item(), step.kind());
+    }
+
+    /// Records metrics for the current command having been acknowledged
+    fn command_acked(&self) {
+        let step = &self.recipe.get_steps()[self.step_index];
+        metrics::record_command(CommandEvent::Acked, step.item(), step.kind());
+        if let Some(sent_at) = self.command_sent_at {
+            metrics::observe_ack_latency(step.item(), step.kind(), clock::since(sent_at));
+        }
+    }
+
+    /// Records metrics for the current command having been completed
+    fn command_completed(&self) {
+        let step = &self.recipe.get_steps()[self.step_index];
+        metrics::record_command(CommandEvent::Completed, step.item(), step.kind());
+        if let Some(sent_at) = self.command_sent_at {
+            metrics::observe_step_duration(step.item(), step.kind(), clock::since(sent_at));
+        }
+    }
+
+    /// Ends the trace spans of the current step and the run, marking them with
+    /// the given error if the run failed
+    fn end_traces(&mut self, error: Option<String>) {
+        for mut trace in [self.step_trace.take(), self.run_trace.take()]
+            .into_iter()
+            .flatten()
+        {
+            if let Some(error) = &error {
+                trace.set_attribute("error", error);
+            }
+            trace.end();
+        }
+    }
+}
+
+impl Service for HeadChefService {
+    const KIND: &'static str = "head-chef";
+
+    fn span(&self) -> Span {
+        self.run_span.clone()
+    }
+
+    /// Head chef is automatically issued upon creation
+    fn on_created(&mut self) -> Result<State, String> {
+        Ok(State::ISSUED)
+    }
+
+    /// After service creation, begin initialization prior to preparing recipe
+    fn on_issued(&mut self) -> Result<State, String> {
+        // fail before anything else if ingredients are missing, waiting on
+        // the pantry over as many cycles as it takes to reply
+        if self.check_stock && !self.reserve_stock()? {
+            return Ok(State::ISSUED);
+        }
+        info!("Head chef opening up the restaurant for the evening");
+        clock::sleep(OPENING_TIME);
+        self.check_peers()?;
+        info!(
+            "The recipe for tonight appears to be {}",
+            self.recipe.get_title()
+        );
+        let estimate = self.recipe.estimate()?;
+        info!(
+            estimate_secs = estimate.get_sequential().as_secs(),
+            critical_path_secs = estimate.get_total().as_secs(),
+            "Expecting the recipe to take {}",
+            format_duration(estimate.get_sequential())
+        );
+        self.estimate = Some(estimate);
+        self.record_run("started");
+        // let the expediter know what makes up the dish
+        let items = self.recipe.get_steps().iter().map(Step::item).collect();
+        self.plate_order_sender
+            .send(PlateOrder::new(
+                self.run_id,
+                self.recipe.get_title().clone(),
+                items,
+            ))
+            .map_err(|e| format!("Failed to send plate order: {}", e))?;
+        Ok(State::EXECUTING)
+    }
+
+    /// After initialization, delegate necessary preparation and cooking tasks
+    fn on_executing(&mut self) -> Result<State, String> {
+        match self.control.latest(self.run_id) {
+            Some(ControlAction::PAUSE) => {
+                info!("Pausing, no new commands will be sent until resumed");
+                Ok(State::PAUSED)
+            }
+            Some(ControlAction::CANCEL) => {
+                info!("Cancelling the recipe");
+                Ok(State::SHUTTING_DOWN)
+            }
+            _ => self.advance(State::EXECUTING),
+        }
+    }
+
+    /// While paused, follow through on commands already sent but send no more
+    fn on_paused(&mut self) -> Result<State, String> {
+        match self.control.latest(self.run_id) {
+            Some(ControlAction::RESUME) => {
+                info!("Resuming the recipe");
+                Ok(State::EXECUTING)
+            }
+            Some(ControlAction::CANCEL) => {
+                info!("Cancelling the recipe");
+                Ok(State::SHUTTING_DOWN)
+            }
+            _ => self.advance(State::PAUSED),
+        }
+    }
+
+    /// Counts the run as completed on the cycle it finishes in
+    fn on_completed(&mut self) {
+        self.record_run("completed");
+        self.end_traces(None);
+        self.settle_stock(StockAction::CONSUME);
+    }
+
+    /// Counts the run as cancelled, ending its traces and returning its
+    /// ingredients to the pantry
+    fn on_cancelled(&mut self) {
+        self.end_traces(Some("cancelled".to_string()));
+        self.record_run("cancelled");
+        self.settle_stock(StockAction::RELEASE);
+    }
+
+    /// Counts the run, and the step it was on, as failed, returning its
+    /// ingredients to the pantry
+    fn on_failed(&mut self, reason: &str) {
+        self.end_traces(Some(reason.to_string()));
+        self.record_run("failed");
+        if let Some(step) = self.recipe.get_steps().get(self.step_index) {
+            metrics::record_command(CommandEvent::Failed, step.item(), step.kind());
+        }
+        self.settle_stock(StockAction::RELEASE);
+        // cancel the run, so worker chefs drop its commands and the expediter
+        // gives up on its dish
+        let cancel = Control::new(Some(self.run_id), ControlAction::CANCEL);
+        if let Err(e) = self.control_sender.send(cancel) {
+            warn!("Unable to cancel the failed run: {}", e);
+        }
+    }
+}
+
+/// Test module for the head chef, run against simulated worker chefs
+#[cfg(test)]
+mod head_chef_tests {
+    use std::time::Duration;
+
+    use common::{
+        ingredients::{Quantity, Unit},
+        io::Sender,
+        msgs::{Control, ControlAction, CookCommand, Heartbeat, PrepareCommand},
+        progress::DEFAULT_PROGRESS_INTERVAL,
+        protocol::PROTOCOL_VERSION,
+        recipe::Recipe,
+        service::Service,
+        sim::{SimMessage, Simulation},
+        state::State,
+        steps::{FoodItem, Step},
+        stock::{Stock, StockLevel},
+        strategies,
+        timings::{gathering_time, preparing_time},
+        topics::TopicName,
+        trace::TraceSpan,
+    };
+    use cook_chef::CookChefService;
+    use pantry::PantryService;
+    use prep_chef::PrepChefService;
+    use proptest::prelude::*;
+    use serde_json::json;
+    use tracing::Span;
+
+    use crate::head_chef_service::{HeadChefService, OPENING_TIME};
+
+    /// Every recipe in the repository, leaving out sub-recipes
+    fn recipe_files() -> Vec<String> {
+        let mut files: Vec<String> = std::fs::read_dir("../recipes")
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            // sketches a future recipe format rather than being a recipe
+            .filter(|path| path.is_file() && !path.ends_with("concept.yaml"))
+            .map(|path| path.display().to_string())
+            .collect();
+        files.sort();
+        files
+    }
+
+    /// Runs the recipe at the given path with the given numbers of prep and
+    /// cook chefs, returning the head chef's final state, the time and topic
+    /// of every message sent, and the time the run took
+    fn simulate(
+        path: &str,
+        prep_chefs: usize,
+        cook_chefs: usize,
+        seed: u64,
+    ) -> (State, Vec<(Duration, String)>, Duration) {
+        let mut sim = Simulation::new(seed);
+        let head_chef = sim.add(HeadChefService::new(Recipe::from_file(path).unwrap()));
+        for _ in 0..prep_chefs {
+            sim.add_restarting(|| PrepChefService::new(DEFAULT_PROGRESS_INTERVAL));
+        }
+        for _ in 0..cook_chefs {
+            sim.add_restarting(|| CookChefService::new(DEFAULT_PROGRESS_INTERVAL));
+        }
+        let elapsed = sim.run(Duration::from_secs(600)).unwrap();
+        let messages = sim
+            .get_messages()
+            .iter()
+            .map(|msg| (msg.get_at(), msg.get_topic().to_string()))
+            .collect();
+        (sim.get_state(head_chef).clone(), messages, elapsed)
+    }
+
+    /// Checks every recipe completes with a command, ack and done for each
+    /// step in turn, taking at least the time spent working on the steps and
+    /// no longer than estimated
+    #[test]
+    fn recipes_complete() {
+        for path in recipe_files() {
+            let (state, messages, elapsed) = simulate(&path, 1, 1, 7);
+            assert_eq!(state, State::COMPLETED, "{}", path);
+
+            let recipe = Recipe::from_file(&path).unwrap();
+            let mut expected = Vec::new();
+            let mut working = Duration::ZERO;
+            for step in recipe.get_steps() {
+                let kind = match step {
+                    Step::Prepare(item) => {
+                        working += gathering_time(*item) + preparing_time(*item);
+                        "prepare"
+                    }
+                    Step::Cook(_, time, _) => {
+                        working += *time;
+                        "cook"
+                    }
+                };
+                for suffix in ["", "_ack", "_done"] {
+                    expected.push(format!("{}_command{}", kind, suffix));
                 }
             }
+            let commands: Vec<String> = messages
+                .into_iter()
+                .map(|(_, topic)| topic)
+                .filter(|topic| topic.contains("_command"))
+                .collect();
+            assert_eq!(commands, expected, "{}", path);
+
+            // allowing a second per step for messages to be passed on
+            let slack = Duration::from_secs(recipe.get_steps().len() as u64);
+            assert!(elapsed >= OPENING_TIME + working, "{}", path);
+            assert!(
+                elapsed <= OPENING_TIME + recipe.estimate().unwrap().get_sequential() + slack,
+                "{}: took {:?}",
+                path,
+                elapsed
+            );
+        }
+    }
+
+    /// Checks a run is refused once a chef advertises protocol versions it
+    /// can't be worked with, before any command is sent
+    #[test]
+    fn refuses_incompatible_chefs() {
+        let mut sim = Simulation::new(0);
+        let recipe = Recipe::from_file("../recipes/base_output.yaml").unwrap();
+        let head_chef = sim.add(HeadChefService::new(recipe));
+        let heartbeat: Heartbeat = serde_json::from_value(json!({
+            "service": "prep-chef-9",
+            "state": "IDLE",
+            "history": [],
+            "protocols": [PROTOCOL_VERSION + 1]
+        }))
+        .unwrap();
+        Sender::new(TopicName::Heartbeat.to_string(), None)
+            .send(heartbeat)
+            .unwrap();
+        sim.run(Duration::from_secs(60)).unwrap();
+
+        let State::FAILED(reason) = sim.get_state(head_chef) else {
+            panic!("Run wasn't refused: {:?}", sim.get_state(head_chef));
+        };
+        assert!(
+            reason.starts_with("Refusing to run: prep-chef-9"),
+            "{}",
+            reason
+        );
+        assert!(!sim
+            .get_messages()
+            .iter()
+            .any(|msg| msg.get_topic().ends_with("_command")));
+    }
+
+    /// Pantry holding the given number of grams of salmon
+    fn salmon_pantry(grams: f64) -> PantryService {
+        let salmon = StockLevel::new(FoodItem::SALMON, Quantity::new(grams, Unit::GRAMS));
+        PantryService::new(Stock::new(vec![salmon]))
+    }
+
+    /// Checks a run fails before sending any command when the pantry lacks
+    /// its ingredients
+    #[test]
+    fn missing_ingredients() {
+        let mut sim = Simulation::new(0);
+        let recipe = Recipe::from_file("../recipes/salmon_for_two.yaml").unwrap();
+        let head_chef = sim.add(HeadChefService::new(recipe).with_stock_check());
+        sim.add_restarting(|| salmon_pantry(200.0));
+        sim.run(Duration::from_secs(60)).unwrap();
+
+        let State::FAILED(reason) = sim.get_state(head_chef) else {
+            panic!("Run wasn't failed: {:?}", sim.get_state(head_chef));
         };
+        assert!(
+            reason.starts_with("Missing ingredients: Salmon"),
+            "{}",
+            reason
+        );
+        assert!(!sim
+            .get_messages()
+            .iter()
+            .any(|msg| msg.get_topic().ends_with("_command")));
+    }
+
+    /// Checks a cancelled run returns the ingredients it reserved, leaving
+    /// enough for the next run to complete
+    #[test]
+    fn cancelled_run_releases_stock() {
+        let mut sim = Simulation::new(0);
+        let recipe = || Recipe::from_file("../recipes/salmon_for_two.yaml").unwrap();
+        sim.add_restarting(|| salmon_pantry(400.0));
+        sim.add_restarting(|| PrepChefService::new(DEFAULT_PROGRESS_INTERVAL));
+        sim.add_restarting(|| CookChefService::new(DEFAULT_PROGRESS_INTERVAL));
+
+        let cancelled = HeadChefService::new(recipe()).with_stock_check();
+        Sender::new(TopicName::Control.to_string(), None)
+            .send(Control::new(
+                Some(cancelled.get_run_id()),
+                ControlAction::CANCEL,
+            ))
+            .unwrap();
+        let cancelled = sim.add(cancelled);
+        sim.run(Duration::from_secs(60)).unwrap();
+        assert_eq!(sim.get_state(cancelled), &State::CANCELLED);
+
+        let next = sim.add(HeadChefService::new(recipe()).with_stock_check());
+        sim.run(Duration::from_secs(120)).unwrap();
+        assert_eq!(sim.get_state(next), &State::COMPLETED);
+    }
+
+    /// Checks worker chefs holding commands of a paused run drop them once
+    /// the run is cancelled, awaiting further commands without finishing them
+    #[test]
+    fn paused_run_cancelled() {
+        let mut sim = Simulation::new(0);
+        let prep_chef = sim.add(PrepChefService::new(DEFAULT_PROGRESS_INTERVAL));
+        let cook_chef = sim.add(CookChefService::new(DEFAULT_PROGRESS_INTERVAL));
+        let trace = TraceSpan::root("run").context();
+        Sender::new(TopicName::PrepareCommand.to_string(), None)
+            .send(PrepareCommand::new(
+                7,
+                "abc123".to_string(),
+                0,
+                FoodItem::SALMON,
+                trace,
+            ))
+            .unwrap();
+        Sender::new(TopicName::CookCommand.to_string(), None)
+            .send(CookCommand::new(
+                7,
+                "abc123".to_string(),
+                1,
+                FoodItem::CHICKEN,
+                Duration::from_secs(60),
+                None,
+                trace,
+            ))
+            .unwrap();
+        let control = |action| {
+            Sender::new(TopicName::Control.to_string(), None)
+                .send(Control::new(Some(7), action))
+                .unwrap()
+        };
+
+        // a cycle each to be created and take the commands
+        sim.run_for(Duration::from_millis(100));
+        control(ControlAction::PAUSE);
+        sim.run_for(Duration::from_secs(30));
+        assert_eq!(sim.get_state(prep_chef), &State::PAUSED);
+        assert_eq!(sim.get_state(cook_chef), &State::PAUSED);
+
+        control(ControlAction::CANCEL);
+        sim.run_for(Duration::from_secs(90));
+        assert_eq!(sim.get_state(prep_chef), &State::IDLE);
+        assert_eq!(sim.get_state(cook_chef), &State::IDLE);
+        assert!(!sim
+            .get_messages()
+            .iter()
+            .any(|msg| msg.get_topic().ends_with("_done")));
+    }
+
+    /// Checks runs with several chefs of each kind complete whatever the
+    /// interleaving, which is the same each time for the same seed
+    #[test]
+    fn interleavings() {
+        let path = "../recipes/surf_and_turf.yaml";
+        for seed in 0..4 {
+            let (state, messages, elapsed) = simulate(path, 2, 2, seed);
+            assert_eq!(state, State::COMPLETED, "seed {}", seed);
+            assert_eq!(
+                simulate(path, 2, 2, seed),
+                (state, messages, elapsed),
+                "seed {}",
+                seed
+            );
+        }
+    }
+
+    /// Worker chef failing on first entering a state, if given one
+    struct Flaky<S> {
+        service: S,
+        fail_at: Option<State>,
+    }
+
+    impl<S: Service> Flaky<S> {
+        /// Fails the service if it has just entered the state it fails at
+        fn check(&mut self, state: State) -> Result<(), String> {
+            match self.fail_at.take_if(|fail_at| *fail_at == state) {
+                Some(_) => Err(format!("Injected failure on entering {:?}", state)),
+                None => Ok(()),
+            }
+        }
+    }
+
+    impl<S: Service> Service for Flaky<S> {
+        const KIND: &'static str = S::KIND;
+
+        fn span(&self) -> Span {
+            self.service.span()
+        }
+        fn on_created(&mut self) -> Result<State, String> {
+            self.service.on_created()
+        }
+        fn on_idle(&mut self) -> Result<State, String> {
+            self.service.on_idle()
+        }
+        fn on_issued(&mut self) -> Result<State, String> {
+            self.check(State::ISSUED)?;
+            self.service.on_issued()
+        }
+        fn on_executing(&mut self) -> Result<State, String> {
+            self.check(State::EXECUTING)?;
+            self.service.on_executing()
+        }
+        fn on_paused(&mut self) -> Result<State, String> {
+            self.service.on_paused()
+        }
+        fn on_failed(&mut self, reason: &str) {
+            self.service.on_failed(reason)
+        }
+        fn on_shutdown(&mut self) {
+            self.service.on_shutdown()
+        }
+    }
+
+    /// Where each worker chef started in turn fails, if it does, with
+    /// chefs started after the last in the plan never failing
+    fn failure_plan() -> impl Strategy<Value = Vec<Option<State>>> {
+        let failure = prop_oneof![
+            2 => Just(None),
+            1 => Just(Some(State::ISSUED)),
+            1 => Just(Some(State::EXECUTING)),
+        ];
+        prop::collection::vec(failure, 0..6)
+    }
+
+    /// Runs the recipe with a prep and a cook chef, each failing as planned
+    /// when restarted, returning the head chef's final state and every message
+    /// sent
+    fn simulate_flaky(
+        recipe: Recipe,
+        prep_plan: Vec<Option<State>>,
+        cook_plan: Vec<Option<State>>,
+    ) -> (State, Vec<SimMessage>) {
+        // a run stalls once a command goes unanswered, so no longer than
+        // expected is waited for one to finish
+        let limit = OPENING_TIME
+            + recipe.estimate().unwrap().get_sequential()
+            + Duration::from_secs(recipe.get_steps().len() as u64 + 10);
+        let mut sim = Simulation::new(0);
+        let head_chef = sim.add(HeadChefService::new(recipe));
+        let mut prep_plan = prep_plan.into_iter();
+        sim.add_restarting(move || Flaky {
+            service: PrepChefService::new(DEFAULT_PROGRESS_INTERVAL),
+            fail_at: prep_plan.next().flatten(),
+        });
+        let mut cook_plan = cook_plan.into_iter();
+        sim.add_restarting(move || Flaky {
+            service: CookChefService::new(DEFAULT_PROGRESS_INTERVAL),
+            fail_at: cook_plan.next().flatten(),
+        });
+        let _ = sim.run(limit);
+        (sim.get_state(head_chef).clone(), sim.get_messages())
+    }
+
+    /// Ids of the commands reported done, each once, in order
+    fn done_ids(messages: &[SimMessage]) -> Vec<u64> {
+        let mut ids: Vec<u64> = messages
+            .iter()
+            .filter(|msg| msg.get_topic().ends_with("_command_done"))
+            .map(|msg| msg.get_body()["command_id"].as_u64().unwrap())
+            .collect();
+        ids.sort();
+        ids.dedup();
+        ids
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        /// Checks any recipe completes with workers which always succeed,
+        /// sending exactly one command for each step, for its item
+        #[test]
+        fn any_recipe_completes(
+            recipe in strategies::recipe(1..6, Duration::from_secs(300)),
+        ) {
+            let steps = recipe.get_steps().clone();
+            let (state, messages) = simulate_flaky(recipe, Vec::new(), Vec::new());
+            prop_assert_eq!(state, State::COMPLETED);
+
+            let commands: Vec<&SimMessage> = messages
+                .iter()
+                .filter(|msg| msg.get_topic().ends_with("_command"))
+                .collect();
+            prop_assert_eq!(commands.len(), steps.len());
+            for (i, (command, step)) in commands.iter().zip(&steps).enumerate() {
+                let item = match step {
+                    Step::Prepare(item) | Step::Cook(item, _, _) => item,
+                };
+                prop_assert_eq!(command.get_topic(), format!("{}_command", step.kind()));
+                prop_assert_eq!(&command.get_body()["id"], i);
+                let name = format!("{:?}", item);
+                prop_assert_eq!(&command.get_body()["item"], name.as_str());
+            }
+        }
+
+        /// Checks a run never completes without a done for every step when
+        /// workers fail at random
+        #[test]
+        fn no_completion_without_dones(
+            recipe in strategies::recipe(1..6, Duration::from_secs(300)),
+            prep_plan in failure_plan(),
+            cook_plan in failure_plan(),
+        ) {
+            let steps = recipe.get_steps().len() as u64;
+            let (state, messages) = simulate_flaky(recipe, prep_plan, cook_plan);
+            if state == State::COMPLETED {
+                prop_assert_eq!(done_ids(&messages), (0..steps).collect::<Vec<u64>>());
+            }
+        }
     }
 }
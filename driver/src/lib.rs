@@ -0,0 +1,3 @@
+mod head_chef_service;
+
+pub use crate::head_chef_service::HeadChefService;
@@ -1,8 +1,53 @@
-mod head_chef_service;
-use std::{env, thread::sleep, time::Duration};
+use std::{
+    collections::BTreeMap,
+    env, io,
+    thread::{self, sleep},
+    time::Duration,
+};
 
-use common::recipe::Recipe;
-use head_chef_service::HeadChefService;
+use common::{
+    faults,
+    io::Sender,
+    logging, metrics,
+    msgs::{Control, ControlAction},
+    recipe::Recipe,
+    service::ServiceRunner,
+    state::State,
+    topics::TopicName,
+    trace,
+};
+use driver::HeadChefService;
+use tracing::{error, info, warn};
+
+/// Publishes the control actions typed into the terminal, one per line, e.g.
+/// `pause` or `resume`, for the given run
+fn forward_controls(run_id: u32) {
+    let sender = Sender::new(TopicName::Control.to_string(), None);
+    for line in io::stdin().lines().map_while(Result::ok) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match line.parse::<ControlAction>() {
+            Ok(action) => match sender.send(Control::new(Some(run_id), action)) {
+                Ok(()) => info!("Requested {}", action),
+                Err(e) => warn!("Unable to request {}: {}", action, e),
+            },
+            Err(e) => warn!("{}", e),
+        }
+    }
+}
+
+/// Helper which checks whether the recipe's ingredients should be reserved
+/// from the pantry before starting
+fn retrieve_stock_check() -> bool {
+    env::args().any(|a| a == "--check-stock")
+}
+
+/// Helper which checks whether to wait for the expediter to plate the dish
+/// before completing the run
+fn retrieve_await_plate() -> bool {
+    env::args().any(|a| a == "--await-plate")
+}
 
 /// Helper which attempts to retrieve a filename from provided command line args
 fn retreive_filename() -> Option<String> {
@@ -18,42 +63,131 @@ fn retreive_filename() -> Option<String> {
     in_file
 }
 
+/// Helper which attempts to retrieve a port to serve metrics on from provided
+/// command line args
+fn retrieve_metrics_port() -> Option<u16> {
+    let args: Vec<String> = env::args().collect();
+
+    let mut port = None;
+    for (i, a) in args.iter().enumerate() {
+        if a == "-m" && i + 1 < args.len() {
+            port = args[i + 1].parse().ok();
+        }
+    }
+    port
+}
+
+/// Helper which attempts to retrieve the servings to scale the recipe to from
+/// provided command line args
+fn retrieve_servings() -> Option<u32> {
+    let args: Vec<String> = env::args().collect();
+
+    let mut servings = None;
+    for (i, a) in args.iter().enumerate() {
+        if a == "-s" && i + 1 < args.len() {
+            servings = args[i + 1].parse().ok();
+        }
+    }
+    servings
+}
+
+/// Helper which collects the recipe parameters overridden with
+/// `--set <name>=<value>` in provided command line args
+fn retrieve_overrides() -> Result<BTreeMap<String, String>, String> {
+    let args: Vec<String> = env::args().collect();
+
+    let mut overrides = BTreeMap::new();
+    for (i, a) in args.iter().enumerate() {
+        if a == "--set" {
+            let (name, value) = args
+                .get(i + 1)
+                .and_then(|setting| setting.split_once('='))
+                .ok_or_else(|| "Expected --set <name>=<value>".to_string())?;
+            overrides.insert(name.to_string(), value.to_string());
+        }
+    }
+    Ok(overrides)
+}
+
 fn main() {
+    let _service = logging::init("driver");
+    if let Err(e) = trace::init("driver") {
+        error!("Unable to set up trace export: {}", e);
+        return;
+    }
+    if let Err(e) = faults::init("head-chef") {
+        error!("Unable to load fault scenario: {}", e);
+        return;
+    }
     let mut p: HeadChefService;
 
     // attempt to read in recipe file and initialize control service
     if let Some(file_name) = retreive_filename() {
-        // attempting to read in provided recipe file
-        match Recipe::from_file(&file_name) {
+        // attempting to read in provided recipe file with any overridden
+        // parameters
+        // scaling the recipe if asked to make a different number of servings
+        let recipe = retrieve_overrides()
+            .and_then(|overrides| Recipe::from_file_with(&file_name, &overrides))
+            .and_then(|recipe| match retrieve_servings() {
+                Some(servings) => recipe.scale(servings),
+                None => Ok(recipe),
+            });
+        match recipe {
             Ok(recipe) => {
                 if recipe.get_steps().is_empty() {
-                    println!("Recipe must contain a non-zero number of steps");
+                    error!("Recipe must contain a non-zero number of steps");
                     return;
                 }
-                p = HeadChefService::new(recipe)
+                for ingredient in recipe.get_ingredients() {
+                    info!(
+                        servings = recipe.get_servings(),
+                        "Using {} of {}",
+                        ingredient.get_quantity(),
+                        ingredient.get_item()
+                    );
+                }
+                p = HeadChefService::new(recipe);
+                // optionally fail early if the pantry lacks any ingredients
+                if retrieve_stock_check() {
+                    p = p.with_stock_check();
+                }
+                // optionally finish with the dish plated rather than cooked
+                if retrieve_await_plate() {
+                    p = p.with_plating();
+                }
             }
             Err(e) => {
-                println!("Error reading recipe: {}", e);
+                error!("Error reading recipe: {}", e);
                 return;
             }
         }
     } else {
         // filename not passed in successfully
-        println!("Usage: driver -f <recipe-filename>");
+        println!("Usage: driver -f <recipe-filename> [-s <servings>] [--set <name>=<value>]... [--check-stock] [--await-plate]");
         return;
     }
 
-    // initialization delay
-    sleep(Duration::from_secs(5));
-
-    println!("Beginning chef-ing");
-    while !p.check_completed() {
-        p.cycle();
-        if let Some(error_msg) = p.check_failed() {
-            println!("Head chef has failed: {}", error_msg);
+    // optionally expose metrics for scraping
+    if let Some(port) = retrieve_metrics_port() {
+        if let Err(e) = metrics::serve(port) {
+            error!("Unable to serve metrics on port {}: {}", port, e);
             return;
         }
     }
 
-    println!("Chef-ing complete!");
+    // accept pause and resume requests from the terminal throughout the run
+    let run_id = p.get_run_id();
+    thread::spawn(move || forward_controls(run_id));
+
+    // initialization delay
+    sleep(Duration::from_secs(5));
+
+    info!("Beginning chef-ing");
+    let mut runner = ServiceRunner::new(p);
+    match runner.run() {
+        Ok(()) => info!("Chef-ing complete!"),
+        Err(_) if runner.get_state() == &State::CANCELLED => warn!("Chef-ing cancelled"),
+        Err(e) => error!("Head chef has failed: {}", e),
+    }
+    trace::flush();
 }
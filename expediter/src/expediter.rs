@@ -0,0 +1,415 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use common::{
+    clock,
+    control::ControlListener,
+    io::{Receiver, Sender},
+    msgs::{
+        CommandDone, ControlAction, Plate, PlateOrder, PlatedComponent, StockAction, StockRequest,
+    },
+    service::Service,
+    state::State,
+    steps::FoodItem,
+    topics::TopicName,
+    units::format_duration,
+};
+use tracing::{info, warn};
+
+/// Default longest a finished component may be held before it goes cold
+pub const DEFAULT_HOLD_LIMIT: Duration = Duration::from_secs(30);
+
+/// Time between checks for finished components
+const TICK: Duration = Duration::from_millis(50);
+
+/// Number of plated or given up runs remembered, so repeated orders for them
+/// are ignored
+const FINISHED_RUNS: usize = 64;
+
+/// A finished component waiting for the rest of its plate
+struct Held {
+    item: FoodItem,
+    ready_at: Instant,
+    cold: bool,
+}
+
+/// A run's dish, made up of the items its steps work on
+///
+/// An item is ready once every step working on it is done, e.g. chicken once
+/// it has been both prepared and cooked.
+struct Order {
+    run_id: u32,
+    recipe: String,
+    /// Item each step works on, by command id, and whether the step is done
+    steps: Vec<(FoodItem, bool)>,
+    held: Vec<Held>,
+}
+
+impl Order {
+    fn new(order: &PlateOrder) -> Self {
+        Self {
+            run_id: order.get_run_id(),
+            recipe: order.get_recipe().to_string(),
+            steps: order
+                .get_items()
+                .iter()
+                .map(|item| (*item, false))
+                .collect(),
+            held: Vec::new(),
+        }
+    }
+
+    /// Distinct items making up the dish
+    fn components(&self) -> Vec<FoodItem> {
+        let mut items: Vec<FoodItem> = Vec::new();
+        for (item, _) in &self.steps {
+            if !items.contains(item) {
+                items.push(*item);
+            }
+        }
+        items
+    }
+
+    /// Marks the given step done, returning the item it finished, if any
+    fn step_done(&mut self, command_id: u32, now: Instant) -> Option<FoodItem> {
+        let (item, done) = self.steps.get_mut(command_id as usize)?;
+        // ignore repeated done messages
+        if *done {
+            return None;
+        }
+        *done = true;
+        let item = *item;
+        if self
+            .steps
+            .iter()
+            .any(|(other, done)| *other == item && !done)
+        {
+            return None;
+        }
+        self.held.push(Held {
+            item,
+            ready_at: now,
+            cold: false,
+        });
+        Some(item)
+    }
+
+    /// Flags items held longer than the given limit as cold, returning those
+    /// which have newly gone cold
+    fn check_cold(&mut self, now: Instant, limit: Duration) -> Vec<FoodItem> {
+        self.held
+            .iter_mut()
+            .filter(|held| !held.cold && now.duration_since(held.ready_at) > limit)
+            .map(|held| {
+                held.cold = true;
+                held.item
+            })
+            .collect()
+    }
+
+    /// Whether every step is done, so the dish can be plated
+    fn is_ready(&self) -> bool {
+        self.steps.iter().all(|(_, done)| *done)
+    }
+
+    /// Plates the dish, noting how long each item was held for
+    fn plate(&self, now: Instant) -> Plate {
+        Plate::new(
+            self.run_id,
+            self.held
+                .iter()
+                .map(|held| {
+                    PlatedComponent::new(held.item, now.duration_since(held.ready_at), held.cold)
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Expediter which holds finished components until every one of a run's dish
+/// is ready, then plates them
+pub struct ExpediterService {
+    hold_limit: Duration,
+    orders: Vec<Order>,
+    /// Runs most recently plated or given up on, oldest first
+    finished: VecDeque<u32>,
+    control: ControlListener,
+    order_receiver: Receiver<PlateOrder>,
+    prep_done_receiver: Receiver<CommandDone>,
+    cook_done_receiver: Receiver<CommandDone>,
+    stock_request_receiver: Receiver<StockRequest>,
+    plate_sender: Sender<Plate>,
+}
+
+impl ExpediterService {
+    /// Creates new ExpediterService instance, flagging components held longer
+    /// than `hold_limit` as cold
+    pub fn new(hold_limit: Duration) -> Self {
+        Self {
+            hold_limit,
+            orders: Vec::new(),
+            finished: VecDeque::new(),
+            control: ControlListener::new(),
+            order_receiver: Receiver::new(TopicName::PlateOrder.to_string(), None),
+            prep_done_receiver: Receiver::new(TopicName::PrepareCommandDone.to_string(), None),
+            cook_done_receiver: Receiver::new(TopicName::CookCommandDone.to_string(), None),
+            stock_request_receiver: Receiver::new(TopicName::StockRequest.to_string(), None),
+            plate_sender: Sender::new(TopicName::Plate.to_string(), None),
+        }
+    }
+
+    /// Takes on any new orders, ignoring repeats of those already taken on
+    /// or finished
+    fn receive_orders(&mut self) {
+        while let Some(order) = self.order_receiver.receive() {
+            let run_id = order.get_run_id();
+            if self.orders.iter().any(|o| o.run_id == run_id) || self.finished.contains(&run_id) {
+                continue;
+            }
+            let order = Order::new(&order);
+            let components: Vec<String> =
+                order.components().iter().map(ToString::to_string).collect();
+            info!(
+                run_id = order.run_id,
+                recipe = order.recipe,
+                "Order received, plating {}",
+                components.join(", ")
+            );
+            self.orders.push(order);
+        }
+    }
+
+    /// Remembers the run as finished, forgetting the oldest beyond those
+    /// kept
+    fn finish(&mut self, run_id: u32) {
+        if self.finished.len() == FINISHED_RUNS {
+            self.finished.pop_front();
+        }
+        self.finished.push_back(run_id);
+    }
+
+    /// Gives up on the orders of any runs which have ended without finishing
+    /// their dish, as they were cancelled or returned their ingredients after
+    /// failing
+    ///
+    /// Runs ended before their order arrives are remembered as finished, so
+    /// the order is ignored.
+    fn receive_ended(&mut self) {
+        let mut ended = Vec::new();
+        for control in self.control.receive_all() {
+            if control.get_action() != ControlAction::CANCEL {
+                continue;
+            }
+            match control.get_run_id() {
+                Some(run_id) => ended.push(run_id),
+                None => ended.extend(self.orders.iter().map(|order| order.run_id)),
+            }
+        }
+        while let Some(request) = self.stock_request_receiver.receive() {
+            if request.get_action() == StockAction::RELEASE {
+                ended.push(request.get_run_id());
+            }
+        }
+        for run_id in ended {
+            if self.finished.contains(&run_id) {
+                continue;
+            }
+            if let Some(index) = self.orders.iter().position(|o| o.run_id == run_id) {
+                let order = self.orders.remove(index);
+                warn!(
+                    run_id,
+                    recipe = order.recipe,
+                    "Run ended unfinished, giving up on plating"
+                );
+            }
+            self.finish(run_id);
+        }
+    }
+
+    /// Marks the steps of any done messages as done, noting items they finish
+    fn receive_done(&mut self, now: Instant) {
+        let mut done = Vec::new();
+        while let Some(msg) = self.prep_done_receiver.receive() {
+            done.push(msg);
+        }
+        while let Some(msg) = self.cook_done_receiver.receive() {
+            done.push(msg);
+        }
+        for msg in done {
+            let Some(order) = self
+                .orders
+                .iter_mut()
+                .find(|order| order.run_id == msg.get_run_id())
+            else {
+                continue;
+            };
+            if let Some(item) = order.step_done(msg.get_command_id(), now) {
+                info!(run_id = order.run_id, "{} ready, holding", item);
+            }
+        }
+    }
+}
+
+impl Service for ExpediterService {
+    const KIND: &'static str = "expediter";
+
+    /// Nothing to set up once created, so await orders straight away
+    fn on_created(&mut self) -> Result<State, String> {
+        Ok(State::IDLE)
+    }
+
+    /// Hold components as they are finished, plating each dish once ready,
+    /// staying idle indefinitely
+    fn on_idle(&mut self) -> Result<State, String> {
+        clock::sleep(TICK);
+        let now = clock::now();
+        self.receive_orders();
+        self.receive_done(now);
+        self.receive_ended();
+        for order in &mut self.orders {
+            for item in order.check_cold(now, self.hold_limit) {
+                warn!(
+                    run_id = order.run_id,
+                    "{} held for over {} and gone cold",
+                    item,
+                    format_duration(self.hold_limit)
+                );
+            }
+        }
+
+        let (ready, waiting) = std::mem::take(&mut self.orders)
+            .into_iter()
+            .partition(Order::is_ready);
+        self.orders = waiting;
+        for order in ready {
+            self.finish(order.run_id);
+            let plate = order.plate(now);
+            let cold = plate
+                .get_components()
+                .iter()
+                .filter(|component| component.is_cold())
+                .count();
+            if cold > 0 {
+                warn!(
+                    run_id = order.run_id,
+                    recipe = order.recipe,
+                    cold,
+                    "Plated with cold components"
+                );
+            } else {
+                info!(run_id = order.run_id, recipe = order.recipe, "Plated");
+            }
+            self.plate_sender
+                .send(plate)
+                .map_err(|e| format!("Failed to send plate: {}", e))?;
+        }
+        Ok(State::IDLE)
+    }
+
+    /// The expediter plates while idle, and is never issued
+    fn on_issued(&mut self) -> Result<State, String> {
+        Err("The expediter is never issued".to_string())
+    }
+
+    /// The expediter plates while idle, and never executes
+    fn on_executing(&mut self) -> Result<State, String> {
+        Err("The expediter never executes".to_string())
+    }
+}
+
+/// Test module for the expediter
+#[cfg(test)]
+mod expediter_tests {
+    use std::time::{Duration, Instant};
+
+    use common::{
+        clock,
+        io::Sender,
+        msgs::{Control, ControlAction, PlateOrder, StockAction, StockRequest},
+        service::Service,
+        sim::Simulation,
+        steps::FoodItem,
+        topics::TopicName,
+    };
+
+    use crate::expediter::{ExpediterService, Order};
+
+    /// Checks items are only held once all their steps are done, and go cold
+    /// once held too long
+    #[test]
+    fn hold_and_plate() {
+        let start = Instant::now();
+        let mut order = Order::new(&PlateOrder::new(
+            7,
+            "surf-and-turf".to_string(),
+            vec![
+                FoodItem::CHICKEN,
+                FoodItem::CHICKEN,
+                FoodItem::SALMON,
+                FoodItem::SALMON,
+            ],
+        ));
+        let at = |secs| start + Duration::from_secs(secs);
+        let limit = Duration::from_secs(30);
+
+        assert_eq!(order.step_done(0, at(5)), None);
+        assert_eq!(order.step_done(1, at(10)), Some(FoodItem::CHICKEN));
+        assert_eq!(order.step_done(1, at(11)), None);
+        assert_eq!(order.check_cold(at(40), limit), vec![]);
+        assert_eq!(order.check_cold(at(41), limit), vec![FoodItem::CHICKEN]);
+        assert_eq!(order.check_cold(at(42), limit), vec![]);
+
+        order.step_done(2, at(45));
+        assert!(!order.is_ready());
+        assert_eq!(order.step_done(3, at(50)), Some(FoodItem::SALMON));
+        assert!(order.is_ready());
+
+        let plate = order.plate(at(50));
+        let components = plate.get_components();
+        assert_eq!(components[0].get_held(), Duration::from_secs(40));
+        assert!(components[0].is_cold());
+        assert!(!components[1].is_cold());
+    }
+
+    /// Checks orders are held however long their runs take, and only given
+    /// up on once their runs are cancelled or fail, ignoring repeats of them
+    #[test]
+    fn ended_runs_expire() {
+        let _sim = Simulation::new(0);
+        let mut expediter = ExpediterService::new(Duration::from_secs(1));
+        let order = |run_id| {
+            PlateOrder::new(
+                run_id,
+                "surf-and-turf".to_string(),
+                vec![FoodItem::CHICKEN, FoodItem::SALMON],
+            )
+        };
+        let orders = Sender::new(TopicName::PlateOrder.to_string(), None);
+        orders.send(order(7)).unwrap();
+        orders.send(order(8)).unwrap();
+        let start = clock::now();
+        while clock::since(start) < Duration::from_secs(600) {
+            expediter.on_idle().unwrap();
+        }
+        assert_eq!(expediter.orders.len(), 2);
+
+        Sender::new(TopicName::Control.to_string(), None)
+            .send(Control::new(Some(7), ControlAction::CANCEL))
+            .unwrap();
+        expediter.on_idle().unwrap();
+        assert_eq!(expediter.orders.len(), 1);
+
+        // a failed run returns its ingredients
+        Sender::new(TopicName::StockRequest.to_string(), None)
+            .send(StockRequest::new(8, StockAction::RELEASE, Vec::new()))
+            .unwrap();
+        expediter.on_idle().unwrap();
+        assert!(expediter.orders.is_empty());
+
+        orders.send(order(7)).unwrap();
+        expediter.on_idle().unwrap();
+        assert!(expediter.orders.is_empty());
+    }
+}
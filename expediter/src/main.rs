@@ -0,0 +1,43 @@
+mod expediter;
+
+use std::{env, time::Duration};
+
+use common::{faults, logging, service::ServiceRunner, trace};
+
+use tracing::{error, info};
+
+use crate::expediter::{ExpediterService, DEFAULT_HOLD_LIMIT};
+
+/// Helper which attempts to retrieve the longest a component may be held
+/// before going cold, given in seconds, from provided command line args
+fn retrieve_hold_limit() -> Option<Duration> {
+    let args: Vec<String> = env::args().collect();
+
+    let mut limit = None;
+    for (i, a) in args.iter().enumerate() {
+        if a == "-l" && i + 1 < args.len() {
+            limit = args[i + 1].parse().ok().map(Duration::from_secs);
+        }
+    }
+    limit
+}
+
+fn main() {
+    let _service = logging::init("expediter");
+    if let Err(e) = trace::init("expediter") {
+        error!("Unable to set up trace export: {}", e);
+        return;
+    }
+    if let Err(e) = faults::init("expediter") {
+        error!("Unable to load fault scenario: {}", e);
+        return;
+    }
+
+    let mut runner = ServiceRunner::new(ExpediterService::new(
+        retrieve_hold_limit().unwrap_or(DEFAULT_HOLD_LIMIT),
+    ));
+    info!("Expediter awaiting orders");
+    if let Err(e) = runner.run() {
+        error!("Expediter has failed: {}", e);
+    }
+}
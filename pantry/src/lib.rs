@@ -0,0 +1,3 @@
+mod pantry;
+
+pub use crate::pantry::PantryService;
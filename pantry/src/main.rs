@@ -0,0 +1,54 @@
+use std::{env, path::PathBuf};
+
+use common::{faults, logging, service::ServiceRunner, stock::Stock, trace};
+
+use tracing::{error, info};
+
+use pantry::PantryService;
+
+/// Helper which attempts to retrieve the path of the stock file from provided
+/// command line args
+fn retrieve_stock_path() -> Option<PathBuf> {
+    let args: Vec<String> = env::args().collect();
+
+    let mut path = None;
+    for (i, a) in args.iter().enumerate() {
+        if a == "-f" && i + 1 < args.len() {
+            path = Some(PathBuf::from(&args[i + 1]));
+        }
+    }
+    path
+}
+
+fn main() {
+    let _service = logging::init("pantry");
+    if let Err(e) = trace::init("pantry") {
+        error!("Unable to set up trace export: {}", e);
+        return;
+    }
+    if let Err(e) = faults::init("pantry") {
+        error!("Unable to load fault scenario: {}", e);
+        return;
+    }
+
+    let Some(path) = retrieve_stock_path() else {
+        println!("Usage: pantry -f <stock-filename>");
+        return;
+    };
+    let stock = match Stock::load(&path) {
+        Ok(stock) => stock,
+        Err(e) => {
+            error!("Error reading stock: {}", e);
+            return;
+        }
+    };
+    for level in stock.get_on_hand() {
+        info!("Stocking {} of {}", level.get_quantity(), level.get_item());
+    }
+
+    let mut runner = ServiceRunner::new(PantryService::new(stock));
+    info!("Pantry open for requests");
+    if let Err(e) = runner.run() {
+        error!("Pantry has failed: {}", e);
+    }
+}
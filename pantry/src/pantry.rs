@@ -0,0 +1,99 @@
+use std::time::Duration;
+
+use common::{
+    clock,
+    io::{Receiver, Sender},
+    msgs::{StockAction, StockReply, StockRequest},
+    service::Service,
+    state::State,
+    stock::Stock,
+    topics::TopicName,
+};
+use tracing::{info, warn};
+
+/// Time between checks for new requests
+const TICK: Duration = Duration::from_millis(50);
+
+/// Pantry keeping track of the kitchen's stock, which runs reserve their
+/// ingredients from before starting
+pub struct PantryService {
+    stock: Stock,
+    request_receiver: Receiver<StockRequest>,
+    reply_sender: Sender<StockReply>,
+}
+
+impl PantryService {
+    /// Creates new PantryService instance holding the given stock
+    pub fn new(stock: Stock) -> Self {
+        Self {
+            stock,
+            request_receiver: Receiver::new(TopicName::StockRequest.to_string(), None),
+            reply_sender: Sender::new(TopicName::StockReply.to_string(), None),
+        }
+    }
+
+    /// Takes the action asked for on the stock and replies with any shortfalls
+    fn handle(&mut self, request: StockRequest) -> Result<(), String> {
+        let run_id = request.get_run_id();
+        let action = request.get_action();
+        let shortfalls = match action {
+            StockAction::QUERY => self.stock.shortfalls(request.get_levels()),
+            StockAction::RESERVE => match self.stock.reserve(run_id, request.get_levels()) {
+                Ok(()) => {
+                    info!(run_id, "Reserved ingredients for run");
+                    Vec::new()
+                }
+                Err(shortfalls) => shortfalls,
+            },
+            StockAction::CONSUME => {
+                if self.stock.consume(run_id) {
+                    info!(run_id, "Used up ingredients reserved for run");
+                    for level in self.stock.get_on_hand() {
+                        info!("{} of {} left", level.get_quantity(), level.get_item());
+                    }
+                }
+                Vec::new()
+            }
+            StockAction::RELEASE => {
+                if self.stock.release(run_id) {
+                    info!(run_id, "Returned ingredients reserved for run");
+                }
+                Vec::new()
+            }
+        };
+        for shortfall in &shortfalls {
+            warn!(run_id, %action, "Not enough {}", shortfall);
+        }
+        self.reply_sender
+            .send(StockReply::new(run_id, action, shortfalls))
+            .map_err(|e| format!("Failed to send reply: {}", e))
+    }
+}
+
+impl Service for PantryService {
+    const KIND: &'static str = "pantry";
+
+    /// Stock is loaded up front, so await requests straight away
+    fn on_created(&mut self) -> Result<State, String> {
+        Ok(State::IDLE)
+    }
+
+    /// Answer requests as they arrive, staying idle indefinitely
+    fn on_idle(&mut self) -> Result<State, String> {
+        clock::sleep(TICK);
+        while let Some(request) = self.request_receiver.receive() {
+            self.handle(request)?;
+        }
+        Ok(State::IDLE)
+    }
+
+    /// The pantry answers requests while idle, and is never issued
+    fn on_issued(&mut self) -> Result<State, String> {
+        Err("The pantry is never issued".to_string())
+    }
+
+    /// The pantry answers requests while idle, and never executes
+    fn on_executing(&mut self) -> Result<State, String> {
+        Err("The pantry never executes".to_string())
+    }
+}
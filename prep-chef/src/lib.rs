@@ -0,0 +1,3 @@
+mod prep_chef;
+
+pub use crate::prep_chef::PrepChefService;
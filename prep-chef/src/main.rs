@@ -1,19 +1,87 @@
-mod prep_chef;
+use std::{env, time::Duration};
 
-use crate::prep_chef::PrepChefService;
+use common::{
+    faults, logging, metrics, progress::DEFAULT_PROGRESS_INTERVAL, service::ServiceRunner, trace,
+};
+
+use tracing::{error, info};
+
+use prep_chef::PrepChefService;
+
+/// Helper which attempts to retrieve a progress reporting interval, given in
+/// milliseconds, from provided command line args
+fn retrieve_progress_interval() -> Option<Duration> {
+    let args: Vec<String> = env::args().collect();
+
+    let mut interval = None;
+    for (i, a) in args.iter().enumerate() {
+        if a == "-i" && i + 1 < args.len() {
+            interval = args[i + 1].parse().ok().map(Duration::from_millis);
+        }
+    }
+    interval
+}
+
+/// Helper which attempts to retrieve a port to serve metrics on from provided
+/// command line args
+fn retrieve_metrics_port() -> Option<u16> {
+    let args: Vec<String> = env::args().collect();
+
+    let mut port = None;
+    for (i, a) in args.iter().enumerate() {
+        if a == "-m" && i + 1 < args.len() {
+            port = args[i + 1].parse().ok();
+        }
+    }
+    port
+}
+
+/// Helper which attempts to retrieve the fingerprint of the only recipe to
+/// accept commands from, as printed by the driver, from provided command line
+/// args
+fn retrieve_expected_recipe() -> Option<String> {
+    let args: Vec<String> = env::args().collect();
+
+    let mut recipe_hash = None;
+    for (i, a) in args.iter().enumerate() {
+        if a == "-e" && i + 1 < args.len() {
+            recipe_hash = Some(args[i + 1].clone());
+        }
+    }
+    recipe_hash
+}
 
 fn main() {
-    // initialize prep chef service
-    let mut pc = PrepChefService::new();
+    let _service = logging::init("prep-chef");
+    if let Err(e) = trace::init("prep-chef") {
+        error!("Unable to set up trace export: {}", e);
+        return;
+    }
+    if let Err(e) = faults::init("prep-chef") {
+        error!("Unable to load fault scenario: {}", e);
+        return;
+    }
 
-    println!("Prep chef awaiting instructions");
-    while !pc.check_completed() {
-        pc.cycle();
-        if let Some(error_msg) = pc.check_failed() {
-            println!("Prep chef has failed: {}", error_msg);
+    // optionally expose metrics for scraping
+    if let Some(port) = retrieve_metrics_port() {
+        if let Err(e) = metrics::serve(port) {
+            error!("Unable to serve metrics on port {}: {}", port, e);
             return;
         }
     }
 
-    println!("Prep chef completed preparations");
+    // initialize prep chef service
+    let mut pc =
+        PrepChefService::new(retrieve_progress_interval().unwrap_or(DEFAULT_PROGRESS_INTERVAL));
+    // optionally refuse commands from any recipe but the expected one
+    if let Some(recipe_hash) = retrieve_expected_recipe() {
+        pc = pc.with_expected_recipe(recipe_hash);
+    }
+
+    let mut runner = ServiceRunner::new(pc);
+    info!("Prep chef awaiting instructions");
+    match runner.run() {
+        Ok(()) => info!("Prep chef completed preparations"),
+        Err(e) => error!("Prep chef has failed: {}", e),
+    }
 }
@@ -1,151 +1,434 @@
-use std::{thread::sleep, time::Duration};
+use std::time::{Duration, Instant};
 
 use common::{
+    clock,
+    control::ControlListener,
+    faults,
     io::{Receiver, Sender},
-    msgs::{CommandDone, PrepareCommand, PrepareCommandAck},
+    metrics::{self, CommandEvent},
+    msgs::{
+        CommandDone, ControlAction, PrepareCommand, PrepareCommandAck, StepPhase, StepProgress,
+    },
+    progress::ProgressTracker,
+    service::Service,
     state::State,
     steps::FoodItem,
+    timings::{gathering_time, preparing_time},
     topics::TopicName,
+    trace::TraceSpan,
+    units::format_duration,
 };
+use tracing::{info, info_span, warn, Span};
+
+/// Kind of step this chef carries out, as labelled in metrics
+const STEP_KIND: &str = "prepare";
+
+/// Time between checks for a request to resume or cancel while paused
+const TICK: Duration = Duration::from_millis(50);
 
 pub struct PrepChefService {
     prep_item: Option<FoodItem>,
-    service_state: State,
+    progress_interval: Duration,
+    expected_recipe: Option<String>,
+    run_id: Option<u32>,
+    command_id: Option<u32>,
+    received_at: Option<Instant>,
+    command_span: Span,
+    trace_span: Option<TraceSpan>,
+    progress: Option<ProgressTracker>,
+    /// Phase of the current command, and the time left in it if paused
+    phase: StepPhase,
+    phase_remaining: Option<Duration>,
+    control: ControlListener,
     command_receiver: Receiver<PrepareCommand>,
     command_ack_sender: Sender<PrepareCommandAck>,
     command_done_sender: Sender<CommandDone>,
+    progress_sender: Sender<StepProgress>,
 }
 
 impl PrepChefService {
-    /// Creates new PrepChefService instance
-    pub fn new() -> Self {
+    /// Creates new PrepChefService instance which reports its progress on a
+    /// command every `progress_interval`
+    pub fn new(progress_interval: Duration) -> Self {
         Self {
             prep_item: None,
-            service_state: State::CREATED,
+            progress_interval,
+            expected_recipe: None,
+            run_id: None,
+            command_id: None,
+            received_at: None,
+            command_span: Span::none(),
+            trace_span: None,
+            progress: None,
+            phase: StepPhase::GATHERING,
+            phase_remaining: None,
+            control: ControlListener::new(),
             command_receiver: Receiver::new(TopicName::PrepareCommand.to_string(), None),
             command_ack_sender: Sender::new(TopicName::PrepareCommandAck.to_string(), None),
             command_done_sender: Sender::new(TopicName::PrepareCommandDone.to_string(), None),
+            progress_sender: Sender::new(TopicName::StepProgress.to_string(), None),
         }
     }
 
-    /// Returns boolean indicating completion status of the service
-    pub fn check_completed(&self) -> bool {
-        matches!(self.service_state, State::COMPLETED)
+    /// Limits the chef to commands from the recipe with the given fingerprint,
+    /// or any fingerprint starting with it, refusing any others
+    pub fn with_expected_recipe(mut self, recipe_hash: String) -> Self {
+        self.expected_recipe = Some(recipe_hash);
+        self
     }
-    /// Returns option indicating whether the service failed while "cooking"
-    /// ### Returns
-    /// - `None` if service is not in `FAILED` state
-    /// - `Some(message)` describing the failure if `FAILED`
-    pub fn check_failed(&self) -> Option<&str> {
-        if let State::FAILED(error_message) = &self.service_state {
-            return Some(error_message);
-        }
-        None
-    }
-
-    pub fn cycle(&mut self) {
-        match self.service_state {
-            State::CREATED => self.on_created(),
-            State::ISSUED => self.on_issued(),
-            State::EXECUTING => self.on_executing(),
-            // shouldn't enter cycle() while COMPLETED
-            State::COMPLETED => (),
-            // failures checked and printed in main loop
-            State::FAILED(_) => (),
+
+    /// Waits out the given phase of the current command, reporting progress,
+    /// but stops early if the current run is paused or cancelled, returning
+    /// which along with the time left in the phase if so
+    fn wait_or_pause(
+        &mut self,
+        phase: StepPhase,
+        duration: Duration,
+    ) -> Result<Option<(ControlAction, Duration)>, String> {
+        let phase_span =
+            TraceSpan::child_of(phase.name(), &self.trace_span.as_ref().unwrap().context());
+        let run_id = self.run_id.unwrap();
+        let control = &mut self.control;
+        let mut stopped_by = None;
+        let result = self
+            .progress
+            .as_ref()
+            .unwrap()
+            .wait_or_pause(phase, duration, &self.progress_sender, || {
+                stopped_by = control
+                    .latest(run_id)
+                    .filter(|action| *action != ControlAction::RESUME);
+                stopped_by.is_some()
+            })
+            .map_err(|e| format!("Failed to send progress: {}", e));
+        phase_span.end();
+        Ok(result?.and_then(|remaining| Some((stopped_by?, remaining))))
+    }
+
+    /// Works through the current phase, taking the given time unless picking
+    /// up where a pause left it, returning the state to move to if the phase
+    /// was paused or cancelled rather than finished
+    fn work_phase(&mut self, time: Duration) -> Result<Option<State>, String> {
+        let phase = self.phase;
+        let remaining = match self.phase_remaining.take() {
+            Some(remaining) => remaining,
+            None => faults::stretch(time)?,
+        };
+        match self.wait_or_pause(phase, remaining)? {
+            None => Ok(None),
+            Some((ControlAction::CANCEL, _)) => Ok(Some(self.cancel())),
+            Some((_, remaining)) => {
+                self.phase_remaining = Some(remaining);
+                self.progress.as_mut().unwrap().pause();
+                info!(
+                    %phase,
+                    remaining = %format_duration(remaining),
+                    "Pausing preparations"
+                );
+                Ok(Some(State::PAUSED))
+            }
         }
     }
 
-    /// After service creation, await command before moving to ISSUED
-    fn on_created(&mut self) {
-        if let Some(command) = self.command_receiver.receive() {
-            self.prep_item = Some(command.get_item());
-            self.service_state = State::ISSUED;
-            println!("Instructions received, beginning preparations");
-            // send ack
-            self.command_ack_sender
-                .send(PrepareCommandAck)
-                .unwrap_or_else(|e| {
-                    self.service_state = State::FAILED(format!("Failed to send ack: {}", e));
-                });
+    /// Abandons the current command as its run was cancelled, counting it as
+    /// failed, and returns to awaiting commands
+    fn cancel(&mut self) -> State {
+        info!("Run cancelled, abandoning preparations");
+        if let Some(mut trace_span) = self.trace_span.take() {
+            trace_span.set_attribute("error", "cancelled");
+            trace_span.end();
+        }
+        if let Some(item) = self.prep_item.take() {
+            metrics::record_command(CommandEvent::Failed, item, STEP_KIND);
         }
+        self.run_id = None;
+        self.command_id = None;
+        self.received_at = None;
+        self.progress = None;
+        self.phase_remaining = None;
+        self.command_span = Span::none();
+        State::IDLE
     }
+}
 
-    /// After service receives command, perform initialization prior to cooking
-    fn on_issued(&mut self) {
-        match self.prep_item.unwrap() {
-            FoodItem::CHICKEN => {
-                println!("Gathering spices and setting up cutting board");
-                sleep(Duration::from_secs(2));
-            }
-            FoodItem::SALMON => {
-                println!("Preparing baking sheet and getting paprika");
-                sleep(Duration::from_secs(3));
+impl Service for PrepChefService {
+    const KIND: &'static str = "prep-chef";
+
+    fn span(&self) -> Span {
+        self.command_span.clone()
+    }
+
+    /// Nothing to set up once created, so await commands straight away
+    fn on_created(&mut self) -> Result<State, String> {
+        Ok(State::IDLE)
+    }
+
+    /// While idle, await command before moving to ISSUED, refusing to go on
+    /// if one can't be read
+    fn on_idle(&mut self) -> Result<State, String> {
+        let Some(command) = self.command_receiver.try_receive()? else {
+            return Ok(State::IDLE);
+        };
+        let item = command.get_item();
+        if let Some(expected) = &self.expected_recipe {
+            if !command.get_recipe_hash().starts_with(expected.as_str()) {
+                warn!(
+                    run_id = command.get_run_id(),
+                    command_id = command.get_id(),
+                    recipe_hash = command.get_recipe_hash(),
+                    "Refusing command from unexpected recipe"
+                );
+                metrics::record_command(CommandEvent::Refused, item, STEP_KIND);
+                return Ok(State::IDLE);
             }
         }
-        println!(
-            "Prep materials gathered for the {}",
-            self.prep_item.unwrap()
+        let duration = match faults::stretch(gathering_time(item) + preparing_time(item)) {
+            Ok(duration) => duration,
+            Err(e) => {
+                warn!(
+                    run_id = command.get_run_id(),
+                    command_id = command.get_id(),
+                    "Refusing command: {}",
+                    e
+                );
+                metrics::record_command(CommandEvent::Refused, item, STEP_KIND);
+                return Ok(State::IDLE);
+            }
+        };
+        self.prep_item = Some(item);
+        self.run_id = Some(command.get_run_id());
+        self.command_id = Some(command.get_id());
+        self.received_at = Some(clock::now());
+        self.command_span = info_span!(
+            "command",
+            run_id = command.get_run_id(),
+            command_id = command.get_id(),
+            %item,
+            recipe_hash = command.get_recipe_hash(),
+            trace_id = %command.get_trace().trace_id_hex()
         );
-        self.service_state = State::EXECUTING;
+        let _command = self.command_span.clone().entered();
+        // continue the head chef's trace for the duration of the command
+        let mut trace_span = TraceSpan::child_of(STEP_KIND, &command.get_trace());
+        trace_span.set_attribute("run_id", command.get_run_id());
+        trace_span.set_attribute("command_id", command.get_id());
+        trace_span.set_attribute("item", item);
+        let trace = trace_span.context();
+        self.trace_span = Some(trace_span);
+        metrics::record_command(CommandEvent::Received, item, STEP_KIND);
+        self.progress = Some(ProgressTracker::new(
+            command.get_run_id(),
+            command.get_id(),
+            duration,
+            self.progress_interval,
+        ));
+        info!("Instructions received, beginning preparations");
+        // send ack
+        self.command_ack_sender
+            .send(PrepareCommandAck::new(
+                command.get_run_id(),
+                command.get_id(),
+                trace,
+            ))
+            .map_err(|e| format!("Failed to send ack: {}", e))?;
+        metrics::record_command(CommandEvent::Acked, item, STEP_KIND);
+        Ok(State::ISSUED)
     }
 
-    /// After initialization, perform necessary preparation task
-    fn on_executing(&mut self) {
+    /// After service receives command, begin gathering prep materials
+    fn on_issued(&mut self) -> Result<State, String> {
         match self.prep_item.unwrap() {
-            FoodItem::CHICKEN => {
-                println!("Removing extra fat and seasoning the chicken");
-                sleep(Duration::from_secs(5));
+            FoodItem::CHICKEN => info!("Gathering spices and setting up cutting board"),
+            FoodItem::SALMON => info!("Preparing baking sheet and getting paprika"),
+        }
+        self.phase = StepPhase::GATHERING;
+        self.phase_remaining = None;
+        Ok(State::EXECUTING)
+    }
+
+    /// Gather prep materials then perform the necessary preparation task,
+    /// either of which may be paused or cancelled part way through
+    fn on_executing(&mut self) -> Result<State, String> {
+        let item = self.prep_item.unwrap();
+        if self.phase == StepPhase::GATHERING {
+            if let Some(state) = self.work_phase(gathering_time(item))? {
+                return Ok(state);
             }
-            FoodItem::SALMON => {
-                println!("Prep salmon on baking sheet and season");
-                sleep(Duration::from_secs(3));
+            info!("Prep materials gathered for the {}", item);
+            match item {
+                FoodItem::CHICKEN => info!("Removing extra fat and seasoning the chicken"),
+                FoodItem::SALMON => info!("Prep salmon on baking sheet and season"),
             }
+            self.phase = StepPhase::PREPARING;
+        }
+        if let Some(state) = self.work_phase(preparing_time(item))? {
+            return Ok(state);
         }
 
-        println!("Preparations completed");
-        self.service_state = State::COMPLETED;
+        info!("Preparations completed");
         // send done indicator
         self.command_done_sender
-            .send(CommandDone)
-            .unwrap_or_else(|e| {
-                self.service_state = State::FAILED(format!("Failed to send done: {}", e));
-            });
+            .send(CommandDone::new(
+                self.run_id.unwrap(),
+                self.command_id.unwrap(),
+                self.trace_span.as_ref().unwrap().context(),
+            ))
+            .map_err(|e| format!("Failed to send done: {}", e))?;
+        self.trace_span.take().unwrap().end();
+        metrics::record_command(CommandEvent::Completed, item, STEP_KIND);
+        metrics::observe_step_duration(item, STEP_KIND, clock::since(self.received_at.unwrap()));
+        Ok(State::COMPLETED)
     }
-}
 
+    /// While paused, await a request to resume preparations for the current
+    /// run, or to cancel it
+    fn on_paused(&mut self) -> Result<State, String> {
+        clock::sleep(TICK);
+        match self.control.latest(self.run_id.unwrap()) {
+            Some(ControlAction::RESUME) => (),
+            Some(ControlAction::CANCEL) => return Ok(self.cancel()),
+            _ => return Ok(State::PAUSED),
+        }
+        self.progress.as_mut().unwrap().resume();
+        info!(
+            remaining = %format_duration(self.phase_remaining.unwrap()),
+            "Resuming preparations"
+        );
+        Ok(State::EXECUTING)
+    }
+
+    fn on_failed(&mut self, reason: &str) {
+        if let Some(mut trace_span) = self.trace_span.take() {
+            trace_span.set_attribute("error", reason);
+            trace_span.end();
+        }
+        // count the command as failed on the cycle it fails in
+        if let Some(item) = self.prep_item {
+            metrics::record_command(CommandEvent::Failed, item, STEP_KIND);
+        }
+    }
+}
 
 /// Test module for the prep chef
 #[cfg(test)]
 mod prep_chef_tests {
-    use common::state::State;
+    use std::time::Duration;
+
+    use common::{
+        io::Sender,
+        msgs::{Control, ControlAction, PrepareCommand},
+        progress::DEFAULT_PROGRESS_INTERVAL,
+        service::ServiceRunner,
+        sim::Simulation,
+        state::State,
+        steps::FoodItem,
+        topics::TopicName,
+        trace::TraceSpan,
+    };
 
     use crate::prep_chef::PrepChefService;
 
-    /// Tests initial values of chef state variables 
+    /// Sends a command to prepare the given item from a recipe with the given
+    /// fingerprint
+    fn send_command(item: FoodItem, recipe_hash: &str) {
+        Sender::new(TopicName::PrepareCommand.to_string(), None)
+            .send(PrepareCommand::new(
+                7,
+                recipe_hash.to_string(),
+                0,
+                item,
+                TraceSpan::root("run").context(),
+            ))
+            .unwrap();
+    }
+
+    /// Asks for the given action to be taken on the run commands are sent for
+    fn send_control(action: ControlAction) {
+        Sender::new(TopicName::Control.to_string(), None)
+            .send(Control::new(Some(7), action))
+            .unwrap();
+    }
+
+    /// Topics of every message published in the simulation but heartbeats
+    fn topics(sim: &Simulation) -> Vec<String> {
+        sim.get_messages()
+            .iter()
+            .map(|msg| msg.get_topic().to_string())
+            .filter(|topic| topic != "heartbeat")
+            .collect()
+    }
+
+    /// Tests initial values of chef state variables
     #[test]
     fn initialization() {
-        let chef = PrepChefService::new();
-        assert_eq!(chef.service_state, State::CREATED);
+        let chef = ServiceRunner::new(PrepChefService::new(DEFAULT_PROGRESS_INTERVAL));
+        assert_eq!(chef.get_state(), &State::CREATED);
         assert!(!chef.check_completed());
         assert!(chef.check_failed().is_none());
-        assert!(chef.prep_item.is_none());
+        assert!(chef.get_service().prep_item.is_none());
     }
 
-    /// Ensures chef state doesn't progress without receiving a cycle
-    /// TODO: revisit. This testing format may not even make sense
-    ///         look into best methods for testing infinite loops
+    /// Ensures the chef waits in IDLE until it receives a command
     #[test]
-    fn state_check_no_command() {
-        let mut chef = PrepChefService::new();
-        // cycle a large number of times and check state
-        for _ in 0..10000 {
-            chef.cycle();
-            assert_eq!(chef.service_state, State::CREATED);
-        }
+    fn idle_without_command() {
+        let mut sim = Simulation::new(0);
+        let chef = sim.add(PrepChefService::new(DEFAULT_PROGRESS_INTERVAL));
+        sim.run_for(Duration::from_secs(60));
+        assert_eq!(sim.get_state(chef), &State::IDLE);
+        assert!(topics(&sim).is_empty());
+    }
+
+    /// Checks a command is acknowledged, reported on each second, and done
+    /// once its item is gathered and prepared
+    #[test]
+    fn prepare_command() {
+        let mut sim = Simulation::new(0);
+        let chef = sim.add(PrepChefService::new(DEFAULT_PROGRESS_INTERVAL));
+        send_command(FoodItem::SALMON, "abc123");
+        let elapsed = sim.run(Duration::from_secs(60)).unwrap();
+        assert_eq!(sim.get_state(chef), &State::COMPLETED);
+
+        let mut expected = vec!["prepare_command", "prepare_command_ack"];
+        expected.extend(["step_progress"; 6]);
+        expected.push("prepare_command_done");
+        assert_eq!(topics(&sim), expected);
+        // a cycle each to be created, take the command and begin gathering,
+        // then three seconds each gathering and preparing
+        assert_eq!(elapsed, Duration::from_millis(6150));
     }
 
-    /// Checks state progress upon receiving prepare command
+    /// Checks commands from recipes other than the expected one are refused
     #[test]
-    fn state_check_command() {
+    fn refuse_unexpected_recipe() {
+        let mut sim = Simulation::new(0);
+        let chef = sim.add(
+            PrepChefService::new(DEFAULT_PROGRESS_INTERVAL).with_expected_recipe("abc".to_string()),
+        );
+        send_command(FoodItem::CHICKEN, "def456");
+        sim.run_for(Duration::from_secs(60));
+        assert_eq!(sim.get_state(chef), &State::IDLE);
+        assert_eq!(topics(&sim), vec!["prepare_command"]);
+    }
+
+    /// Checks gathering can be paused part way through, carrying on with the
+    /// time left once resumed
+    #[test]
+    fn pause_while_gathering() {
+        let mut sim = Simulation::new(0);
+        let chef = sim.add(PrepChefService::new(DEFAULT_PROGRESS_INTERVAL));
+        send_command(FoodItem::SALMON, "abc123");
+        // a cycle each to be created and take the command, pausing as
+        // gathering begins
+        sim.run_for(Duration::from_millis(100));
+        send_control(ControlAction::PAUSE);
+        sim.run_for(Duration::from_secs(60));
+        assert_eq!(sim.get_state(chef), &State::PAUSED);
+
+        send_control(ControlAction::RESUME);
+        let elapsed = sim.run(Duration::from_secs(120)).unwrap();
+        assert_eq!(sim.get_state(chef), &State::COMPLETED);
+        // paused a second into gathering, leaving two seconds of it and three
+        // of preparing once resumed after a minute, then a cycle to finish
+        assert_eq!(elapsed, Duration::from_millis(65050));
     }
 }
@@ -0,0 +1,233 @@
+use std::{collections::BTreeMap, env};
+
+use common::{formats::Format, graph, recipe::Recipe, schema, units::format_duration};
+
+const USAGE: &str =
+    "Usage: recipe-tool convert <in-file> <out-file> [--from <format>] [--to <format>]
+       recipe-tool fingerprint <file> [-s <servings>] [--set <name>=<value>]...
+       recipe-tool estimate <file> [-s <servings>] [--set <name>=<value>]...
+       recipe-tool graph <file> [--as dot|mermaid] [-s <servings>] [--set <name>=<value>]...
+       recipe-tool schema";
+
+/// Width in columns of the bars of estimate charts
+const CHART_WIDTH: usize = 48;
+
+/// Options for converting a recipe between file formats
+struct ConvertArgs {
+    in_file: String,
+    out_file: String,
+    /// Format to read the input in, instead of the one given by its extension
+    from: Option<Format>,
+    /// Format to write the output in, instead of the one given by its extension
+    to: Option<Format>,
+}
+
+/// Helper which parses the convert command's options from the args following
+/// the command name
+fn retrieve_convert_args(args: &[String]) -> Result<ConvertArgs, String> {
+    let mut files = Vec::new();
+    let mut from = None;
+    let mut to = None;
+    let mut args = args.iter();
+    while let Some(a) = args.next() {
+        match a.as_str() {
+            "--from" | "--to" => {
+                let format = args
+                    .next()
+                    .ok_or_else(|| format!("Missing value for {}", a))?
+                    .parse()?;
+                if a == "--from" {
+                    from = Some(format);
+                } else {
+                    to = Some(format);
+                }
+            }
+            _ if a.starts_with("--") => return Err(format!("Unknown argument: {}", a)),
+            _ => files.push(a.clone()),
+        }
+    }
+    match <[String; 2]>::try_from(files) {
+        Ok([in_file, out_file]) => Ok(ConvertArgs {
+            in_file,
+            out_file,
+            from,
+            to,
+        }),
+        Err(_) => Err("Expected an input and an output file".to_string()),
+    }
+}
+
+/// Options selecting a recipe file and how the driver would run it
+struct RecipeArgs {
+    file: String,
+    /// Servings to scale the recipe to
+    servings: Option<u32>,
+    /// Values overriding the defaults of the recipe's parameters
+    overrides: BTreeMap<String, String>,
+}
+
+/// Helper which parses the options of commands acting on a recipe as the
+/// driver would run it, from the args following the command name
+fn retrieve_recipe_args(args: &[String]) -> Result<RecipeArgs, String> {
+    let mut files = Vec::new();
+    let mut servings = None;
+    let mut overrides = BTreeMap::new();
+    let mut args = args.iter();
+    while let Some(a) = args.next() {
+        match a.as_str() {
+            "-s" => {
+                let value = args.next().ok_or("Missing value for -s")?;
+                servings = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("Invalid servings: {}", value))?,
+                );
+            }
+            "--set" => {
+                let (name, value) = args
+                    .next()
+                    .and_then(|setting| setting.split_once('='))
+                    .ok_or("Expected --set <name>=<value>")?;
+                overrides.insert(name.to_string(), value.to_string());
+            }
+            _ if a.starts_with('-') => return Err(format!("Unknown argument: {}", a)),
+            _ => files.push(a.clone()),
+        }
+    }
+    match <[String; 1]>::try_from(files) {
+        Ok([file]) => Ok(RecipeArgs {
+            file,
+            servings,
+            overrides,
+        }),
+        Err(_) => Err("Expected a single recipe file".to_string()),
+    }
+}
+
+/// Reads a recipe with its parameters overridden and scaled as asked
+fn load_recipe(args: &RecipeArgs) -> Result<Recipe, String> {
+    let recipe = Recipe::from_file_with(&args.file, &args.overrides)?;
+    match args.servings {
+        Some(servings) => recipe.scale(servings),
+        None => Ok(recipe),
+    }
+}
+
+/// Prints the fingerprint of a recipe, matching the one the driver sends with
+/// its commands when given the same options
+fn fingerprint(args: &RecipeArgs) -> Result<(), String> {
+    println!("{}", load_recipe(args)?.fingerprint());
+    Ok(())
+}
+
+/// Prints how long a recipe is estimated to take as a Gantt chart, with each
+/// step starting as early as the steps it depends on allow. Steps on the
+/// critical path are marked with `*` and drawn solid.
+fn estimate(args: &RecipeArgs) -> Result<(), String> {
+    let recipe = load_recipe(args)?;
+    let estimate = recipe.estimate()?;
+    println!(
+        "{}: {} with independent steps run at once, {} one step at a time",
+        recipe.get_title(),
+        format_duration(estimate.get_total()),
+        format_duration(estimate.get_sequential())
+    );
+
+    let labels: Vec<String> = recipe
+        .get_steps()
+        .iter()
+        .enumerate()
+        .map(|(i, step)| format!("{:>3} {}", i + 1, step))
+        .collect();
+    let label_width = labels.iter().map(|label| label.chars().count()).max();
+    let label_width = label_width.unwrap_or_default();
+    // columns of the chart per second of the total time
+    let scale = CHART_WIDTH as f64 / estimate.get_total().as_secs_f64().max(1.0);
+    for (i, (label, step)) in labels.iter().zip(estimate.get_steps()).enumerate() {
+        let critical = estimate.get_critical_path().contains(&i);
+        let start = (step.get_earliest_start().as_secs_f64() * scale).round() as usize;
+        let end = ((step.finish().as_secs_f64() * scale).round() as usize).max(start + 1);
+        println!(
+            "{} {:<label_width$} {:>9} {:>9} |{}{}{}|",
+            if critical { '*' } else { ' ' },
+            label,
+            format_duration(step.get_earliest_start()),
+            format_duration(step.get_duration()),
+            " ".repeat(start),
+            if critical { "█" } else { "░" }.repeat(end - start),
+            " ".repeat(CHART_WIDTH.saturating_sub(end))
+        );
+    }
+    Ok(())
+}
+
+/// Prints the step graph of a recipe as a Graphviz DOT or Mermaid flowchart,
+/// DOT unless `--as mermaid` is given
+fn graph(args: &[String]) -> Result<(), String> {
+    // pull out the diagram format, leaving the options selecting the recipe
+    let mut diagram = "dot".to_string();
+    let mut recipe_args = Vec::new();
+    let mut args = args.iter();
+    while let Some(a) = args.next() {
+        match a.as_str() {
+            "--as" => diagram = args.next().ok_or("Missing value for --as")?.clone(),
+            _ => recipe_args.push(a.clone()),
+        }
+    }
+    let recipe = load_recipe(&retrieve_recipe_args(&recipe_args)?)?;
+    match diagram.as_str() {
+        "dot" => print!("{}", graph::to_dot(&recipe)?),
+        "mermaid" => print!("{}", graph::to_mermaid(&recipe)?),
+        _ => return Err(format!("Unknown diagram format: {}", diagram)),
+    }
+    Ok(())
+}
+
+/// Reads a recipe in one format and writes it out in another, expanding any
+/// included recipes along the way
+fn convert(args: &ConvertArgs) -> Result<(), String> {
+    let recipe = match args.from {
+        Some(format) => Recipe::from_file_as(&args.in_file, format, &BTreeMap::new())?,
+        None => Recipe::from_file(&args.in_file)?,
+    };
+    match args.to {
+        Some(format) => recipe.to_file_as(&args.out_file, format),
+        None => recipe.to_file(&args.out_file),
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let result = match args.split_first() {
+        Some((command, args)) if command == "convert" => {
+            retrieve_convert_args(args).and_then(|args| convert(&args))
+        }
+        Some((command, args)) if command == "fingerprint" => {
+            retrieve_recipe_args(args).and_then(|args| fingerprint(&args))
+        }
+        Some((command, args)) if command == "estimate" => {
+            retrieve_recipe_args(args).and_then(|args| estimate(&args))
+        }
+        Some((command, args)) if command == "graph" => graph(args),
+        // print the JSON Schema for recipe files, e.g. to refresh the copy in
+        // schema/ after changing the recipe types
+        Some((command, [])) if command == "schema" => {
+            serde_json::to_string_pretty(&schema::recipe_schema())
+                .map(|schema| println!("{}", schema))
+                .map_err(|e| e.to_string())
+        }
+        _ => Err(format!(
+            "Supported formats: {}",
+            Format::ALL
+                .iter()
+                .map(|format| format.name())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    };
+    if let Err(e) = result {
+        eprintln!("{}\n{}", e, USAGE);
+        std::process::exit(1);
+    }
+}